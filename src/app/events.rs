@@ -24,7 +24,7 @@ impl EventHandler {
                 // Poll for events and send them through the channel
                 if event::poll(tick_rate).unwrap() {
                     if let Ok(event) = event::read() {
-                        if let Err(_) = tx.send(event) {
+                        if tx.send(event).is_err() {
                             break;
                         }
                     }
@@ -9,16 +9,45 @@ use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::fs::{self, File};
 use std::io::{self, Stdout, Write};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use self::events::EventHandler;
-use self::state::{AppMode, AppState};
+use self::state::{AppMode, AppState, LabelPrompt, LabelPurpose, MetadataPrompt, MetadataPurpose};
 use crate::config::Config;
 use packrat::editor::Editor;
 use crate::explorer::Explorer;
 use crate::ui::{render, UiSerializer};
 use crate::viewer::Viewer;
-use crate::storage::ChunkStorage;
+use crate::storage::{
+    default_export_path, export_chunks, ChunkStorage, ExportFormat, ExportOptions, MatchKind, Metadata, Placement,
+    ReanchorReport,
+};
+use crate::utils::labels::LabelInput;
+use crate::utils::metadata_form::MetadataForm;
+use crate::utils::prompt::PromptContext;
+use crate::utils::Tokenizer;
+
+/// Lets the editor (from the library crate) share the viewer's tokenizer
+struct SharedTokenizer(Arc<dyn Tokenizer>);
+
+impl packrat::utils::Tokenizer for SharedTokenizer {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+    
+    fn count(&self, text: &str) -> usize {
+        self.0.count(text)
+    }
+    
+    fn count_lines(&self, lines: &[String]) -> usize {
+        self.0.count_lines(lines)
+    }
+    
+    fn token_spans(&self, text: &str) -> Vec<std::ops::Range<usize>> {
+        self.0.token_spans(text)
+    }
+}
 
 /// Main application struct
 pub struct App {
@@ -38,11 +67,13 @@ pub struct App {
     config: Config,
     /// Chunk storage
     chunk_storage: ChunkStorage,
+    /// Re-anchored chunk positions waiting for the user to confirm
+    pending_reanchor: Option<ReanchorReport>,
 }
 
 impl App {
-    /// Create a new application instance
-    pub fn new() -> Result<Self> {
+    /// Create a new application instance with the given configuration
+    pub fn new(config: Config) -> Result<Self> {
         // Setup terminal
         terminal::enable_raw_mode()?;
         let mut stdout = io::stdout();
@@ -50,19 +81,24 @@ impl App {
         let backend = CrosstermBackend::new(stdout);
         let terminal = Terminal::new(backend)?;
 
-        // Load configuration
-        let config = Config::load()?;
-        
         // Create app components
-        let state = AppState::default();
+        let mut state = AppState::default();
         let events = EventHandler::new(Duration::from_millis(100));
         let source_dir = config.absolute_source_dir();
         let mut explorer = Explorer::new(&source_dir)?;
         let mut viewer = Viewer::new();
-        let editor = Editor::new();
+        let mut editor = Editor::new();
         
-        // Configure viewer with token limit from config
+        // Configure viewer with token limit from config, counted with the prompt template if any
         viewer.set_max_tokens_per_chunk(config.max_tokens_per_chunk);
+        viewer.set_prompt_template(config.prompt_template.clone(), explorer.root_dir());
+        viewer.set_sliding_window(config.window_tokens, config.window_overlap_tokens);
+        
+        // Count tokens with the configured tokenizer (the editor comes from the
+        // library crate, so it gets the same tokenizer through an adapter)
+        let tokenizer = config.load_tokenizer()?;
+        viewer.set_tokenizer(tokenizer.clone());
+        editor.set_tokenizer(Arc::new(SharedTokenizer(tokenizer)));
         
         // Create debug directory if enabled
         if config.enable_debug {
@@ -72,9 +108,15 @@ impl App {
         
         // Initialize chunk storage
         let chunk_file = config.absolute_chunk_file();
-        let chunk_storage = ChunkStorage::new(&chunk_file)
+        let mut chunk_storage = ChunkStorage::open(&chunk_file, config.storage_backend)
             .with_context(|| format!("Failed to initialize chunk storage at: {:?}", chunk_file))?;
         
+        // Report anything storage had to recover from (e.g. a truncated log record)
+        let warnings = chunk_storage.take_warnings();
+        if !warnings.is_empty() {
+            state.set_debug_message(format!("Chunk storage: {}", warnings.join("; ")), 5);
+        }
+        
         // Initialize chunking progress for files in the explorer
         if let Err(e) = explorer.init_chunking_progress(&chunk_storage) {
             eprintln!("Warning: Failed to initialize chunking progress: {}", e);
@@ -89,6 +131,7 @@ impl App {
             editor,
             config,
             chunk_storage,
+            pending_reanchor: None,
         })
     }
 
@@ -104,15 +147,24 @@ impl App {
                 self.state.clear_debug_message();
             }
             
+            // Queue exact token counts and show the ones that finished (the
+            // event thread wakes this loop up regularly, so they appear soon)
+            self.viewer.poll_token_counts();
+            self.editor.poll_token_count();
+            
             // Draw the UI
             self.terminal.draw(|frame| {
                 render(frame, &self.state, &self.explorer, &self.viewer, &mut self.editor);
             })?;
 
             // Handle events
-            if let Ok(event) = self.events.next() {
-                if let Event::Key(key_event) = event {
-                    self.handle_key_event(key_event);
+            if let Ok(Event::Key(key_event)) = self.events.next() {
+                self.handle_key_event(key_event);
+                
+                // Changes that were saved but couldn't be journalled for undo
+                let warnings = self.chunk_storage.take_warnings();
+                if !warnings.is_empty() {
+                    self.state.set_debug_message(format!("Chunk storage: {}", warnings.join("; ")), 5);
                 }
             }
         }
@@ -137,6 +189,16 @@ impl App {
             return;
         }
 
+        // An open label prompt takes all keys until it is confirmed or cancelled
+        if self.state.label_prompt.is_some() {
+            self.handle_label_prompt_key(event);
+            return;
+        }
+        if self.state.metadata_prompt.is_some() {
+            self.handle_metadata_prompt_key(event);
+            return;
+        }
+        
         // Handle '?' key to toggle help BUT NOT in insert mode
         if !in_insert_mode && event.code == KeyCode::Char('?') {
             self.state.show_help = !self.state.show_help;
@@ -146,15 +208,12 @@ impl App {
         // Handle debug shortcuts if enabled, regardless of mode
         // (Control keys still work in insert mode)
         if self.config.enable_debug && event.modifiers.contains(KeyModifiers::CONTROL) {
-            match event.code {
-                // Ctrl+D: Dump UI state
-                KeyCode::Char('d') => {
-                    if let Err(e) = self.dump_ui_state() {
-                        eprintln!("Error dumping UI state: {}", e);
-                    }
-                    return;
-                },
-                _ => {}
+            // Ctrl+D: Dump UI state
+            if event.code == KeyCode::Char('d') {
+                if let Err(e) = self.dump_ui_state() {
+                    eprintln!("Error dumping UI state: {}", e);
+                }
+                return;
             }
         }
 
@@ -234,6 +293,15 @@ impl App {
             KeyCode::Home => self.explorer.select_first(),
             KeyCode::End => self.explorer.select_last(),
             
+            // Filter files by chunk labels with 'F' key
+            KeyCode::Char('f') => {
+                let current = self.explorer.label_filter().to_vec();
+                self.open_label_prompt(LabelPurpose::ExplorerFilter, &current);
+            },
+            
+            // Export chunks (matching the label filter) with 'X' key
+            KeyCode::Char('x') => self.export_filtered_chunks(),
+            
             // Directory/file navigation
             KeyCode::Enter | KeyCode::Char('l') | KeyCode::Right => {
                 if self.explorer.entries().is_empty() {
@@ -253,8 +321,13 @@ impl App {
                         eprintln!("Error opening file: {}", e);
                     } else {
                         // Load any existing chunk data
-                        if let Err(e) = self.viewer.load_chunked_ranges(&self.chunk_storage, &self.explorer.root_dir()) {
+                        if let Err(e) = self.viewer.load_chunked_ranges(&self.chunk_storage, self.explorer.root_dir()) {
                             self.state.set_debug_message(format!("Error loading chunks: {}", e), 3);
+                        } else if self.viewer.stale_chunk_count() > 0 {
+                            self.state.set_debug_message(
+                                format!("{} chunk(s) no longer match the file (shown in red) - press a to re-anchor", self.viewer.stale_chunk_count()),
+                                3
+                            );
                         }
                         
                         // Switch to viewer mode
@@ -275,9 +348,28 @@ impl App {
     /// Handle key events in viewer mode
     fn handle_viewer_key_event(&mut self, event: event::KeyEvent) {
         use ratatui::crossterm::event::KeyCode;
+        
+        // A pending re-anchor is applied with 'y' and cancelled by any other key
+        if let Some(report) = self.pending_reanchor.take() {
+            if event.code == KeyCode::Char('y') {
+                self.apply_reanchor(report);
+            } else {
+                self.state.set_debug_message("Re-anchoring cancelled".to_string(), 2);
+            }
+            return;
+        }
+        
+        // While proposed chunks are reviewed, only navigation keys keep their meaning
+        if self.viewer.chunk_preview().is_some() && self.handle_chunk_preview_key(event) {
+            return;
+        }
 
         match event.code {
             
+            // Move the cursor within the line, e.g. to where a character-wise
+            // selection should start (Shift moves 10 characters at a time)
+            _ if self.viewer.handle_column_key(event) => {},
+            
             // Exit viewer and return to explorer (q, Esc, h, or left arrow)
             KeyCode::Char('q') | KeyCode::Esc | KeyCode::Char('h') | KeyCode::Left => {
                 self.state.mode = AppMode::Explorer;
@@ -294,13 +386,26 @@ impl App {
                 self.state.set_debug_message(message.to_string(), 2);
             },
             
+            // Toggle character-wise selection with 'V' key
+            KeyCode::Char('v') => {
+                self.viewer.toggle_char_selection_mode();
+                let message = if self.viewer.is_char_selection() {
+                    "Character selection activated - Use ←→ to choose columns"
+                } else {
+                    "Character selection deactivated"
+                };
+                self.state.set_debug_message(message.to_string(), 2);
+            },
+            
             // Enter editor mode with 'E' key
             KeyCode::Char('e') => {
+                // Editing a selection creates a new chunk, not an update to a re-opened one
+                self.viewer.clear_reopened_chunk();
+                
                 // Only enter editor mode if there is a selection
-                if let Some((start, end)) = self.viewer.selection_range() {
-                    let content = self.viewer.content();
-                    // Extract the selected lines
-                    let selected_lines = content[start..=end].to_vec();
+                if self.viewer.selection_range().is_some() {
+                    // Extract the selected text (trimmed to the selected columns)
+                    let selected_lines = self.viewer.selected_lines();
                     
                     // Set the editor content with the selected lines
                     self.editor.set_content(selected_lines);
@@ -312,8 +417,9 @@ impl App {
                         }
                     }
                     
-                    // Set the max tokens from the viewer
+                    // Set the max tokens from the viewer, counted with the same prompt
                     self.editor.set_max_tokens(self.viewer.max_tokens_per_chunk());
+                    self.set_editor_prompt(self.viewer.selection_prompt_context());
                     
                     // Switch to editor mode
                     self.state.mode = AppMode::Editor;
@@ -325,69 +431,185 @@ impl App {
                 }
             },
             
-            // Save chunk with 'S' key
+            // Save chunk with 'S' key (asking for labels first if enabled)
             KeyCode::Char('s') => {
                 // Only save if there's a selection
-                if let Some((start, end)) = self.viewer.selection_range() {
-                    // Check for overlap with existing chunks
-                    let has_overlap = self.viewer.check_chunk_overlap(start, end);
-                    
-                    // If there's an overlap, warn the user but proceed
-                    if has_overlap {
+                if self.viewer.selection_range().is_some() {
+                    self.request_save(false);
+                } else {
+                    self.state.set_debug_message("No text selected for chunking".to_string(), 2);
+                }
+            },
+            
+            // Edit the metadata of the chunk under the cursor with 'M' key
+            KeyCode::Char('m') => {
+                if self.config.metadata_fields.is_empty() {
+                    self.state.set_debug_message(
+                        "No metadata fields configured - add [[metadata_fields]] to the config file".to_string(),
+                        3
+                    );
+                    return;
+                }
+                match self.viewer.stored_chunk_at_cursor(&self.chunk_storage) {
+                    Ok(Some(chunk)) => {
+                        let purpose = MetadataPurpose::EditChunk { chunk_id: chunk.id.clone() };
+                        self.open_metadata_prompt(purpose, &chunk.metadata);
+                    },
+                    Ok(None) => {
+                        self.state.set_debug_message("No chunk under cursor".to_string(), 2);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error reading chunk: {}", e), 3);
+                    }
+                }
+            },
+            
+            // Edit the labels of the chunk under the cursor with 'T' key
+            KeyCode::Char('t') => {
+                match self.viewer.stored_chunk_at_cursor(&self.chunk_storage) {
+                    Ok(Some(chunk)) => {
+                        let purpose = LabelPurpose::EditChunk { chunk_id: chunk.id.clone() };
+                        self.open_label_prompt(purpose, &chunk.labels);
+                    },
+                    Ok(None) => {
+                        self.state.set_debug_message("No chunk under cursor".to_string(), 2);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error reading chunk: {}", e), 3);
+                    }
+                }
+            },
+            
+            // Propose chunks up to the token budget with 'C' key
+            KeyCode::Char('c') => {
+                match self.viewer.propose_chunks() {
+                    Ok(count) => {
+                        let along = self.viewer.structure().map(|structure| format!(" along {}", structure.name())).unwrap_or_default();
                         self.state.set_debug_message(
-                            "Warning: Selected text overlaps with existing chunks".to_string(), 
-                            2
+                            format!("Proposed {} chunk(s){} - Enter to save, Esc to discard, [ ] x m to adjust", count, along),
+                            30
                         );
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Cannot propose chunks: {}", e), 3);
                     }
-                    
-                    // Store the selection range to reference after saving
-                    let selection_range = self.viewer.selection_range();
-                    
-                    // Save the chunk to CSV storage
-                    match self.viewer.save_selection_as_chunk(&mut self.chunk_storage, &self.explorer.root_dir()) {
-                        Ok(chunk_id) => {
-                            // Set cursor to the end of the saved chunk - makes it easier to select next block
-                            if let Some((_, end)) = selection_range {
-                                let next_line = (end + 1).min(self.viewer.content().len().saturating_sub(1));
-                                
-                                // Move cursor to the next line after the saved chunk
-                                if next_line > end {
-                                    // Position cursor at the next line
-                                    for _ in 0..(next_line - self.viewer.cursor_position()) {
-                                        self.viewer.cursor_down();
-                                    }
-                                }
-                            }
-                            
-                            // Clear selection after saving
-                            self.viewer.clear_selection();
-                            let percent = self.viewer.chunking_percentage();
-                            
-                            // Update the explorer chunking progress
-                            if let Some(file_path) = self.viewer.file_path() {
-                                self.explorer.update_chunking_progress(file_path, percent);
-                            }
-                            
-                            if has_overlap {
-                                self.state.set_debug_message(
-                                    format!("Chunk saved with overlaps (ID: {}) ({:.1}% chunked)", 
-                                             chunk_id, percent), 
-                                    3
-                                );
-                            } else {
-                                self.state.set_debug_message(
-                                    format!("Chunk saved (ID: {}) ({:.1}% chunked)", 
-                                             chunk_id, percent), 
-                                    3
-                                );
-                            }
-                        },
-                        Err(e) => {
-                            self.state.set_debug_message(format!("Error saving chunk: {}", e), 3);
-                        }
+                }
+            },
+            
+            // Propose overlapping sliding windows with 'W' key
+            KeyCode::Char('w') => {
+                match self.viewer.propose_windows() {
+                    Ok(count) => {
+                        self.state.set_debug_message(
+                            format!("Proposed {} overlapping window(s) - Enter to save, Esc to discard, [ ] x m to adjust", count),
+                            30
+                        );
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Cannot propose windows: {}", e), 3);
                     }
+                }
+            },
+            
+            // Show or hide token boundaries with 'B' key
+            KeyCode::Char('b') => {
+                self.viewer.toggle_token_boundaries();
+                let message = if self.viewer.shows_token_boundaries() {
+                    format!("Showing {} token boundaries", self.viewer.tokenizer_name())
                 } else {
-                    self.state.set_debug_message("No text selected for chunking".to_string(), 2);
+                    "Token boundaries hidden".to_string()
+                };
+                self.state.set_debug_message(message, 2);
+            },
+            
+            // Delete the chunk under the cursor with 'D' key (not Ctrl+D, which is easy to hit by accident)
+            KeyCode::Char('d') if event.modifiers == KeyModifiers::NONE => {
+                match self.viewer.delete_chunk_at_cursor(&mut self.chunk_storage) {
+                    Ok(Some(chunk)) => {
+                        let percent = self.viewer.chunking_percentage();
+                        
+                        // Update the explorer chunking progress
+                        if let Some(file_path) = self.viewer.file_path() {
+                            self.explorer.update_chunking_progress(file_path, percent);
+                        }
+                        
+                        self.state.set_debug_message(
+                            format!("Chunk deleted (ID: {}) ({:.1}% chunked)", chunk.id, percent),
+                            3
+                        );
+                    },
+                    Ok(None) => {
+                        self.state.set_debug_message("No chunk under cursor".to_string(), 2);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error deleting chunk: {}", e), 3);
+                    }
+                }
+            },
+            
+            // Re-anchor stale chunks with 'A' key
+            KeyCode::Char('a') => {
+                match self.viewer.reanchor_stale_chunks(&self.chunk_storage, self.explorer.root_dir()) {
+                    Ok(report) if report.is_empty() && report.unplaced.is_empty() => {
+                        self.state.set_debug_message("No stale chunks to re-anchor".to_string(), 2);
+                    },
+                    Ok(report) if report.is_empty() => {
+                        self.state.set_debug_message(
+                            format!("Could not place {} stale chunk(s): {}", report.unplaced.len(), Self::describe_unplaced(&report)),
+                            5
+                        );
+                    },
+                    Ok(report) => {
+                        let moves = report.placed.iter().map(Self::describe_move).collect::<Vec<_>>().join(", ");
+                        self.state.set_debug_message(
+                            format!(
+                                "Re-anchor {} ({} unplaced) - press y to update storage, any other key to cancel",
+                                moves, report.unplaced.len()
+                            ),
+                            30
+                        );
+                        self.pending_reanchor = Some(report);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error re-anchoring chunks: {}", e), 3);
+                    }
+                }
+            },
+            
+            // Undo the last chunk operation with 'U' key
+            KeyCode::Char('u') => self.undo_chunk_operation(false),
+            
+            // Redo the last undone chunk operation with Ctrl+R
+            KeyCode::Char('r') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.undo_chunk_operation(true);
+            },
+            
+            // Re-open the chunk under the cursor in the editor with 'R' key
+            KeyCode::Char('r') => {
+                match self.viewer.reopen_chunk_at_cursor(&self.chunk_storage) {
+                    Ok(Some(chunk)) => {
+                        // Load the stored chunk content, which may differ from the file
+                        self.editor.set_content(chunk.content.lines().map(String::from).collect());
+                        
+                        if let Some(file_name) = chunk.file_path.file_name() {
+                            self.editor.set_file_name(file_name.to_string_lossy().to_string());
+                        }
+                        self.editor.set_max_tokens(self.viewer.max_tokens_per_chunk());
+                        self.set_editor_prompt(Some(PromptContext {
+                            file_path: chunk.file_path.to_string_lossy().to_string(),
+                            start_line: chunk.start_line,
+                            end_line: chunk.end_line,
+                        }));
+                        
+                        self.state.mode = AppMode::Editor;
+                        self.state.clear_debug_message();
+                    },
+                    Ok(None) => {
+                        self.state.set_debug_message("No chunk under cursor".to_string(), 2);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error opening chunk: {}", e), 3);
+                    }
                 }
             },
             
@@ -433,6 +655,445 @@ impl App {
         }
     }
     
+    /// Handle a key while proposed chunks are reviewed
+    /// 
+    /// Returns false for navigation keys, which move the cursor as usual.
+    fn handle_chunk_preview_key(&mut self, event: event::KeyEvent) -> bool {
+        use ratatui::crossterm::event::KeyCode;
+        
+        let adjusted = match event.code {
+            // Accept the proposal, asking for labels and metadata first if enabled
+            KeyCode::Enter => {
+                if self.config.prompt_for_labels {
+                    self.open_label_prompt(LabelPurpose::SaveProposedChunks, &[]);
+                } else {
+                    self.request_preview_metadata(Vec::new());
+                }
+                return true;
+            },
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.viewer.cancel_chunk_preview();
+                self.state.set_debug_message("Proposed chunks discarded".to_string(), 2);
+                return true;
+            },
+            
+            // Adjust the boundaries around the cursor
+            KeyCode::Char(']') => self.viewer.move_preview_boundary(true),
+            KeyCode::Char('[') => self.viewer.move_preview_boundary(false),
+            KeyCode::Char('x') => self.viewer.split_preview_at_cursor(),
+            KeyCode::Char('m') => self.viewer.merge_preview_with_next(),
+            
+            // Jump between proposed chunks
+            KeyCode::Char('}') => {
+                self.viewer.jump_to_proposed_chunk(true);
+                return true;
+            },
+            KeyCode::Char('{') => {
+                self.viewer.jump_to_proposed_chunk(false);
+                return true;
+            },
+            
+            KeyCode::Up | KeyCode::Down | KeyCode::Char('j') | KeyCode::Char('k')
+            | KeyCode::PageUp | KeyCode::PageDown | KeyCode::Home | KeyCode::End => return false,
+            _ => {
+                self.state.set_debug_message(
+                    "Reviewing proposed chunks - Enter to save, Esc to discard, [ ] x m to adjust, { } to jump".to_string(),
+                    5
+                );
+                return true;
+            }
+        };
+        
+        match adjusted {
+            Ok(true) => self.state.clear_debug_message(),
+            Ok(false) => self.state.set_debug_message("Can't adjust the proposed chunks there".to_string(), 2),
+            Err(e) => self.state.set_debug_message(format!("Error adjusting chunks: {}", e), 3),
+        }
+        true
+    }
+    
+    /// Show the metadata form for accepted proposed chunks, or save them if no fields are configured
+    fn request_preview_metadata(&mut self, labels: Vec<String>) {
+        if self.config.metadata_fields.is_empty() {
+            self.save_chunk_preview(labels, Metadata::new());
+        } else {
+            self.open_metadata_prompt(MetadataPurpose::SaveProposedChunks { labels }, &Metadata::new());
+        }
+    }
+    
+    /// Save the proposed chunks in one batch with the given labels and metadata
+    fn save_chunk_preview(&mut self, labels: Vec<String>, metadata: Metadata) {
+        match self.viewer.save_chunk_preview(&mut self.chunk_storage, self.explorer.root_dir(), labels, metadata) {
+            Ok(count) => {
+                let percent = self.viewer.chunking_percentage();
+                if let Some(file_path) = self.viewer.file_path() {
+                    self.explorer.update_chunking_progress(file_path, percent);
+                }
+                let _ = self.explorer.refresh_label_filter(&self.chunk_storage);
+                
+                self.state.set_debug_message(
+                    format!("Saved {} chunk(s) - press u to undo them all ({:.1}% chunked)", count, percent),
+                    3
+                );
+            },
+            Err(e) => {
+                self.state.set_debug_message(format!("Error saving chunks: {}", e), 3);
+            }
+        }
+    }
+    
+    /// Save the selection as a chunk, asking for its labels and metadata first if enabled
+    fn request_save(&mut self, edited: bool) {
+        if self.config.prompt_for_labels {
+            self.open_label_prompt(LabelPurpose::SaveChunk { edited }, &[]);
+        } else {
+            self.request_metadata(edited, Vec::new());
+        }
+    }
+    
+    /// Show the metadata form for the chunk being saved, or save it if no fields are configured
+    fn request_metadata(&mut self, edited: bool, labels: Vec<String>) {
+        if self.config.metadata_fields.is_empty() {
+            self.save_selection(labels, Metadata::new(), edited);
+        } else {
+            self.open_metadata_prompt(MetadataPurpose::SaveChunk { edited, labels }, &Metadata::new());
+        }
+    }
+    
+    /// Save the selection as a chunk with the given labels and metadata
+    /// 
+    /// `edited` picks the message shown for content changed in the editor.
+    fn save_selection(&mut self, labels: Vec<String>, metadata: Metadata, edited: bool) {
+        // Check for overlap with existing chunks (saved anyway, with a warning)
+        let has_overlap = self.viewer.selection_overlaps_chunks();
+        
+        // Store the selection range to reference after saving
+        let selection_range = self.viewer.selection_range();
+        
+        match self.viewer.save_annotated_selection(&mut self.chunk_storage, self.explorer.root_dir(), labels, metadata) {
+            Ok(chunk_id) => {
+                // Set cursor to the line after the saved chunk - makes it easier to select next block
+                if let Some((_, end)) = selection_range {
+                    let next_line = (end + 1).min(self.viewer.content().len().saturating_sub(1));
+                    if next_line > end {
+                        for _ in 0..(next_line - self.viewer.cursor_position()) {
+                            self.viewer.cursor_down();
+                        }
+                    }
+                }
+                
+                // Clear selection after saving
+                self.viewer.clear_selection();
+                let percent = self.viewer.chunking_percentage();
+                
+                // Update the explorer chunking progress and label filter
+                if let Some(file_path) = self.viewer.file_path() {
+                    self.explorer.update_chunking_progress(file_path, percent);
+                }
+                let _ = self.explorer.refresh_label_filter(&self.chunk_storage);
+                
+                let saved = match (edited, has_overlap) {
+                    (true, _) => "Edited content saved",
+                    (false, true) => "Chunk saved with overlaps",
+                    (false, false) => "Chunk saved",
+                };
+                self.state.set_debug_message(format!("{} (ID: {}) ({:.1}% chunked)", saved, chunk_id, percent), 3);
+            },
+            Err(e) => {
+                self.state.set_debug_message(format!("Error saving chunk: {}", e), 3);
+            }
+        }
+    }
+    
+    /// Show a label prompt prefilled with `labels`, completing from labels already in use
+    fn open_label_prompt(&mut self, purpose: LabelPurpose, labels: &[String]) {
+        let known = match self.chunk_storage.all_labels() {
+            Ok(known) => known,
+            Err(e) => {
+                self.state.set_debug_message(format!("Error reading labels: {}", e), 3);
+                Vec::new()
+            }
+        };
+        self.state.label_prompt = Some(LabelPrompt {
+            purpose,
+            input: LabelInput::new(labels, known),
+        });
+    }
+    
+    /// Handle a key typed into the label prompt
+    fn handle_label_prompt_key(&mut self, event: event::KeyEvent) {
+        use ratatui::crossterm::event::KeyCode;
+        
+        let Some(prompt) = self.state.label_prompt.as_mut() else {
+            return;
+        };
+        
+        match event.code {
+            KeyCode::Enter => {
+                if let Some(prompt) = self.state.label_prompt.take() {
+                    self.confirm_label_prompt(prompt);
+                }
+            },
+            KeyCode::Esc => {
+                if let Some(prompt) = self.state.label_prompt.take() {
+                    let message = match prompt.purpose {
+                        LabelPurpose::SaveChunk { .. } => "Chunk not saved",
+                        LabelPurpose::EditChunk { .. } => "Labels unchanged",
+                        LabelPurpose::SaveProposedChunks => "Proposed chunks not saved",
+                        LabelPurpose::ExplorerFilter => "Label filter unchanged",
+                    };
+                    self.state.set_debug_message(message.to_string(), 2);
+                }
+            },
+            KeyCode::Tab => {
+                prompt.input.complete();
+            },
+            KeyCode::Backspace => prompt.input.backspace(),
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => prompt.input.push(c),
+            _ => {}
+        }
+    }
+    
+    /// Use the labels entered into a label prompt
+    fn confirm_label_prompt(&mut self, prompt: LabelPrompt) {
+        let labels = prompt.input.labels();
+        
+        match prompt.purpose {
+            LabelPurpose::SaveChunk { edited } => self.request_metadata(edited, labels),
+            LabelPurpose::SaveProposedChunks => self.request_preview_metadata(labels),
+            LabelPurpose::EditChunk { chunk_id } => {
+                let result = self.chunk_storage.get_chunk(&chunk_id).and_then(|chunk| {
+                    let mut chunk = chunk.ok_or_else(|| anyhow::anyhow!("Chunk {} is no longer in storage", chunk_id))?;
+                    chunk.labels = labels;
+                    self.chunk_storage.update_chunk(chunk)
+                });
+                match result {
+                    Ok(()) => {
+                        let _ = self.explorer.refresh_label_filter(&self.chunk_storage);
+                        self.state.set_debug_message(format!("Labels updated (ID: {})", chunk_id), 3);
+                    },
+                    Err(e) => {
+                        self.state.set_debug_message(format!("Error updating labels: {}", e), 3);
+                    }
+                }
+            },
+            LabelPurpose::ExplorerFilter => {
+                let message = if labels.is_empty() {
+                    "Showing all files".to_string()
+                } else {
+                    format!("Showing files with labels: {}", labels.join(", "))
+                };
+                match self.explorer.set_label_filter(labels, &self.chunk_storage) {
+                    Ok(()) => self.state.set_debug_message(message, 3),
+                    Err(e) => self.state.set_debug_message(format!("Error filtering by labels: {}", e), 3),
+                }
+            },
+        }
+    }
+    
+    /// Show the metadata form for the configured fields, prefilled with `metadata`
+    fn open_metadata_prompt(&mut self, purpose: MetadataPurpose, metadata: &Metadata) {
+        self.state.metadata_prompt = Some(MetadataPrompt {
+            purpose,
+            form: MetadataForm::new(self.config.metadata_fields.clone(), metadata),
+        });
+    }
+    
+    /// Handle a key typed into the metadata form
+    fn handle_metadata_prompt_key(&mut self, event: event::KeyEvent) {
+        use ratatui::crossterm::event::KeyCode;
+        
+        let Some(prompt) = self.state.metadata_prompt.as_mut() else {
+            return;
+        };
+        
+        match event.code {
+            KeyCode::Enter => {
+                // Invalid values keep the form open with the offending field focused
+                match prompt.form.metadata() {
+                    Ok(metadata) => {
+                        if let Some(prompt) = self.state.metadata_prompt.take() {
+                            self.confirm_metadata_prompt(prompt.purpose, metadata);
+                        }
+                    },
+                    Err(e) => self.state.set_debug_message(e.to_string(), 3),
+                }
+            },
+            KeyCode::Esc => {
+                if let Some(prompt) = self.state.metadata_prompt.take() {
+                    let message = match prompt.purpose {
+                        MetadataPurpose::SaveChunk { .. } => "Chunk not saved",
+                        MetadataPurpose::EditChunk { .. } => "Metadata unchanged",
+                        MetadataPurpose::SaveProposedChunks { .. } => "Proposed chunks not saved",
+                    };
+                    self.state.set_debug_message(message.to_string(), 2);
+                }
+            },
+            KeyCode::Tab | KeyCode::Down => prompt.form.next_field(),
+            KeyCode::BackTab | KeyCode::Up => prompt.form.previous_field(),
+            KeyCode::Backspace => prompt.form.backspace(),
+            KeyCode::Char(c) if !event.modifiers.contains(KeyModifiers::CONTROL) => prompt.form.push(c),
+            _ => {}
+        }
+    }
+    
+    /// Use the metadata entered into the metadata form
+    fn confirm_metadata_prompt(&mut self, purpose: MetadataPurpose, metadata: Metadata) {
+        match purpose {
+            MetadataPurpose::SaveChunk { edited, labels } => self.save_selection(labels, metadata, edited),
+            MetadataPurpose::EditChunk { chunk_id } => {
+                let result = self.chunk_storage.get_chunk(&chunk_id).and_then(|chunk| {
+                    let mut chunk = chunk.ok_or_else(|| anyhow::anyhow!("Chunk {} is no longer in storage", chunk_id))?;
+                    chunk.metadata = metadata;
+                    self.chunk_storage.update_chunk(chunk)
+                });
+                match result {
+                    Ok(()) => self.state.set_debug_message(format!("Metadata updated (ID: {})", chunk_id), 3),
+                    Err(e) => self.state.set_debug_message(format!("Error updating metadata: {}", e), 3),
+                }
+            },
+            MetadataPurpose::SaveProposedChunks { labels } => self.save_chunk_preview(labels, metadata),
+        }
+    }
+    
+    /// Count the editor's content in the configured prompt template, if any
+    /// 
+    /// The editor comes from the library crate, so the template is rebuilt
+    /// from its text.
+    fn set_editor_prompt(&mut self, context: Option<PromptContext>) {
+        use packrat::utils::prompt as lib_prompt;
+        
+        let prompt = self.config.prompt_template.as_ref().zip(context).map(|(template, context)| {
+            let context = lib_prompt::PromptContext {
+                file_path: context.file_path,
+                start_line: context.start_line,
+                end_line: context.end_line,
+            };
+            (lib_prompt::PromptTemplate::new(template.as_str()), context)
+        });
+        self.editor.set_prompt(prompt);
+    }
+    
+    /// Export the chunks matching the explorer's label filter next to the chunk file
+    /// 
+    /// With a prompt template configured, each chunk is also exported rendered in it.
+    fn export_filtered_chunks(&mut self) {
+        let path = default_export_path(&self.config.absolute_chunk_file());
+        let options = ExportOptions {
+            labels: self.explorer.label_filter().to_vec(),
+            prompt_template: self.config.prompt_template.clone(),
+        };
+        
+        match export_chunks(&self.chunk_storage, &path, ExportFormat::Jsonl, &options) {
+            Ok(count) => {
+                self.state.set_debug_message(format!("Exported {} chunk(s) to {}", count, path.display()), 5);
+            },
+            Err(e) => {
+                self.state.set_debug_message(format!("Error exporting chunks: {}", e), 3);
+            }
+        }
+    }
+    
+    /// Undo (or redo) the most recent chunk operation and refresh what depends on it
+    fn undo_chunk_operation(&mut self, redo: bool) {
+        let result = if redo {
+            self.chunk_storage.redo()
+        } else {
+            self.chunk_storage.undo()
+        };
+        let action = if redo { "Redid" } else { "Undid" };
+        
+        match result {
+            Ok(Some(operation)) => {
+                // Reload highlighted ranges for the open file
+                if let Err(e) = self.viewer.load_chunked_ranges(&self.chunk_storage, self.explorer.root_dir()) {
+                    self.state.set_debug_message(format!("Error reloading chunks: {}", e), 3);
+                    return;
+                }
+                
+                // Update the explorer progress of every file the operation touched
+                for file_path in operation.file_paths() {
+                    let _ = self.explorer.refresh_chunking_progress(&self.chunk_storage, &file_path);
+                }
+                
+                self.state.set_debug_message(
+                    format!("{} {} ({:.1}% chunked)", action, operation.describe(), self.viewer.chunking_percentage()),
+                    3
+                );
+            },
+            Ok(None) => {
+                let message = if redo { "Nothing to redo" } else { "Nothing to undo" };
+                self.state.set_debug_message(message.to_string(), 2);
+            },
+            Err(e) => {
+                self.state.set_debug_message(format!("Error: {}", e), 3);
+            }
+        }
+    }
+    
+    /// Write re-anchored chunk positions to storage in one batch
+    fn apply_reanchor(&mut self, report: ReanchorReport) {
+        if let Err(e) = self.chunk_storage.update_chunks(report.updated_chunks()) {
+            self.state.set_debug_message(format!("Error updating chunks: {}", e), 3);
+            return;
+        }
+        
+        // Show the chunks at their new positions
+        if let Err(e) = self.viewer.load_chunked_ranges(&self.chunk_storage, self.explorer.root_dir()) {
+            self.state.set_debug_message(format!("Error reloading chunks: {}", e), 3);
+            return;
+        }
+        let percent = self.viewer.chunking_percentage();
+        if let Some(file_path) = self.viewer.file_path() {
+            self.explorer.update_chunking_progress(file_path, percent);
+        }
+        
+        let message = if report.unplaced.is_empty() {
+            format!("Re-anchored {} chunk(s) ({:.1}% chunked)", report.placed.len(), percent)
+        } else {
+            format!(
+                "Re-anchored {} chunk(s); could not place {}: {}",
+                report.placed.len(), report.unplaced.len(), Self::describe_unplaced(&report)
+            )
+        };
+        self.state.set_debug_message(message, 5);
+    }
+    
+    /// Describe where a re-anchored chunk moves, e.g. `3-5 -> 5-7 (fuzzy)`
+    fn describe_move(placement: &Placement) -> String {
+        format!(
+            "{}-{} -> {}-{}{}",
+            placement.old_range.0,
+            placement.old_range.1,
+            placement.chunk.start_line,
+            placement.chunk.end_line,
+            if placement.kind == MatchKind::Fuzzy { " (fuzzy)" } else { "" }
+        )
+    }
+    
+    /// List the old line ranges of chunks that could not be re-anchored
+    fn describe_unplaced(report: &ReanchorReport) -> String {
+        report.unplaced
+            .iter()
+            .map(|chunk| format!("lines {}-{}", chunk.start_line, chunk.end_line))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    
+    /// Write the editor content back into the chunk re-opened from the viewer
+    fn save_reopened_chunk(&mut self) {
+        let edited_content = self.editor.content();
+        
+        match self.viewer.save_reopened_chunk(&mut self.chunk_storage, edited_content) {
+            Ok(chunk_id) => {
+                self.state.set_debug_message(format!("Chunk updated (ID: {})", chunk_id), 3);
+            },
+            Err(e) => {
+                self.state.set_debug_message(format!("Error updating chunk: {}", e), 3);
+            }
+        }
+    }
+    
     /// Handle key events in editor mode
     fn handle_editor_key_event(&mut self, event: event::KeyEvent) {
         use ratatui::crossterm::event::KeyCode;
@@ -473,7 +1134,11 @@ impl App {
             KeyCode::Enter => {
                 // Only process if we're in command mode
                 if self.editor.is_in_command_mode() {
-                    if self.editor.is_save_command() {
+                    if self.editor.is_save_command() && self.viewer.reopened_chunk_id().is_some() {
+                        // User typed :wq or :x on a re-opened chunk - update it in place
+                        self.save_reopened_chunk();
+                        self.state.mode = AppMode::Viewer;
+                    } else if self.editor.is_save_command() {
                         // User typed :wq or :x - save the content as a chunk before exiting
                         // Get the edited content
                         let edited_content = self.editor.content();
@@ -486,35 +1151,7 @@ impl App {
                             // Replace the selected lines with the edited content
                             if self.viewer.update_selected_content(edited_content) {
                                 // Save the updated content as a chunk
-                                match self.viewer.save_selection_as_chunk(&mut self.chunk_storage, &self.explorer.root_dir()) {
-                                    Ok(chunk_id) => {
-                                        // Clear selection after saving
-                                        self.viewer.clear_selection();
-                                        let percent = self.viewer.chunking_percentage();
-                                        
-                                        // Update the explorer chunking progress
-                                        if let Some(file_path) = self.viewer.file_path() {
-                                            self.explorer.update_chunking_progress(file_path, percent);
-                                        }
-                                        
-                                        if is_modified {
-                                            self.state.set_debug_message(
-                                                format!("Edited content saved (ID: {}) ({:.1}% chunked)", 
-                                                         chunk_id, percent), 
-                                                3
-                                            );
-                                        } else {
-                                            self.state.set_debug_message(
-                                                format!("Chunk saved (ID: {}) ({:.1}% chunked)", 
-                                                         chunk_id, percent), 
-                                                3
-                                            );
-                                        }
-                                    },
-                                    Err(e) => {
-                                        self.state.set_debug_message(format!("Error saving chunk: {}", e), 3);
-                                    }
-                                }
+                                self.request_save(is_modified);
                             } else {
                                 // Show error message if replacement failed
                                 self.state.set_debug_message("Failed to update content - selection range may be invalid".to_string(), 3);
@@ -529,7 +1166,6 @@ impl App {
                             self.state.set_debug_message("No write since last change (use :q! to override)".to_string(), 3);
                             // Do not exit the editor - pass the Enter key to the editor
                             self.editor.handle_key_event(event);
-                            return;
                         } else {
                             // No unsaved changes, exit to viewer mode
                             self.state.mode = AppMode::Viewer;
@@ -543,16 +1179,20 @@ impl App {
                     } else {
                         // Pass the Enter key to the editor for other commands
                         self.editor.handle_key_event(event);
-                        return;
                     }
                 } else {
                     // Pass the Enter key to the editor if not in command mode
                     self.editor.handle_key_event(event);
-                    return;
                 }
             },
             
             // Save changes, create chunk, and return to viewer
+            KeyCode::Char('s') if event.modifiers.contains(KeyModifiers::CONTROL) && self.viewer.reopened_chunk_id().is_some() => {
+                // Update the re-opened chunk in place instead of creating a new one
+                self.save_reopened_chunk();
+                self.state.mode = AppMode::Viewer;
+            },
+            
             KeyCode::Char('s') if event.modifiers.contains(KeyModifiers::CONTROL) => {
                 // Get the edited content
                 let edited_content = self.editor.content();
@@ -561,50 +1201,11 @@ impl App {
                 let is_modified = self.editor.is_modified();
                 
                 // Update viewer with the edited content if a selection exists
-                if let Some((_start, _end)) = self.viewer.selection_range() {
-                    // Store the selection range to reference after saving
-                    let selection_range = self.viewer.selection_range();
-                    
+                if self.viewer.selection_range().is_some() {
                     // Replace the selected lines with the edited content
                     if self.viewer.update_selected_content(edited_content) {
                         // Save the updated content as a chunk
-                        match self.viewer.save_selection_as_chunk(&mut self.chunk_storage, &self.explorer.root_dir()) {
-                            Ok(chunk_id) => {
-                                // Set cursor to the end of the saved chunk - makes it easier to select next block
-                                if let Some((_, end)) = selection_range {
-                                    let next_line = (end + 1).min(self.viewer.content().len().saturating_sub(1));
-                                    
-                                    // Move cursor to the next line after the saved chunk
-                                    if next_line > end {
-                                        // Position cursor at the next line
-                                        for _ in 0..(next_line - self.viewer.cursor_position()) {
-                                            self.viewer.cursor_down();
-                                        }
-                                    }
-                                }
-                                
-                                // Clear selection after saving
-                                self.viewer.clear_selection();
-                                let percent = self.viewer.chunking_percentage();
-                                
-                                if is_modified {
-                                    self.state.set_debug_message(
-                                        format!("Edited content saved (ID: {}) ({:.1}% chunked)", 
-                                                 chunk_id, percent), 
-                                        3
-                                    );
-                                } else {
-                                    self.state.set_debug_message(
-                                        format!("Chunk saved (ID: {}) ({:.1}% chunked)", 
-                                                 chunk_id, percent), 
-                                        3
-                                    );
-                                }
-                            },
-                            Err(e) => {
-                                self.state.set_debug_message(format!("Error saving chunk: {}", e), 3);
-                            }
-                        }
+                        self.request_save(is_modified);
                     } else {
                         // Show error message if replacement failed
                         self.state.set_debug_message("Failed to update content - selection range may be invalid".to_string(), 3);
@@ -625,14 +1226,9 @@ impl App {
                 if !handled {
                     // If the editor didn't handle it, check for our custom keys
                     // BUT only if not in insert mode
-                    if !self.editor.is_in_insert_mode() {
-                        match event.code {
-                            // Toggle help panel
-                            KeyCode::Char('?') => {
-                                self.state.show_help = !self.state.show_help;
-                            },
-                            _ => {}
-                        }
+                    // Toggle help panel
+                    if !self.editor.is_in_insert_mode() && event.code == KeyCode::Char('?') {
+                        self.state.show_help = !self.state.show_help;
                     }
                 }
             }
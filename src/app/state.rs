@@ -1,9 +1,13 @@
 use std::time::Instant;
 
+use crate::utils::labels::LabelInput;
+use crate::utils::metadata_form::MetadataForm;
+
 /// Application mode
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum AppMode {
     /// File explorer mode
+    #[default]
     Explorer,
     /// Text viewer mode
     Viewer,
@@ -11,13 +15,77 @@ pub enum AppMode {
     Editor,
 }
 
-impl Default for AppMode {
-    fn default() -> Self {
-        Self::Explorer
+/// What the labels typed into a label prompt are used for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelPurpose {
+    /// Labels for the chunk about to be saved from the selection
+    SaveChunk {
+        /// Whether the selection was changed in the editor
+        edited: bool,
+    },
+    /// New labels for an existing chunk
+    EditChunk {
+        /// Id of the chunk being relabelled
+        chunk_id: String,
+    },
+    /// Labels for every chunk of an accepted chunk preview
+    SaveProposedChunks,
+    /// Labels the explorer is filtered by
+    ExplorerFilter,
+}
+
+/// A prompt for entering labels, shown at the bottom of the screen
+pub struct LabelPrompt {
+    /// What the labels are for
+    pub purpose: LabelPurpose,
+    /// The labels typed so far
+    pub input: LabelInput,
+}
+
+impl LabelPrompt {
+    /// Title shown above the input
+    pub fn title(&self) -> &'static str {
+        match self.purpose {
+            LabelPurpose::SaveChunk { .. } => "Labels for new chunk",
+            LabelPurpose::EditChunk { .. } => "Edit chunk labels",
+            LabelPurpose::SaveProposedChunks => "Labels for all proposed chunks",
+            LabelPurpose::ExplorerFilter => "Show files with labels",
+        }
     }
 }
 
+/// What the values typed into the metadata form are used for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataPurpose {
+    /// Metadata for the chunk about to be saved from the selection
+    SaveChunk {
+        /// Whether the selection was changed in the editor
+        edited: bool,
+        /// Labels already entered for the chunk
+        labels: Vec<String>,
+    },
+    /// New metadata for an existing chunk
+    EditChunk {
+        /// Id of the chunk being updated
+        chunk_id: String,
+    },
+    /// Metadata for every chunk of an accepted chunk preview
+    SaveProposedChunks {
+        /// Labels already entered for the chunks
+        labels: Vec<String>,
+    },
+}
+
+/// The metadata form, shown over the viewer
+pub struct MetadataPrompt {
+    /// What the metadata is for
+    pub purpose: MetadataPurpose,
+    /// The values typed so far
+    pub form: MetadataForm,
+}
+
 /// Application state
+#[derive(Default)]
 pub struct AppState {
     /// Flag to indicate if the application should quit
     pub should_quit: bool,
@@ -29,18 +97,10 @@ pub struct AppState {
     pub debug_message: Option<String>,
     /// Timestamp when debug message was set (for auto-clearing)
     pub debug_message_time: Option<Instant>,
-}
-
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            should_quit: false,
-            mode: AppMode::default(),
-            show_help: false,
-            debug_message: None,
-            debug_message_time: None,
-        }
-    }
+    /// Label prompt waiting for input, if any
+    pub label_prompt: Option<LabelPrompt>,
+    /// Metadata form waiting for input, if any
+    pub metadata_prompt: Option<MetadataPrompt>,
 }
 
 impl AppState {
@@ -0,0 +1,85 @@
+/// Runes that open a core, whose arms run until a `--`
+const CORE_RUNES: &[&str] = &["|%", "|_", "|@", "|^"];
+
+/// Runes that start an arm, or a chapter of arms (`+|`)
+const ARM_RUNES: &[&str] = &["++", "+$", "+*", "+|"];
+
+/// Find the lines of Hoon source where a chunk may start
+/// 
+/// Arms, chapters, cores and the code following a core's `--` may start a
+/// chunk. Each of them is nested as deep as the cores around it (arms of a
+/// top-level core are at depth 1, arms of a core inside one of those arms at
+/// depth 2, and so on); a core and its arms are at the same depth, so the
+/// code leading into a core stays with its first arms. The `::` comment
+/// block above an arm or core documents it and starts the chunk with it.
+pub fn hoon_breaks(lines: &[String]) -> Vec<Option<usize>> {
+    let mut breaks: Vec<Option<usize>> = vec![None; lines.len()];
+    let mut depth = 0;
+    let mut after_core = false;
+    
+    for (index, line) in lines.iter().enumerate() {
+        // Runes in trailing comments don't count
+        let mut tokens = line.split_whitespace().take_while(|token| !token.starts_with("::")).peekable();
+        let Some(&first) = tokens.peek() else {
+            continue;
+        };
+        
+        // The code after a core continues at the depth around it, unless
+        // it just closes the core around that
+        if after_core && first != "--" {
+            breaks[index] = Some(depth);
+        }
+        after_core = false;
+        if ARM_RUNES.contains(&first) {
+            breaks[index] = Some(breaks[index].map_or(depth, |other| other.min(depth)));
+        }
+        
+        for token in tokens {
+            if token == "--" {
+                depth = depth.saturating_sub(1);
+                after_core = true;
+            } else if CORE_RUNES.contains(&token) {
+                depth += 1;
+                if breaks[index].is_none() && first != "--" {
+                    breaks[index] = Some(depth);
+                }
+            }
+        }
+    }
+    
+    attach_comments(lines, &mut breaks);
+    breaks
+}
+
+/// Move every break up over the comment block and blank lines above it
+/// 
+/// Comments indented deeper than the line they're above belong to the end
+/// of the code before them and stay where they are.
+fn attach_comments(lines: &[String], breaks: &mut [Option<usize>]) {
+    for index in 0..lines.len() {
+        let Some(depth) = breaks[index] else {
+            continue;
+        };
+        
+        let indent = indentation(&lines[index]);
+        let mut top = index;
+        while top > 0 && breaks[top - 1].is_none() {
+            let above = lines[top - 1].trim_start();
+            let attached = above.is_empty() || (above.starts_with("::") && indentation(&lines[top - 1]) <= indent);
+            if !attached {
+                break;
+            }
+            top -= 1;
+        }
+        
+        if top != index {
+            breaks[index] = None;
+            breaks[top] = Some(depth);
+        }
+    }
+}
+
+/// Number of leading whitespace characters of a line
+fn indentation(line: &str) -> usize {
+    line.chars().take_while(|c| c.is_whitespace()).count()
+}
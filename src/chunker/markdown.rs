@@ -0,0 +1,211 @@
+use std::ops::Range;
+
+/// Nesting depth of blocks (paragraphs, lists, code fences and tables), below every heading level
+const BLOCK_DEPTH: usize = 6;
+
+/// Nesting depth of list items inside other list items
+const NESTED_ITEM_DEPTH: usize = 7;
+
+/// The structure of a Markdown document
+#[derive(Debug)]
+struct Outline {
+    /// For every line, its nesting depth if a chunk may start there
+    breaks: Vec<Option<usize>>,
+    /// Code fences and tables, which chunks never split
+    blocks: Vec<Range<usize>>,
+    /// Level and title of the heading on every line
+    headings: Vec<Option<(usize, String)>>,
+}
+
+/// Find the lines of a Markdown document where a chunk may start
+/// 
+/// Headings may start a chunk at a depth one less than their level (`#` at
+/// depth 0, `##` at depth 1, ...), so sections are packed together by
+/// level. Paragraphs, list items, code fences and tables may start a chunk
+/// below every heading level, and nested list items deeper still.
+pub fn markdown_breaks(lines: &[String]) -> Vec<Option<usize>> {
+    outline(lines).breaks
+}
+
+/// Find the code fences and tables of a Markdown document, as ranges of lines
+pub fn markdown_blocks(lines: &[String]) -> Vec<Range<usize>> {
+    outline(lines).blocks
+}
+
+/// The titles of the headings a line is under, outermost first
+/// 
+/// A heading on the line itself is the last title.
+pub fn markdown_breadcrumb(lines: &[String], line: usize) -> Vec<String> {
+    let mut trail: Vec<(usize, String)> = Vec::new();
+    for (level, title) in outline(lines).headings.into_iter().take(line + 1).flatten() {
+        while trail.last().is_some_and(|(outer, _)| *outer >= level) {
+            trail.pop();
+        }
+        trail.push((level, title));
+    }
+    trail.into_iter().map(|(_, title)| title).collect()
+}
+
+/// Parse the headings and blocks of a Markdown document
+fn outline(lines: &[String]) -> Outline {
+    let mut outline = Outline {
+        breaks: vec![None; lines.len()],
+        blocks: Vec::new(),
+        headings: vec![None; lines.len()],
+    };
+    // Opening character, length and line of the code fence being read
+    let mut fence: Option<(char, usize, usize)> = None;
+    // First line of the table being read
+    let mut table: Option<usize> = None;
+    // First line of the paragraph being read, which a setext underline turns into a heading
+    let mut paragraph: Option<usize> = None;
+    // Whether the next line starts a new block
+    let mut block_ended = true;
+    
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        
+        if let Some((marker, length, start)) = fence {
+            if indent <= 3 && closes_fence(trimmed, marker, length) {
+                outline.blocks.push(start..index + 1);
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(start) = table {
+            if !trimmed.is_empty() && line.contains('|') {
+                continue;
+            }
+            outline.blocks.push(start..index);
+            table = None;
+        }
+        if trimmed.is_empty() {
+            paragraph = None;
+            block_ended = true;
+            continue;
+        }
+        
+        if indent <= 3 {
+            if let Some((marker, length)) = opens_fence(trimmed) {
+                fence = Some((marker, length, index));
+                outline.breaks[index] = Some(BLOCK_DEPTH);
+                paragraph = None;
+                block_ended = false;
+                continue;
+            }
+            if let Some((level, title)) = atx_heading(trimmed) {
+                outline.breaks[index] = Some(level - 1);
+                outline.headings[index] = Some((level, title));
+                paragraph = None;
+                block_ended = true;
+                continue;
+            }
+            if let Some(level) = setext_level(trimmed).filter(|_| index > 0 && paragraph == Some(index - 1)) {
+                outline.breaks[index - 1] = Some(level - 1);
+                outline.headings[index - 1] = Some((level, lines[index - 1].trim().to_string()));
+                paragraph = None;
+                block_ended = true;
+                continue;
+            }
+        }
+        if line.contains('|') && lines.get(index + 1).is_some_and(|next| is_delimiter_row(next)) {
+            table = Some(index);
+            outline.breaks[index] = Some(BLOCK_DEPTH);
+            paragraph = None;
+            block_ended = false;
+            continue;
+        }
+        if is_list_item(trimmed) {
+            outline.breaks[index] = Some(if indent >= 2 { NESTED_ITEM_DEPTH } else { BLOCK_DEPTH });
+            paragraph = None;
+            block_ended = false;
+            continue;
+        }
+        
+        if block_ended {
+            outline.breaks[index] = Some(BLOCK_DEPTH);
+            paragraph = Some(index);
+        }
+        block_ended = false;
+    }
+    
+    // Unclosed fences and tables run to the end of the document
+    if let Some((_, _, start)) = fence {
+        outline.blocks.push(start..lines.len());
+    }
+    if let Some(start) = table {
+        outline.blocks.push(start..lines.len());
+    }
+    
+    outline
+}
+
+/// The marker and length of a code fence opened by a line, if it opens one
+fn opens_fence(trimmed: &str) -> Option<(char, usize)> {
+    let marker = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let length = trimmed.chars().take_while(|&c| c == marker).count();
+    // Backtick fences can't have backticks in their info string
+    let info = &trimmed[length..];
+    (length >= 3 && !(marker == '`' && info.contains('`'))).then_some((marker, length))
+}
+
+/// Whether a line closes a code fence opened with `length` of `marker`
+fn closes_fence(trimmed: &str, marker: char, length: usize) -> bool {
+    let count = trimmed.chars().take_while(|&c| c == marker).count();
+    count >= length && trimmed[count..].trim().is_empty()
+}
+
+/// The level and title of an ATX heading (`## Title`), if the line is one
+fn atx_heading(trimmed: &str) -> Option<(usize, String)> {
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    
+    // A closing sequence of #s only counts after a space, so `C#` keeps its #
+    let title = rest.trim();
+    let without_closing = title.trim_end_matches('#');
+    let title = if without_closing.is_empty() || without_closing.ends_with([' ', '\t']) {
+        without_closing.trim_end()
+    } else {
+        title
+    };
+    Some((level, title.to_string()))
+}
+
+/// The heading level a setext underline (`===` or `---`) gives the line above it
+fn setext_level(trimmed: &str) -> Option<usize> {
+    let underline = trimmed.trim_end();
+    if !underline.is_empty() && underline.chars().all(|c| c == '=') {
+        Some(1)
+    } else if !underline.is_empty() && underline.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Whether a line is the delimiter row under a table's header (`| --- | :-: |`)
+fn is_delimiter_row(line: &str) -> bool {
+    let row = line.trim();
+    row.contains('-') && row.contains('|') && row.chars().all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Whether a line starts a bullet (`- `, `* `, `+ `) or numbered (`1. `, `1) `) list item
+fn is_list_item(trimmed: &str) -> bool {
+    let marker_end = match trimmed.chars().next() {
+        Some('-' | '*' | '+') => 1,
+        Some(c) if c.is_ascii_digit() => {
+            let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits > 9 || !trimmed[digits..].starts_with(['.', ')']) {
+                return false;
+            }
+            digits + 1
+        },
+        _ => return false,
+    };
+    let rest = &trimmed[marker_end..];
+    rest.is_empty() || rest.starts_with([' ', '\t'])
+}
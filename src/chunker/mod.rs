@@ -0,0 +1,351 @@
+mod hoon;
+mod markdown;
+mod syntax;
+
+use std::ops::Range;
+use std::path::Path;
+
+pub use hoon::hoon_breaks;
+pub use markdown::{markdown_blocks, markdown_breadcrumb, markdown_breaks};
+pub use syntax::{syntax_breaks, Language};
+
+/// Metadata field holding the headings a chunk is under (e.g. `Install > Linux`)
+pub const BREADCRUMB_FIELD: &str = "breadcrumb";
+
+/// Source structure chunks can follow instead of blank lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Structure {
+    /// Hoon cores and arms
+    Hoon,
+    /// Markdown sections, by heading level
+    Markdown,
+    /// Items of source code (functions, impls, classes, modules), from its syntax tree
+    Syntax(Language),
+}
+
+impl Structure {
+    /// The structure of a file, judging by its extension
+    pub fn for_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "hoon" => Some(Structure::Hoon),
+            "md" | "markdown" => Some(Structure::Markdown),
+            extension => Language::from_extension(extension).map(Structure::Syntax),
+        }
+    }
+    
+    /// What chunks are split at, for status messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Structure::Hoon => "Hoon arms",
+            Structure::Markdown => "Markdown headings",
+            Structure::Syntax(Language::Rust) => "Rust items",
+            Structure::Syntax(Language::Python) => "Python definitions",
+            Structure::Syntax(Language::TypeScript | Language::Tsx) => "TypeScript declarations",
+        }
+    }
+    
+    /// For every line, how deeply nested it is if a chunk may start there
+    pub fn breaks(&self, lines: &[String]) -> Vec<Option<usize>> {
+        match self {
+            Structure::Hoon => hoon_breaks(lines),
+            Structure::Markdown => markdown_breaks(lines),
+            Structure::Syntax(language) => syntax_breaks(*language, lines),
+        }
+    }
+    
+    /// Ranges of lines a chunk must not be split inside (e.g. code fences)
+    pub fn blocks(&self, lines: &[String]) -> Vec<Range<usize>> {
+        match self {
+            Structure::Hoon | Structure::Syntax(_) => Vec::new(),
+            Structure::Markdown => markdown_blocks(lines),
+        }
+    }
+    
+    /// Where in the document the chunk of lines `start..=end` is, for its metadata
+    /// 
+    /// The headings its first non-blank line is under for Markdown (e.g.
+    /// `Install > Linux`), or `None` if the structure has no such context.
+    pub fn breadcrumb(&self, lines: &[String], start: usize, end: usize) -> Option<String> {
+        let line = (start..=end).find(|&line| !lines[line].trim().is_empty()).unwrap_or(start);
+        match self {
+            Structure::Hoon | Structure::Syntax(_) => None,
+            Structure::Markdown => Some(markdown_breadcrumb(lines, line).join(" > ")).filter(|trail| !trail.is_empty()),
+        }
+    }
+}
+
+/// A chunk proposed by the planner, before it's saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposedChunk {
+    /// First line of the chunk (0-indexed)
+    pub start: usize,
+    /// Last line of the chunk (0-indexed)
+    pub end: usize,
+    /// Exact token count of the chunk, including any prompt template
+    pub tokens: usize,
+}
+
+/// Plans chunk boundaries so that chunks fit a token budget
+/// 
+/// Line counts give a quick estimate of how many lines fit; every chunk is
+/// then counted exactly with `exact` (which should wrap the text in the
+/// prompt template, if any) and shrunk until it really fits. A single line
+/// over the budget still becomes a chunk of its own, so nothing is skipped.
+pub struct ChunkPlanner<'a> {
+    /// Lines of the file
+    lines: &'a [String],
+    
+    /// Tokens in all lines before each index (one longer than `lines`)
+    prefix: Vec<usize>,
+    
+    /// Largest token count a chunk may have
+    max_tokens: usize,
+    
+    /// Tokens every chunk costs on top of its lines (e.g. the prompt template)
+    overhead: usize,
+    
+    /// Exact token count of lines `start..=end` as a chunk
+    exact: Box<dyn Fn(usize, usize) -> usize + 'a>,
+    
+    /// For every line, its nesting depth if a chunk may start there
+    /// 
+    /// Empty unless the chunks follow a `Structure`; blank lines are
+    /// preferred then.
+    breaks: Vec<Option<usize>>,
+    
+    /// Ranges of lines chunks must not be split inside
+    blocks: Vec<Range<usize>>,
+}
+
+impl<'a> ChunkPlanner<'a> {
+    /// Plan chunks of `lines`, whose token counts are `line_tokens`
+    pub fn new(
+        lines: &'a [String],
+        line_tokens: &[usize],
+        max_tokens: usize,
+        overhead: usize,
+        exact: impl Fn(usize, usize) -> usize + 'a,
+    ) -> Self {
+        let mut prefix = Vec::with_capacity(line_tokens.len() + 1);
+        prefix.push(0);
+        for &count in line_tokens {
+            prefix.push(prefix[prefix.len() - 1] + count);
+        }
+        
+        Self { lines, prefix, max_tokens, overhead, exact: Box::new(exact), breaks: Vec::new(), blocks: Vec::new() }
+    }
+    
+    /// Pack chunks along the structure of the source rather than at blank lines
+    /// 
+    /// `breaks` gives, for every line, how deeply it's nested if a chunk may
+    /// start there (see `Structure::breaks`).
+    pub fn with_breaks(mut self, breaks: Vec<Option<usize>>) -> Self {
+        self.breaks = breaks;
+        self
+    }
+    
+    /// Keep every range of lines in `blocks` (e.g. code fences) within one chunk
+    /// 
+    /// A chunk ends before a block that doesn't fit, and a block too large
+    /// for the budget becomes a chunk of its own, like a single long line.
+    pub fn with_blocks(mut self, blocks: Vec<Range<usize>>) -> Self {
+        self.blocks = blocks;
+        self
+    }
+    
+    /// Estimated tokens in lines `start..=end` as a chunk
+    /// 
+    /// Adds up the lines, the newlines joining them and the overhead.
+    pub fn estimate(&self, start: usize, end: usize) -> usize {
+        self.line_tokens(start, end) + self.overhead
+    }
+    
+    /// Estimated tokens in lines `start..=end` and the newlines joining them
+    fn line_tokens(&self, start: usize, end: usize) -> usize {
+        self.prefix[end + 1] - self.prefix[start] + (end - start)
+    }
+    
+    /// Exact tokens in lines `start..=end` as a chunk
+    pub fn measure(&self, start: usize, end: usize) -> usize {
+        (self.exact)(start, end)
+    }
+    
+    /// The proposed chunk for lines `start..=end`, counted exactly
+    pub fn propose(&self, start: usize, end: usize) -> ProposedChunk {
+        ProposedChunk { start, end, tokens: self.measure(start, end) }
+    }
+    
+    /// Greedily pack every run of lines into chunks up to the token budget
+    /// 
+    /// Runs are ranges of line indices (e.g. the parts of a file no chunk
+    /// covers yet) and are packed independently.
+    pub fn pack(&self, runs: &[Range<usize>]) -> Vec<ProposedChunk> {
+        runs.iter().flat_map(|run| self.pack_run(run.clone())).collect()
+    }
+    
+    /// Greedily pack consecutive lines into chunks up to the token budget
+    /// 
+    /// Each chunk takes as many lines as fit, but ends after a blank line
+    /// if there's one past the middle of the budget, so paragraphs and
+    /// definitions stay together. With breaks from a structure, each chunk
+    /// ends instead before the least nested break that fits, so small
+    /// definitions are packed together and large ones are split at the
+    /// definitions inside them. Chunks cover the run without gaps.
+    pub fn pack_run(&self, run: Range<usize>) -> Vec<ProposedChunk> {
+        let mut chunks = Vec::new();
+        let mut start = run.start;
+        
+        while start < run.end {
+            let chunk = self.fit(start, run.end, true);
+            start = chunk.end + 1;
+            chunks.push(chunk);
+        }
+        
+        chunks
+    }
+    
+    /// Cover every run of lines with overlapping windows up to the token budget
+    /// 
+    /// Like `pack`, but each window shares about `overlap` tokens with the
+    /// one before it. Runs are covered independently.
+    pub fn windows(&self, runs: &[Range<usize>], overlap: usize) -> Vec<ProposedChunk> {
+        runs.iter().flat_map(|run| self.window_run(run.clone(), overlap)).collect()
+    }
+    
+    /// Cover consecutive lines with windows that slide by whole lines
+    /// 
+    /// Every window takes as many lines as fit, without looking for blank
+    /// lines since the overlap keeps context together anyway. The next
+    /// window starts at the latest line that still leaves `overlap` tokens
+    /// shared, and always at least a line further on. The overlap is capped
+    /// at half the budget, so a large one can't make every window repeat
+    /// all but one line of the window before.
+    pub fn window_run(&self, run: Range<usize>, overlap: usize) -> Vec<ProposedChunk> {
+        let overlap = overlap.min(self.max_tokens / 2);
+        let mut windows = Vec::new();
+        let mut start = run.start;
+        // First line no window covers yet
+        let mut covered = run.start;
+        
+        while covered < run.end {
+            let mut window = self.fit(start, run.end, false);
+            if window.end < covered {
+                // A line too long to share a window follows; start afresh after the overlap
+                window = self.fit(covered, run.end, false);
+            }
+            
+            covered = window.end + 1;
+            start = if overlap == 0 {
+                covered
+            } else {
+                (window.start + 1..=window.end)
+                    .rev()
+                    .find(|&start| self.line_tokens(start, window.end) >= overlap)
+                    .unwrap_or(window.start + 1)
+            };
+            windows.push(window);
+        }
+        
+        windows
+    }
+    
+    /// The longest chunk from `start` that fits the budget, ending before `run_end`
+    /// 
+    /// The estimate picks how many lines to take (at least one), then the
+    /// chunk shrinks until the exact count fits. With `prefer_blank` the
+    /// chunk ends at a structural break or after a blank line (see
+    /// `pack_run`); the last chunk of a run needs no boundary and takes the rest.
+    fn fit(&self, start: usize, run_end: usize, prefer_blank: bool) -> ProposedChunk {
+        let boundary = |limit: usize| {
+            let end = match prefer_blank {
+                true if !self.breaks.is_empty() => self.structural_end(start, limit),
+                true => self.preferred_end(start, limit),
+                false => limit,
+            };
+            self.outside_blocks(start, end, run_end)
+        };
+        
+        let mut limit = start;
+        while limit + 1 < run_end && self.estimate(start, limit + 1) <= self.max_tokens {
+            limit += 1;
+        }
+        
+        let mut end = if limit + 1 == run_end { limit } else { boundary(limit) };
+        let mut tokens = self.measure(start, end);
+        while tokens > self.max_tokens && end > start {
+            // A block can't shrink any further
+            let shorter = boundary(end - 1);
+            if shorter >= end {
+                break;
+            }
+            end = shorter;
+            tokens = self.measure(start, end);
+        }
+        
+        ProposedChunk { start, end, tokens }
+    }
+    
+    /// Where a chunk starting at `start` should end, at or before `limit`
+    /// 
+    /// The last blank line that leaves the chunk at least half full, or
+    /// `limit` if there's none.
+    fn preferred_end(&self, start: usize, limit: usize) -> usize {
+        (start..=limit)
+            .rev()
+            .take_while(|&end| self.estimate(start, end) * 2 >= self.max_tokens)
+            .find(|&end| self.lines[end].trim().is_empty())
+            .unwrap_or(limit)
+    }
+    
+    /// Move the end of a chunk out of any block it would split
+    /// 
+    /// The chunk ends before the block instead, or takes all of it (up to
+    /// the end of the run) if it starts with the block.
+    fn outside_blocks(&self, start: usize, end: usize, run_end: usize) -> usize {
+        match self.blocks.iter().find(|block| block.start <= end && end + 1 < block.end) {
+            Some(block) if block.start > start => block.start - 1,
+            Some(block) => (block.end - 1).min(run_end - 1),
+            None => end,
+        }
+    }
+    
+    /// Where a chunk starting at `start` should end, at or before `limit`, following the breaks
+    /// 
+    /// Ends before the last of the least nested breaks after `start`. If
+    /// that leaves the chunk less than half full and the definition after
+    /// the break has to be split anyway, the chunk takes the first part of
+    /// it instead. Without breaks to end before, ends like `preferred_end`.
+    fn structural_end(&self, start: usize, limit: usize) -> usize {
+        let candidates: Vec<(usize, usize)> = (start + 1..=limit + 1)
+            .filter_map(|next| Some((self.breaks.get(next).copied().flatten()?, next)))
+            .collect();
+        let least_nested = |candidates: &mut dyn Iterator<Item = &(usize, usize)>| {
+            candidates.min_by_key(|&&(depth, next)| (depth, std::cmp::Reverse(next))).copied()
+        };
+        
+        let Some((depth, next)) = least_nested(&mut candidates.iter()) else {
+            return self.preferred_end(start, limit);
+        };
+        if self.estimate(start, next - 1) * 2 < self.max_tokens && !self.definition_fits(next, depth) {
+            let filled = least_nested(&mut candidates.iter().filter(|&&(_, next)| self.estimate(start, next - 1) * 2 >= self.max_tokens));
+            if let Some((_, next)) = filled {
+                return next - 1;
+            }
+        }
+        next - 1
+    }
+    
+    /// Whether the definition starting at break `start` fits the budget as one chunk
+    /// 
+    /// The definition runs until the next break nested at most `depth` deep.
+    fn definition_fits(&self, start: usize, depth: usize) -> bool {
+        let mut end = start;
+        while self.estimate(start, end) <= self.max_tokens {
+            if end + 1 == self.lines.len() || self.breaks.get(end + 1).copied().flatten().is_some_and(|next| next <= depth) {
+                return true;
+            }
+            end += 1;
+        }
+        false
+    }
+}
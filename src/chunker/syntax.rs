@@ -0,0 +1,130 @@
+use tree_sitter::{Node, Parser};
+
+/// Languages whose files are chunked along their syntax tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    TypeScript,
+    /// TypeScript with JSX
+    Tsx,
+}
+
+impl Language {
+    /// The language of files with an extension, if it's one we can parse
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "rs" => Some(Language::Rust),
+            "py" | "pyi" => Some(Language::Python),
+            "ts" | "mts" | "cts" => Some(Language::TypeScript),
+            "tsx" => Some(Language::Tsx),
+            _ => None,
+        }
+    }
+    
+    /// The vendored tree-sitter grammar
+    fn grammar(&self) -> tree_sitter::Language {
+        match self {
+            Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Language::Python => tree_sitter_python::LANGUAGE.into(),
+            Language::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Language::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+        }
+    }
+    
+    /// Kinds of nodes whose children are items a chunk may start at
+    /// 
+    /// Files, module/impl/trait/class bodies and function bodies, so large
+    /// items are split at the items or statements inside them.
+    fn containers(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &[
+                "source_file",
+                "declaration_list",
+                "block",
+                "match_block",
+                "field_declaration_list",
+                "enum_variant_list",
+            ],
+            Language::Python => &["module", "block"],
+            Language::TypeScript | Language::Tsx => &[
+                "program",
+                "statement_block",
+                "class_body",
+                "interface_body",
+                "enum_body",
+                "switch_body",
+            ],
+        }
+    }
+    
+    /// Kinds of nodes that belong to the item after them (comments, attributes, decorators)
+    fn leading(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &["line_comment", "block_comment", "attribute_item", "inner_attribute_item"],
+            Language::Python => &["comment"],
+            Language::TypeScript | Language::Tsx => &["comment", "decorator"],
+        }
+    }
+}
+
+/// Find the lines of a source file where a chunk may start, from its syntax tree
+/// 
+/// Every item (function, impl, class, module, ...) and every statement in a
+/// body may start a chunk, nested as deep as the bodies around it: items of
+/// the file are at depth 0, methods of an impl or class at depth 1, and so
+/// on. The comments and attributes right above an item start the chunk with
+/// it. A file the grammar can't be loaded for has no breaks.
+pub fn syntax_breaks(language: Language, lines: &[String]) -> Vec<Option<usize>> {
+    let mut breaks = vec![None; lines.len()];
+    let mut parser = Parser::new();
+    if parser.set_language(&language.grammar()).is_err() {
+        return breaks;
+    }
+    if let Some(tree) = parser.parse(lines.join("\n"), None) {
+        mark_items(language, tree.root_node(), 0, None, &mut breaks);
+    }
+    breaks
+}
+
+/// Mark where the items in and below a node start
+/// 
+/// `leading` is the first line of comments above the node that belong to its
+/// first item, which some grammars put outside the body (Python puts a
+/// comment between `class A:` and the first line of its body in the class).
+fn mark_items(language: Language, node: Node, depth: usize, mut leading: Option<usize>, breaks: &mut [Option<usize>]) {
+    let container = language.containers().contains(&node.kind());
+    // Last line of the previous item, which nothing starting on it can split off
+    let mut previous_end: Option<usize> = None;
+    
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let row = child.start_position().row;
+        let shares_line = previous_end == Some(row);
+        let is_leading = language.leading().contains(&child.kind());
+        // `leading` is the first line of the comments and attributes above this child
+        if is_leading {
+            // A comment after code on the same line belongs to that code
+            if !shares_line {
+                leading.get_or_insert(row);
+            }
+        } else if container {
+            let start = leading.take().or((!shares_line).then_some(row));
+            if let Some(line) = start.and_then(|start| breaks.get_mut(start)) {
+                *line = Some(line.map_or(depth, |other| other.min(depth)));
+            }
+        }
+        previous_end = Some(child.end_position().row);
+        
+        // Comments still waiting for an item belong to the first one in a body
+        let carried = if is_leading {
+            None
+        } else if language.containers().contains(&child.kind()) {
+            leading.take()
+        } else {
+            leading = None;
+            None
+        };
+        mark_items(language, child, depth + usize::from(container), carried, breaks);
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{bail, Context, Result};
+use clap::builder::PossibleValuesParser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::{Path, PathBuf};
+
+use crate::app::App;
+use crate::config::Config;
+use crate::storage::{self, ChunkStats, ChunkStorage};
+use crate::utils::prompt::PromptContext;
+use crate::viewer::{self, ChunkStrategy};
+
+/// Where the configuration comes from, shown after the help
+const CONFIG_HELP: &str = "\
+CONFIGURATION:
+  Packrat searches for configuration in the following locations:
+  1. ./packrat.toml (current directory)
+  2. User config directory (platform-specific)
+  
+  Run 'packrat config --init' to create a default config file with
+  comments explaining all available options.";
+
+/// Packrat - Interactive text file chunker
+#[derive(Parser)]
+#[command(name = "packrat", version, about, after_help = CONFIG_HELP)]
+pub struct Cli {
+    #[command(flatten)]
+    overrides: Overrides,
+    
+    /// What to do (the interactive chunker if left out)
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Settings that replace the config file's for a single run, for every command
+#[derive(Args)]
+struct Overrides {
+    /// Read the configuration from this file instead of searching for one
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    
+    /// Store chunks in this file (chunk_file)
+    #[arg(long, global = true, value_name = "PATH")]
+    chunk_file: Option<PathBuf>,
+    
+    /// Chunk files under this directory (source_dir)
+    #[arg(long, global = true, value_name = "DIR")]
+    source_dir: Option<PathBuf>,
+    
+    /// Token budget per chunk (max_tokens_per_chunk)
+    #[arg(long, global = true, value_name = "N")]
+    max_tokens: Option<usize>,
+    
+    /// Set any other config field, e.g. --set tokenizer=o200k_base (repeat for several)
+    #[arg(long = "set", global = true, value_name = "FIELD=VALUE")]
+    fields: Vec<String>,
+}
+
+impl Overrides {
+    /// Load the configuration and apply the overrides to it
+    fn load(&self) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::load_from_file(path)?,
+            None => Config::load()?,
+        };
+        
+        for field in &self.fields {
+            let (key, value) = field
+                .split_once('=')
+                .with_context(|| format!("--set takes FIELD=VALUE, not {}", field))?;
+            config.set(key.trim(), value.trim())?;
+        }
+        if let Some(chunk_file) = &self.chunk_file {
+            config.chunk_file = chunk_file.clone();
+        }
+        if let Some(source_dir) = &self.source_dir {
+            config.source_dir = source_dir.clone();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.max_tokens_per_chunk = max_tokens;
+        }
+        
+        config.validate().context("Invalid configuration after overrides")?;
+        Ok(config)
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Browse and chunk files interactively (the default)
+    Tui,
+    
+    /// Chunk files and directories without the TUI, saving the chunks to storage
+    /// 
+    /// Only lines no chunk covers yet are chunked, so fully chunked files are
+    /// skipped and the command can run again as files are added.
+    Chunk {
+        /// Files and directories to chunk (directories are walked recursively)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        
+        /// How chunks are proposed
+        #[arg(short, long, default_value = "structure", value_parser = PossibleValuesParser::new(ChunkStrategy::ALL.map(ChunkStrategy::name)))]
+        strategy: String,
+        
+        /// Label every saved chunk (repeat for several labels)
+        #[arg(short, long = "label", value_name = "NAME")]
+        labels: Vec<String>,
+    },
+    
+    /// Export chunks to a .jsonl or .csv file
+    Export {
+        /// File to write, in the format its extension names
+        output: PathBuf,
+        
+        /// Only export chunks with this label (repeat for several labels)
+        #[arg(short, long = "label", value_name = "NAME")]
+        labels: Vec<String>,
+    },
+    
+    /// Import chunks from a .jsonl or .csv export
+    Import {
+        /// Export to read, in the format its extension names
+        input: PathBuf,
+    },
+    
+    /// Show counts of the stored chunks
+    Stats,
+    
+    /// Check stored chunks against their source files and the token budget
+    Validate,
+    
+    /// Show the configuration in effect, with overrides applied
+    Config {
+        /// Create a default configuration file in the user config directory instead
+        #[arg(long)]
+        init: bool,
+    },
+    
+    /// Print a shell completion script
+    Completions {
+        /// Shell to complete commands for
+        shell: Shell,
+    },
+}
+
+/// Run the command given on the command line
+pub fn run(cli: Cli) -> Result<()> {
+    let command = cli.command.unwrap_or(Command::Tui);
+    
+    // These don't need a valid configuration
+    match command {
+        Command::Config { init: true } => {
+            let config_path = Config::create_default_config()
+                .context("Failed to create default configuration file")?;
+            println!("Default configuration created at: {}", config_path.display());
+            println!("Edit this file to customize Packrat's behavior.");
+            return Ok(());
+        },
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "packrat", &mut std::io::stdout());
+            return Ok(());
+        },
+        _ => {},
+    }
+    
+    let config = cli.overrides.load()?;
+    match command {
+        Command::Tui => App::new(config)?.run(),
+        Command::Chunk { paths, strategy, labels } => chunk(&config, &paths, ChunkStrategy::from_name(&strategy)?, labels),
+        Command::Export { output, labels } => export(&config, &output, labels),
+        Command::Import { input } => import(&config, &input),
+        Command::Stats => stats(&config),
+        Command::Validate => validate(&config),
+        Command::Config { .. } => {
+            print!("{}", toml::to_string_pretty(&config).context("Failed to serialize config to TOML")?);
+            Ok(())
+        },
+        Command::Completions { .. } => Ok(()),
+    }
+}
+
+/// Open the configured chunk storage
+fn open_storage(config: &Config) -> Result<ChunkStorage> {
+    let chunk_file = config.absolute_chunk_file();
+    ChunkStorage::open(&chunk_file, config.storage_backend)
+        .with_context(|| format!("Failed to open chunk storage at: {:?}", chunk_file))
+}
+
+/// Print what storage recovered from, e.g. saves that couldn't be journalled for undo
+fn print_warnings(chunk_storage: &mut ChunkStorage) {
+    for warning in chunk_storage.take_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Chunk files without the TUI and print what happened to each
+fn chunk(config: &Config, paths: &[PathBuf], strategy: ChunkStrategy, labels: Vec<String>) -> Result<()> {
+    let mut chunk_storage = open_storage(config)?;
+    let options = viewer::BatchOptions {
+        strategy,
+        max_tokens: config.max_tokens_per_chunk,
+        sliding_window: (config.window_tokens, config.window_overlap_tokens),
+        prompt_template: config.prompt_template.clone(),
+        tokenizer: config.load_tokenizer()?,
+        labels,
+        root_dir: config.absolute_source_dir(),
+        exclude: vec![config.absolute_chunk_file()],
+    };
+    let reports = viewer::chunk_files(paths, &mut chunk_storage, &options)?;
+    print_warnings(&mut chunk_storage);
+    
+    let (mut chunked, mut chunks, mut failed) = (0, 0, 0);
+    for viewer::FileReport { path, outcome } in &reports {
+        let summary = match outcome {
+            viewer::FileOutcome::Chunked { chunks: count, largest } => {
+                chunked += 1;
+                chunks += count;
+                format!("{} chunks saved (largest {} tokens)", count, largest)
+            },
+            viewer::FileOutcome::AlreadyChunked => "skipped, already chunked".to_string(),
+            viewer::FileOutcome::Empty => "skipped, empty".to_string(),
+            viewer::FileOutcome::NotText => "skipped, not a text file".to_string(),
+            viewer::FileOutcome::Failed(error) => {
+                failed += 1;
+                format!("failed: {}", error)
+            },
+        };
+        println!("{}: {}", path.display(), summary);
+    }
+    println!(
+        "Chunked {} of {} files into {} chunks ({}, at most {} tokens each)",
+        chunked, reports.len(), chunks, strategy.name(), config.max_tokens_per_chunk
+    );
+    
+    // Failures fail the run, so CI notices them
+    if failed > 0 {
+        bail!("{} files couldn't be chunked", failed);
+    }
+    Ok(())
+}
+
+/// Export chunks, optionally only those with some labels
+fn export(config: &Config, output: &Path, labels: Vec<String>) -> Result<()> {
+    // Chunks are also exported rendered in the prompt template, if one is configured
+    let options = storage::ExportOptions { labels, prompt_template: config.prompt_template.clone() };
+    let format = storage::ExportFormat::from_path(output)?;
+    let count = storage::export_chunks(&open_storage(config)?, output, format, &options)?;
+    
+    println!("Exported {} chunks to {}", count, output.display());
+    Ok(())
+}
+
+/// Import chunks from an export
+fn import(config: &Config, input: &Path) -> Result<()> {
+    let format = storage::ExportFormat::from_path(input)?;
+    let mut chunk_storage = open_storage(config)?;
+    let report = storage::import_chunks(&mut chunk_storage, input, format)?;
+    print_warnings(&mut chunk_storage);
+    
+    println!("Imported {} chunks from {}", report.imported, input.display());
+    if report.skipped > 0 {
+        println!("Skipped {} chunks that were already stored", report.skipped);
+    }
+    Ok(())
+}
+
+/// Print counts of the stored chunks
+fn stats(config: &Config) -> Result<()> {
+    let chunks = open_storage(config)?.get_chunks()?;
+    let stats = ChunkStats::collect(&chunks);
+    let tokenizer = config.load_tokenizer()?;
+    let tokens: Vec<usize> = chunks.iter().map(|chunk| tokenizer.count(&chunk.content)).collect();
+    
+    println!("Chunks:  {} in {} files", stats.chunks, stats.files);
+    println!("         {} edited, {} character-wise, {} sliding-window passes", stats.edited, stats.partial, stats.window_passes);
+    println!(
+        "Tokens:  {} in total, {} at most ({})",
+        tokens.iter().sum::<usize>(),
+        tokens.iter().max().unwrap_or(&0),
+        tokenizer.name()
+    );
+    
+    // Labels and metadata fields, with the number of chunks that have each
+    let unlabelled = "(unlabelled)";
+    let width = stats.labels.keys().chain(stats.metadata_fields.keys()).map(String::len).fold(unlabelled.len(), usize::max);
+    println!("Labels:");
+    for (label, count) in &stats.labels {
+        println!("  {:width$}  {}", label, count);
+    }
+    println!("  {:width$}  {}", unlabelled, stats.unlabelled);
+    if !stats.metadata_fields.is_empty() {
+        println!("Metadata:");
+        for (field, count) in &stats.metadata_fields {
+            println!("  {:width$}  {}", field, count);
+        }
+    }
+    Ok(())
+}
+
+/// Check stored chunks and list every problem, failing if there are any
+fn validate(config: &Config) -> Result<()> {
+    let chunks = open_storage(config)?.get_chunks()?;
+    let tokenizer = config.load_tokenizer()?;
+    
+    // Chunks are counted as they're sent, like the viewer's token budget
+    let count = |chunk: &storage::Chunk| match &config.prompt_template {
+        Some(template) => {
+            let context = PromptContext {
+                file_path: chunk.file_path.to_string_lossy().to_string(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+            };
+            tokenizer.count(&template.render(&context, &chunk.content))
+        },
+        None => tokenizer.count(&chunk.content),
+    };
+    let problems = storage::validate_chunks(&chunks, &config.absolute_source_dir(), config.max_tokens_per_chunk, count);
+    
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    println!("Checked {} chunks: {} problems", chunks.len(), problems.len());
+    if !problems.is_empty() {
+        bail!("{} problems found", problems.len());
+    }
+    Ok(())
+}
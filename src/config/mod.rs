@@ -1,20 +1,55 @@
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use anyhow::{Context, Result, anyhow};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use toml;
+use crate::storage::{validate_metadata_fields, MetadataField, StorageBackend};
+use crate::utils::prompt::PromptTemplate;
+use crate::utils::{load_bpe_file, Tokenizer, TokenizerKind};
+
+/// The example config file: every default setting, with comments explaining it
+pub const EXAMPLE_CONFIG: &str = include_str!("../../packrat.example.toml");
 
 /// Application configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
-    /// Path to CSV file where chunks are stored
+    /// Path to the file where chunks are stored
     pub chunk_file: PathBuf,
     
+    /// Backend used to store chunks in `chunk_file`
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+    
     /// Maximum number of tokens per chunk (8192 = ~6K words)
     pub max_tokens_per_chunk: usize,
     
+    /// Tokens per window when chunking with a sliding window (capped at `max_tokens_per_chunk`)
+    #[serde(default = "default_window_tokens")]
+    pub window_tokens: usize,
+    
+    /// Tokens each window shares with the one before it
+    #[serde(default = "default_window_overlap_tokens")]
+    pub window_overlap_tokens: usize,
+    
+    /// Prompt each chunk is wrapped in; token meters count it against the budget
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<PromptTemplate>,
+    
+    /// Tokenizer used to count tokens
+    #[serde(default)]
+    pub tokenizer: TokenizerKind,
+    
+    /// BPE vocabulary file used instead of `tokenizer` (a tiktoken ranks
+    /// file or a HuggingFace `tokenizer.json`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer_file: Option<PathBuf>,
+    
+    /// Regex splitting text before BPE merges, for `tokenizer_file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer_pattern: Option<String>,
+    
     /// Enable debug features (like UI state dump)
     pub enable_debug: bool,
     
@@ -26,6 +61,29 @@ pub struct Config {
     
     /// Auto-save chunks when reaching max token count
     pub auto_save_chunks: bool,
+    
+    /// Ask for labels every time a chunk is saved
+    #[serde(default = "default_prompt_for_labels")]
+    pub prompt_for_labels: bool,
+    
+    /// Metadata fields filled in when saving a chunk (none by default)
+    #[serde(default)]
+    pub metadata_fields: Vec<MetadataField>,
+}
+
+/// Label prompts are shown unless turned off in the config file
+fn default_prompt_for_labels() -> bool {
+    true
+}
+
+/// Windows of 1024 tokens suit most retrieval setups
+fn default_window_tokens() -> usize {
+    1024
+}
+
+/// An eighth of the default window, so facts near a boundary land in both windows
+fn default_window_overlap_tokens() -> usize {
+    128
 }
 
 impl Default for Config {
@@ -35,9 +93,26 @@ impl Default for Config {
             // Default to "chunks.csv" in current directory
             chunk_file: PathBuf::from("chunks.csv"),
             
+            // CSV storage by default
+            storage_backend: StorageBackend::Csv,
+            
             // Claude model context size (8192 tokens ≈ 6K words)
             max_tokens_per_chunk: 8192,
             
+            // 1024-token windows overlapping by 128 tokens
+            window_tokens: default_window_tokens(),
+            window_overlap_tokens: default_window_overlap_tokens(),
+            
+            // Chunks are counted on their own
+            prompt_template: None,
+            
+            // OpenAI's cl100k_base encoding
+            tokenizer: TokenizerKind::Cl100k,
+            
+            // No custom vocabulary
+            tokenizer_file: None,
+            tokenizer_pattern: None,
+            
             // Debug features disabled by default in production
             enable_debug: false,
             
@@ -49,6 +124,12 @@ impl Default for Config {
             
             // Don't auto-save chunks by default
             auto_save_chunks: false,
+            
+            // Ask for labels when saving chunks
+            prompt_for_labels: true,
+            
+            // No structured metadata unless fields are declared
+            metadata_fields: Vec::new(),
         }
     }
 }
@@ -97,10 +178,58 @@ impl Config {
         let config: Self = toml::from_str(&contents)
             .with_context(|| format!("Failed to parse TOML config from: {}", path.display()))?;
         
+        config.validate()
+            .with_context(|| format!("Invalid config in: {}", path.display()))?;
+        
         Ok(config)
     }
     
+    /// Check the settings that can't be checked while parsing
+    pub fn validate(&self) -> Result<()> {
+        validate_metadata_fields(&self.metadata_fields)
+            .context("Invalid metadata_fields")?;
+        if let Some(template) = &self.prompt_template {
+            template.validate()
+                .context("Invalid prompt_template")?;
+        }
+        // Windows are capped at the chunk budget, so the overlap must fit in that too
+        let window = self.window_tokens.min(self.max_tokens_per_chunk);
+        if self.window_overlap_tokens >= window {
+            return Err(anyhow!(
+                "window_overlap_tokens ({}) must be smaller than the window ({} tokens: window_tokens capped at max_tokens_per_chunk)",
+                self.window_overlap_tokens,
+                window
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Set a field by its name in the config file, e.g. from a command-line override
+    /// 
+    /// The value is read as a TOML value (`true`, `4096`, `["a", "b"]`), or
+    /// taken as a string if it isn't one, so paths and names need no quotes.
+    /// Call `validate` once every override is applied.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        
+        let mut table = toml::Table::try_from(&*self).context("Failed to serialize config")?;
+        table.insert(key.to_string(), value);
+        let config: Self = table.try_into().with_context(|| format!("Invalid value for {}", key))?;
+        
+        // Unknown fields are ignored when parsing, so they'd be lost on the way back
+        if !toml::Table::try_from(&config).context("Failed to serialize config")?.contains_key(key) {
+            return Err(anyhow!("Unknown config field: {}", key));
+        }
+        *self = config;
+        Ok(())
+    }
+    
     /// Save configuration to a file
+    #[allow(dead_code)]
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let path = path.as_ref();
         
@@ -132,8 +261,10 @@ impl Config {
         let config_dir = project_dirs.config_dir();
         let config_path = config_dir.join("config.toml");
         
-        let config = Config::default();
-        config.save_to_file(&config_path)?;
+        fs::create_dir_all(config_dir)
+            .with_context(|| format!("Failed to create directory: {}", config_dir.display()))?;
+        fs::write(&config_path, EXAMPLE_CONFIG)
+            .with_context(|| format!("Failed to write config to: {}", config_path.display()))?;
         
         Ok(config_path)
     }
@@ -149,6 +280,22 @@ impl Config {
         }
     }
     
+    /// Load the configured tokenizer
+    /// 
+    /// A `tokenizer_file` takes precedence over the built-in `tokenizer`.
+    /// Relative paths are resolved against the current directory.
+    pub fn load_tokenizer(&self) -> Result<Arc<dyn Tokenizer>> {
+        let Some(file) = &self.tokenizer_file else {
+            return self.tokenizer.tokenizer();
+        };
+        let file = if file.is_absolute() {
+            file.clone()
+        } else {
+            std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")).join(file)
+        };
+        Ok(Arc::new(load_bpe_file(&file, self.tokenizer_pattern.as_deref())?))
+    }
+    
     /// Get the absolute path for the source directory
     pub fn absolute_source_dir(&self) -> PathBuf {
         if self.source_dir.is_absolute() {
@@ -1,7 +1,10 @@
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::prelude::{Widget, Style, Color, Modifier};
 use edtui::{EditorEventHandler, EditorState, EditorTheme, EditorView, EditorMode, RowIndex};
-use crate::utils::tokenizer::count_tokens;
+use std::sync::Arc;
+use crate::utils::prompt::{PromptContext, PromptTemplate};
+use crate::utils::token_cache::{BackgroundCounter, LineTokenCounts};
+use crate::utils::tokenizer::{default_tokenizer, Tokenizer};
 
 /// Text editor component 
 pub struct Editor {
@@ -21,6 +24,16 @@ pub struct Editor {
     file_name: Option<String>,
     /// Maximum tokens per chunk
     max_tokens: usize,
+    /// Token counts per line, updated only for the lines an edit touched
+    line_tokens: LineTokenCounts,
+    /// Exact count of the content, done in the background
+    exact_tokens: BackgroundCounter,
+    /// Bumped whenever the content changes, so outdated exact counts are ignored
+    content_version: u64,
+    /// Prompt the content is wrapped in and counted with, if configured
+    prompt: Option<(PromptTemplate, PromptContext)>,
+    /// Tokens the prompt adds around the content
+    prompt_overhead: usize,
 }
 
 impl Default for Editor {
@@ -41,6 +54,11 @@ impl Editor {
             command_mode: false,
             file_name: None,
             max_tokens: 8192, // Default max tokens, same as default config
+            line_tokens: LineTokenCounts::new(default_tokenizer()),
+            exact_tokens: BackgroundCounter::new(default_tokenizer()),
+            content_version: 0,
+            prompt: None,
+            prompt_overhead: 0,
         }
     }
     
@@ -56,6 +74,8 @@ impl Editor {
         }
         
         self.state = new_state;
+        self.line_tokens.recount(&lines);
+        self.content_version += 1;
         self.original_content = lines;
         self.modified = false;
         
@@ -84,22 +104,79 @@ impl Editor {
         self.max_tokens
     }
     
+    /// Set the tokenizer used for the token count
+    pub fn set_tokenizer(&mut self, tokenizer: Arc<dyn Tokenizer>) {
+        self.line_tokens = LineTokenCounts::new(tokenizer.clone());
+        self.exact_tokens = BackgroundCounter::new(tokenizer);
+        self.line_tokens.recount(&self.content());
+        let prompt = self.prompt.take();
+        self.set_prompt(prompt);
+    }
+    
+    /// Set the prompt template the content is counted in, with where the content comes from
+    pub fn set_prompt(&mut self, prompt: Option<(PromptTemplate, PromptContext)>) {
+        self.prompt_overhead = prompt
+            .as_ref()
+            .map_or(0, |(template, context)| self.line_tokens.tokenizer().count(&template.render(context, "")));
+        self.prompt = prompt;
+        self.content_version += 1;
+    }
+    
+    /// Check whether the content is counted in a prompt template
+    pub fn has_prompt(&self) -> bool {
+        self.prompt.is_some()
+    }
+    
+    /// Get the name of the tokenizer used for the token count
+    pub fn tokenizer_name(&self) -> &str {
+        self.line_tokens.tokenizer().name()
+    }
+    
     /// Count tokens in the current content
+    /// 
+    /// The exact count once the background recount finished, otherwise an
+    /// estimate from the per-line counts. With a prompt this is the count of
+    /// the content rendered in it.
     pub fn token_count(&self) -> usize {
-        let content = self.content();
-        let text = content.join("\n");
-        count_tokens(&text)
+        self.exact_tokens
+            .result(self.content_version)
+            .unwrap_or_else(|| self.line_tokens.total() + self.prompt_overhead)
+    }
+    
+    /// Whether the token count is still an estimate
+    pub fn token_count_pending(&self) -> bool {
+        self.exact_tokens.result(self.content_version).is_none()
+    }
+    
+    /// Queue an exact recount of the content and pick up a finished one
+    /// 
+    /// Called once per frame. Returns true if the exact count arrived.
+    pub fn poll_token_count(&mut self) -> bool {
+        let updated = self.exact_tokens.poll();
+        let (state, prompt) = (&self.state, &self.prompt);
+        self.exact_tokens.request(self.content_version, || {
+            let content = Self::lines_of(state).join("\n");
+            match prompt {
+                Some((template, context)) => template.render(context, &content),
+                None => content,
+            }
+        });
+        updated
     }
     
     /// Get the current content as lines
     pub fn content(&self) -> Vec<String> {
-        // Convert the Jagged<char> structure back to Vec<String>
+        Self::lines_of(&self.state)
+    }
+    
+    /// Convert the editor state's Jagged<char> structure back to lines
+    fn lines_of(state: &EditorState) -> Vec<String> {
         let mut result = Vec::new();
-        let num_rows = self.state.lines.len();
+        let num_rows = state.lines.len();
         
         for i in 0..num_rows {
             // Use RowIndex to access rows in the Jagged structure
-            if let Some(row) = self.state.lines.get(RowIndex::new(i)) {
+            if let Some(row) = state.lines.get(RowIndex::new(i)) {
                 // Specify that we're collecting characters into a String
                 let line: String = row.iter().collect::<String>();
                 result.push(line);
@@ -196,20 +273,20 @@ impl Editor {
         match key.code {
             // For Ctrl+S, handle at application level
             KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                return false;
+                false
             }
             
             // For ? (help key), handle at application level ONLY in normal mode
             KeyCode::Char('?') if !key.modifiers.contains(KeyModifiers::CONTROL) && 
                                  self.state.mode == EditorMode::Normal => {
-                return false;
+                false
             },
             
             // For Q (quick exit), handle at application level when in Normal mode
             KeyCode::Char('q') | KeyCode::Char('Q') if 
                 self.state.mode == EditorMode::Normal && 
                 !key.modifiers.contains(KeyModifiers::CONTROL) => {
-                return false;
+                false
             },
             
             // Handle colon key (enter command mode) in Normal mode
@@ -217,7 +294,7 @@ impl Editor {
                 self.command_mode = true;
                 self.command_buffer.clear();
                 self.command_buffer.push(':');
-                return true;
+                true
             },
             
             // Handle Escape key specially
@@ -225,14 +302,14 @@ impl Editor {
                 // Let EdTUI handle Esc for mode changes
                 if matches!(self.state.mode, EditorMode::Insert | EditorMode::Visual) {
                     self.event_handler.on_key_event(key, &mut self.state);
-                    return true;
+                    true
                 } else if self.state.mode == EditorMode::Normal {
                     // In normal mode, let the app handle it
-                    return false;
+                    false
                 } else {
                     // For any other modes, handle here
                     self.event_handler.on_key_event(key, &mut self.state);
-                    return true;
+                    true
                 }
             }
             
@@ -244,13 +321,15 @@ impl Editor {
                 // Let EdTUI handle the key event
                 self.event_handler.on_key_event(key, &mut self.state);
                 
-                // Check if content has changed
+                // Check if content has changed, recounting only the lines that did
                 let content_after = self.content();
                 if content_before != content_after {
                     self.modified = true;
+                    self.line_tokens.update(&content_before, &content_after);
+                    self.content_version += 1;
                 }
                 
-                return true;
+                true
             }
         }
     }
@@ -274,13 +353,13 @@ impl Editor {
                     return true;
                 }
                 // Signal app to exit the editor
-                return false;
+                false
             },
             
             // :q! - Force quit without saving
             "q!" => {
                 // Signal app to exit the editor
-                return false;
+                false
             },
             
             // :w - Write (mark as saved)
@@ -288,13 +367,13 @@ impl Editor {
                 // This would normally save the file, but we're not directly writing files
                 // Instead, we just mark the content as no longer modified
                 self.modified = false;
-                return true;
+                true
             },
             
             // :wq or :x - Write and quit
             "wq" | "x" => {
                 // Signal app to save and exit
-                return false;
+                false
             },
             
             // :set - Set options (supporting a subset of Vim's :set commands)
@@ -321,43 +400,43 @@ impl Editor {
                         }
                     }
                 }
-                return true;
+                true
             },
             
             // :e - Edit file (not supported in our implementation)
             "e" | "edit" => {
                 // We don't support file operations, but handle the command gracefully
-                return true;
+                true
             },
             
             // :split, :vsplit - Split window (not supported)
             "sp" | "split" | "vs" | "vsplit" => {
                 // We don't support splits, but handle gracefully
-                return true;
+                true
             },
             
             // :h, :help - Show help (would show help in a real Vim)
             "h" | "help" => {
                 // We'd show help if implemented
-                return true;
+                true
             },
             
             // :syntax - Syntax highlighting (not fully implemented)
             "syntax" => {
                 // We would handle syntax highlighting settings here
-                return true;
+                true
             },
             
             // :%s - Substitution (not implemented but commonly used)
             "s" | "%s" => {
                 // We'd implement substitutions here
-                return true;
+                true
             },
             
             // Unknown command - would normally show an error in Vim
             _ => {
                 // For now, just ignore unknown commands
-                return true;
+                true
             }
         }
     }
@@ -1,8 +1,8 @@
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use walkdir::WalkDir;
-use crate::storage::ChunkStorage;
+use crate::storage::{coverage_percentage, line_lengths, ChunkStorage};
 
 /// Representation of a directory entry
 #[derive(Clone)]
@@ -29,6 +29,10 @@ pub struct Explorer {
     selected_index: usize,
     /// Cache of chunking progress by file path
     chunking_progress: HashMap<PathBuf, f64>,
+    /// Only show files with chunks carrying one of these labels (all files if empty)
+    label_filter: Vec<String>,
+    /// Absolute paths of the files that pass the label filter
+    labelled_files: HashSet<PathBuf>,
 }
 
 impl Explorer {
@@ -46,6 +50,8 @@ impl Explorer {
             entries: Vec::new(),
             selected_index: 0,
             chunking_progress: HashMap::new(),
+            label_filter: Vec::new(),
+            labelled_files: HashSet::new(),
         };
         
         // Load initial entries
@@ -54,65 +60,35 @@ impl Explorer {
         Ok(explorer)
     }
     
-    /// Initialize chunking progress data from CSV storage
+    /// Initialize chunking progress data from chunk storage
     /// 
     /// Note: Chunk storage uses 1-indexed line numbers, but we need to convert
     /// to 0-indexed when tracking which lines are chunked in our internal arrays.
     pub fn init_chunking_progress(&mut self, chunk_storage: &ChunkStorage) -> Result<()> {
-        // Get all chunks from storage
-        let chunks = chunk_storage.get_chunks();
+        // Get the chunked spans of every file from storage
+        let files_to_process = chunk_storage.get_all_chunk_spans()?;
         
         // If there are no chunks, nothing to do
-        if chunks.is_empty() {
+        if files_to_process.is_empty() {
             return Ok(());
         }
         
-        // Process each file path in the chunks and build a map of file paths to lines
-        let mut files_to_process: HashMap<PathBuf, Vec<(usize, usize)>> = HashMap::new();
-        
-        for chunk in chunks {
-            // Get the file path and range
-            let file_path = chunk.file_path.clone();
-            let start_line = chunk.start_line;
-            let end_line = chunk.end_line;
-            
-            // Add this range to the file's chunks
-            files_to_process
-                .entry(file_path)
-                .or_insert_with(Vec::new)
-                .push((start_line, end_line));
-        }
-        
         // Calculate the chunking progress for each file
-        for (file_path, ranges) in files_to_process.iter() {
-            // Read the file to count lines
-            if let Ok(content) = std::fs::read_to_string(&file_path) {
-                let total_lines = content.lines().count();
+        for (file_path, spans) in files_to_process.iter() {
+            // Read the file to measure its lines
+            if let Ok(content) = std::fs::read_to_string(file_path) {
+                let line_lengths = line_lengths(content.lines());
                 
-                if total_lines > 0 {
-                    // Count unique chunked lines using a boolean vector
-                    let mut chunked_lines = vec![false; total_lines];
-                    
-                    for &(start, end) in ranges {
-                        // Convert from 1-indexed (storage) to 0-indexed (for the boolean array)
-                        let start_idx = start.saturating_sub(1);
-                        let end_idx = end.saturating_sub(1).min(total_lines - 1);
-                        
-                        for i in start_idx..=end_idx {
-                            chunked_lines[i] = true;
-                        }
-                    }
-                    
-                    // Calculate percentage
-                    let chunked_count = chunked_lines.iter().filter(|&&chunked| chunked).count();
-                    let percentage = (chunked_count as f64 / total_lines as f64) * 100.0;
+                if !line_lengths.is_empty() {
+                    // Partly chunked lines count by the share of characters covered
+                    let percentage = coverage_percentage(spans, &line_lengths);
                     
                     // Construct absolute path for matching with file system entries
                     let absolute_path = if file_path.is_absolute() {
                         file_path.clone()
                     } else {
                         // Join with the root directory to get absolute path
-                        self.root_dir.join(&file_path)
+                        self.root_dir.join(file_path)
                     };
                     
                     // Update the chunking progress with absolute path for correct matching
@@ -127,8 +103,62 @@ impl Explorer {
         Ok(())
     }
     
+    /// Recalculate the chunking progress of a single file from chunk storage
+    /// 
+    /// `file_path` is relative to the root directory, as stored in chunks.
+    pub fn refresh_chunking_progress(&mut self, chunk_storage: &ChunkStorage, file_path: &Path) -> Result<()> {
+        let absolute_path = self.root_dir.join(file_path);
+        let content = std::fs::read_to_string(&absolute_path)
+            .with_context(|| format!("Failed to read file: {}", absolute_path.display()))?;
+        
+        let percentage = chunk_storage.calculate_chunking_percentage(file_path, &line_lengths(content.lines()))?;
+        self.update_chunking_progress(&absolute_path, percentage);
+        
+        Ok(())
+    }
+    
+    /// Only show files that have a chunk with one of the given labels
+    /// 
+    /// Directories are shown if they contain such a file. An empty list
+    /// removes the filter.
+    pub fn set_label_filter(&mut self, labels: Vec<String>, chunk_storage: &ChunkStorage) -> Result<()> {
+        self.labelled_files = chunk_storage
+            .files_with_labels(&labels)?
+            .into_iter()
+            .map(|file_path| self.root_dir.join(file_path))
+            .collect();
+        self.label_filter = labels;
+        
+        self.load_entries()
+    }
+    
+    /// Recompute the filtered files after chunk labels changed
+    pub fn refresh_label_filter(&mut self, chunk_storage: &ChunkStorage) -> Result<()> {
+        if self.label_filter.is_empty() {
+            return Ok(());
+        }
+        self.set_label_filter(self.label_filter.clone(), chunk_storage)
+    }
+    
+    /// Get the labels the explorer is filtered by
+    pub fn label_filter(&self) -> &[String] {
+        &self.label_filter
+    }
+    
+    /// Whether an entry passes the label filter
+    fn passes_label_filter(&self, path: &Path, is_dir: bool) -> bool {
+        if self.label_filter.is_empty() {
+            return true;
+        }
+        if is_dir {
+            self.labelled_files.iter().any(|file| file.starts_with(path))
+        } else {
+            self.labelled_files.contains(path)
+        }
+    }
+    
     // The previous calculate_chunking_progress_for_pattern method is no longer needed
-    // as we now directly process chunks from the CSV storage
+    // as we now directly process chunks from chunk storage
     
     /// Reload entries in the current directory
     fn load_entries(&mut self) -> Result<()> {
@@ -159,6 +189,11 @@ impl Explorer {
                 .to_string();
             let is_dir = entry.file_type().is_dir();
             
+            // Hide entries without chunks carrying the filtered labels
+            if !self.passes_label_filter(&path, is_dir) {
+                continue;
+            }
+            
             // Get chunking progress if we have it cached
             let chunking_progress = if !is_dir {
                 *self.chunking_progress.get(&path).unwrap_or(&0.0)
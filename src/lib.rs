@@ -1,4 +1,5 @@
 // Export the modules that tests will need
+pub mod chunker;
 pub mod explorer;
 pub mod config;
 pub mod editor;
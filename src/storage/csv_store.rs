@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::schema::{self, SCHEMA_VERSION};
+use super::{write_atomically, Chunk, ChunkStore};
+
+/// Start of the comment line that records the schema version of a CSV chunk file
+const VERSION_PREFIX: &str = "# packrat chunk schema version ";
+
+/// Chunk store backed by a single CSV file
+/// 
+/// New chunks are appended to the end of the file. Updates and deletes
+/// rewrite the whole file from the in-memory copy. The first line is a
+/// comment recording the schema version, followed by the header row.
+pub struct CsvChunkStore {
+    /// Path to the CSV file
+    csv_path: PathBuf,
+    
+    /// In-memory cache of chunks
+    chunks: Vec<Chunk>,
+    
+    /// Notes about upgrades made while opening the file
+    warnings: Vec<String>,
+}
+
+impl CsvChunkStore {
+    /// Open a CSV chunk store, creating parent directories if needed
+    /// 
+    /// Files from an older schema version are backed up and migrated in
+    /// place; files from a newer version are refused.
+    pub fn open<P: AsRef<Path>>(csv_path: P) -> Result<Self> {
+        let csv_path = csv_path.as_ref().to_path_buf();
+        let mut warnings = Vec::new();
+        
+        let is_empty = !csv_path.exists() || fs::metadata(&csv_path)?.len() == 0;
+        if is_empty {
+            // Create parent directories if they don't exist
+            if let Some(parent) = csv_path.parent() {
+                fs::create_dir_all(parent).context("Failed to create parent directories for CSV file")?;
+            }
+        } else {
+            let (version, recorded) = Self::file_version(&csv_path)?;
+            schema::ensure_supported(&csv_path, version)?;
+            
+            if version < SCHEMA_VERSION {
+                let backup_path = schema::backup(&csv_path, version)?;
+                Self::migrate(&csv_path, version)
+                    .with_context(|| format!("Failed to upgrade chunk file {}", csv_path.display()))?;
+                warnings.push(schema::upgrade_message(&csv_path, version, &backup_path));
+            } else if !recorded {
+                // Up to date apart from the version line itself
+                let store = Self { chunks: Self::load_chunks(&csv_path)?, csv_path, warnings };
+                store.save().context("Failed to add schema version to CSV chunk file")?;
+                return Ok(store);
+            }
+        }
+        
+        Ok(Self {
+            chunks: Self::load_chunks(&csv_path)?,
+            csv_path,
+            warnings,
+        })
+    }
+    
+    /// Save all chunks to the CSV file
+    /// 
+    /// The file is replaced atomically, so a crash mid-write keeps the old contents.
+    pub fn save(&self) -> Result<()> {
+        write_atomically(&self.csv_path, |writer| {
+            writeln!(writer, "{}{}", VERSION_PREFIX, SCHEMA_VERSION)?;
+            
+            // Create a CSV writer with custom options for better quoting
+            let mut csv_writer = Self::writer_builder(true).from_writer(writer);
+            
+            // Write each chunk to CSV
+            for chunk in &self.chunks {
+                csv_writer.serialize(chunk)?;
+            }
+            
+            // Flush writer
+            csv_writer.flush()?;
+            
+            Ok(())
+        })
+    }
+    
+    /// Append a single chunk to the end of the CSV file
+    fn append(&self, chunk: &Chunk) -> Result<()> {
+        // Without any rows the file has no header row yet (at most a version
+        // line), so start it over with version and header rows
+        let needs_header = self.chunks.is_empty();
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(!needs_header)
+            .truncate(needs_header)
+            .open(&self.csv_path)
+            .with_context(|| format!("Failed to open chunk file: {}", self.csv_path.display()))?;
+        
+        let mut writer = BufWriter::new(file);
+        if needs_header {
+            writeln!(writer, "{}{}", VERSION_PREFIX, SCHEMA_VERSION)?;
+        }
+        let mut csv_writer = Self::writer_builder(needs_header).from_writer(writer);
+        
+        csv_writer.serialize(chunk)?;
+        csv_writer.flush()?;
+        
+        Ok(())
+    }
+    
+    /// CSV writer options shared by full rewrites and appends
+    fn writer_builder(has_headers: bool) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .quote_style(csv::QuoteStyle::Always)  // Always quote all fields
+            .double_quote(true)                    // Ensure quotes inside fields are escaped properly
+            .has_headers(has_headers);
+        builder
+    }
+    
+    /// CSV reader options shared by loading and migrating
+    fn reader_builder() -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .flexible(true)              // Be more lenient with parsing
+            .double_quote(true)          // Handle double-quoted quotes
+            .comment(Some(b'#'));        // Skip the schema version line
+        builder
+    }
+    
+    /// Read the schema version of an existing file
+    /// 
+    /// Returns the version and whether it was recorded in the file. Files
+    /// written before versions were recorded are dated by their columns.
+    fn file_version(csv_path: &Path) -> Result<(u32, bool)> {
+        let mut first_line = String::new();
+        BufReader::new(File::open(csv_path)?).read_line(&mut first_line)?;
+        
+        if let Some(version) = first_line.trim_end().strip_prefix(VERSION_PREFIX) {
+            let version = version.parse()
+                .with_context(|| format!("Invalid schema version line in {}", csv_path.display()))?;
+            return Ok((version, true));
+        }
+        
+        let mut csv_reader = Self::reader_builder().from_reader(BufReader::new(File::open(csv_path)?));
+        let headers = csv_reader.headers()?.clone();
+        Ok((schema::infer_version(|field| headers.iter().any(|header| header == field)), false))
+    }
+    
+    /// Rewrite a file from an older schema version with the current columns
+    fn migrate(csv_path: &Path, version: u32) -> Result<()> {
+        let mut csv_reader = Self::reader_builder().from_reader(BufReader::new(File::open(csv_path)?));
+        let mut headers = csv_reader.headers()?.clone();
+        let mut records = csv_reader.records().collect::<csv::Result<Vec<_>>>()?;
+        
+        for migration in schema::migrations_from(version) {
+            for field in migration.added_fields {
+                if headers.iter().any(|header| header == field.name) {
+                    continue;
+                }
+                headers.push_field(field.name);
+                for record in &mut records {
+                    record.push_field(field.default.as_csv());
+                }
+            }
+            
+            for field in migration.rewritten_fields {
+                let Some(index) = headers.iter().position(|header| header == field.name) else {
+                    continue;
+                };
+                for record in &mut records {
+                    *record = record
+                        .iter()
+                        .enumerate()
+                        .map(|(i, value)| if i == index { (field.rewrite)(value) } else { value.to_string() })
+                        .collect();
+                }
+            }
+        }
+        
+        write_atomically(csv_path, |writer| {
+            writeln!(writer, "{}{}", VERSION_PREFIX, SCHEMA_VERSION)?;
+            let mut csv_writer = Self::writer_builder(false).from_writer(writer);
+            csv_writer.write_record(&headers)?;
+            for record in &records {
+                csv_writer.write_record(record)?;
+            }
+            csv_writer.flush()?;
+            Ok(())
+        })
+    }
+    
+    /// Load chunks from CSV file
+    fn load_chunks(csv_path: &Path) -> Result<Vec<Chunk>> {
+        // If file doesn't exist, return empty vector
+        if !csv_path.exists() {
+            return Ok(Vec::new());
+        }
+        
+        // Open file with BufReader for better performance
+        let reader = BufReader::new(File::open(csv_path)?);
+        
+        // Create a CSV reader with custom options to match our writer
+        let mut csv_reader = Self::reader_builder().from_reader(reader);
+        
+        // Parse CSV into Chunk records
+        let mut chunks = Vec::new();
+        for result in csv_reader.deserialize() {
+            let chunk: Chunk = result?;
+            chunks.push(chunk);
+        }
+        
+        Ok(chunks)
+    }
+}
+
+impl ChunkStore for CsvChunkStore {
+    fn add(&mut self, chunk: Chunk) -> Result<()> {
+        self.append(&chunk)?;
+        self.chunks.push(chunk);
+        Ok(())
+    }
+    
+    fn update(&mut self, chunk: Chunk) -> Result<()> {
+        let existing = self.chunks
+            .iter_mut()
+            .find(|existing| existing.id == chunk.id)
+            .ok_or_else(|| anyhow!("No chunk with id {}", chunk.id))?;
+        *existing = chunk;
+        self.save()
+    }
+    
+    fn delete(&mut self, id: &str) -> Result<Option<Chunk>> {
+        let Some(index) = self.chunks.iter().position(|chunk| chunk.id == id) else {
+            return Ok(None);
+        };
+        let removed = self.chunks.remove(index);
+        self.save()?;
+        Ok(Some(removed))
+    }
+    
+    fn get(&self, id: &str) -> Result<Option<Chunk>> {
+        Ok(self.chunks.iter().find(|chunk| chunk.id == id).cloned())
+    }
+    
+    fn chunks_for_file(&self, file_path: &Path) -> Result<Vec<Chunk>> {
+        Ok(self.chunks
+            .iter()
+            .filter(|chunk| chunk.file_path == file_path)
+            .cloned()
+            .collect())
+    }
+    
+    fn for_each_chunk(&self, f: &mut dyn FnMut(&Chunk)) -> Result<()> {
+        self.chunks.iter().for_each(f);
+        Ok(())
+    }
+    
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
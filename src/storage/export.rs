@@ -0,0 +1,196 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{encode_labels, write_atomically, Chunk, ChunkStorage, Metadata};
+use crate::utils::prompt::{PromptContext, PromptTemplate};
+
+/// Fields every exported chunk has, in output order
+/// 
+/// Metadata keys are added after these, so they can't reuse these names.
+pub const EXPORT_FIELDS: &[&str] = &[
+    "id", "file_path", "start_line", "end_line", "start_column", "end_column",
+    "labels", "edited", "timestamp", "window_id", "source_hash", "lines_hash", "content",
+];
+
+/// Field holding a chunk rendered in the prompt template, when exports include it
+pub const PROMPT_FIELD: &str = "prompt";
+
+/// File formats chunks can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line, with labels as an array and a field per metadata key
+    Jsonl,
+    /// A CSV file with a header row, with labels as a JSON array string and a column per metadata key
+    Csv,
+}
+
+impl ExportFormat {
+    /// Pick the format from an output file's extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl") | Some("json") => Ok(ExportFormat::Jsonl),
+            Some("csv") => Ok(ExportFormat::Csv),
+            _ => bail!("Can't tell the export format of {} - use a .jsonl or .csv file", path.display()),
+        }
+    }
+}
+
+/// Which chunks to export, and how
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// Only export chunks with at least one of these labels (all chunks if empty)
+    pub labels: Vec<String>,
+    
+    /// Also export each chunk rendered in this template, in a `prompt` field after `content`
+    pub prompt_template: Option<PromptTemplate>,
+}
+
+impl ExportOptions {
+    /// Check whether a chunk passes the filters
+    pub fn matches(&self, chunk: &Chunk) -> bool {
+        self.labels.is_empty() || chunk.labels.iter().any(|label| self.labels.contains(label))
+    }
+}
+
+/// A chunk as written to a JSONL export, with its metadata flattened into it
+#[derive(Serialize)]
+struct ExportedChunk<'a> {
+    id: &'a str,
+    file_path: &'a Path,
+    start_line: usize,
+    end_line: usize,
+    start_column: Option<usize>,
+    end_column: Option<usize>,
+    labels: &'a [String],
+    edited: bool,
+    timestamp: u64,
+    window_id: &'a str,
+    source_hash: &'a str,
+    lines_hash: &'a str,
+    content: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prompt: Option<String>,
+    #[serde(flatten)]
+    metadata: &'a Metadata,
+}
+
+/// Render a chunk in a prompt template
+fn render_prompt(template: &PromptTemplate, chunk: &Chunk) -> String {
+    let context = PromptContext {
+        file_path: chunk.file_path.to_string_lossy().to_string(),
+        start_line: chunk.start_line,
+        end_line: chunk.end_line,
+    };
+    template.render(&context, &chunk.content)
+}
+
+impl<'a> ExportedChunk<'a> {
+    /// Describe a chunk for export
+    fn new(chunk: &'a Chunk, template: Option<&PromptTemplate>) -> Self {
+        Self {
+            id: &chunk.id,
+            file_path: &chunk.file_path,
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            start_column: chunk.start_column,
+            end_column: chunk.end_column,
+            labels: &chunk.labels,
+            edited: chunk.edited,
+            timestamp: chunk.timestamp,
+            window_id: &chunk.window_id,
+            source_hash: &chunk.source_hash,
+            lines_hash: &chunk.lines_hash,
+            content: &chunk.content,
+            prompt: template.map(|template| render_prompt(template, chunk)),
+            metadata: &chunk.metadata,
+        }
+    }
+}
+
+/// A chunk as a CSV row: the `EXPORT_FIELDS`, the prompt if there is a template,
+/// then one cell per metadata key
+fn csv_record(chunk: &Chunk, template: Option<&PromptTemplate>, metadata_keys: &[&str]) -> Vec<String> {
+    let column = |column: Option<usize>| column.map(|column| column.to_string()).unwrap_or_default();
+    let mut record = vec![
+        chunk.id.clone(),
+        chunk.file_path.to_string_lossy().to_string(),
+        chunk.start_line.to_string(),
+        chunk.end_line.to_string(),
+        column(chunk.start_column),
+        column(chunk.end_column),
+        encode_labels(&chunk.labels),
+        chunk.edited.to_string(),
+        chunk.timestamp.to_string(),
+        chunk.window_id.clone(),
+        chunk.source_hash.clone(),
+        chunk.lines_hash.clone(),
+        chunk.content.clone(),
+    ];
+    record.extend(template.map(|template| render_prompt(template, chunk)));
+    record.extend(metadata_keys.iter().map(|key| {
+        chunk.metadata.get(*key).map(|value| value.to_string()).unwrap_or_default()
+    }));
+    record
+}
+
+/// Export the chunks that match `options` to a file, returning how many were written
+/// 
+/// The file is replaced atomically. Chunks are written in storage order.
+/// CSV exports get a column for every metadata key used by an exported
+/// chunk, left empty for chunks without it. With a prompt template, every
+/// chunk also gets a `prompt` field holding the text sent to the model.
+pub fn export_chunks(
+    storage: &ChunkStorage,
+    path: &Path,
+    format: ExportFormat,
+    options: &ExportOptions,
+) -> Result<usize> {
+    let template = options.prompt_template.as_ref();
+    let chunks: Vec<Chunk> = storage
+        .get_chunks()?
+        .into_iter()
+        .filter(|chunk| options.matches(chunk))
+        .collect();
+    
+    write_atomically(path, |writer| {
+        match format {
+            ExportFormat::Jsonl => {
+                for chunk in &chunks {
+                    serde_json::to_writer(&mut *writer, &ExportedChunk::new(chunk, template))?;
+                    writer.write_all(b"\n")?;
+                }
+            },
+            ExportFormat::Csv => {
+                let metadata_keys: Vec<&str> = chunks
+                    .iter()
+                    .flat_map(|chunk| chunk.metadata.keys().map(String::as_str))
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect();
+                
+                let mut csv_writer = csv::Writer::from_writer(writer);
+                let prompt_field = template.map(|_| PROMPT_FIELD);
+                csv_writer.write_record(EXPORT_FIELDS.iter().chain(&prompt_field).chain(&metadata_keys))?;
+                for chunk in &chunks {
+                    csv_writer.write_record(csv_record(chunk, template, &metadata_keys))?;
+                }
+                csv_writer.flush()?;
+            },
+        }
+        Ok(())
+    })?;
+    
+    Ok(chunks.len())
+}
+
+/// Default export path next to the chunk file, e.g. `chunks.export.jsonl`
+pub fn default_export_path(chunk_file: &Path) -> PathBuf {
+    let stem = chunk_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "chunks".to_string());
+    chunk_file.with_file_name(format!("{}.export.jsonl", stem))
+}
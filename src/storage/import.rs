@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::export::{ExportFormat, PROMPT_FIELD};
+use super::{decode_labels, Chunk, ChunkStorage, Metadata};
+
+/// What an import added to storage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Chunks added
+    pub imported: usize,
+    
+    /// Chunks left out because storage already has a chunk with the same id
+    pub skipped: usize,
+}
+
+/// A chunk as read from an export, with every unknown field taken as metadata
+#[derive(Deserialize)]
+struct ImportedChunk {
+    #[serde(default)]
+    id: String,
+    file_path: PathBuf,
+    start_line: usize,
+    end_line: usize,
+    #[serde(default)]
+    start_column: Option<usize>,
+    #[serde(default)]
+    end_column: Option<usize>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(default)]
+    edited: bool,
+    #[serde(default)]
+    timestamp: u64,
+    #[serde(default)]
+    window_id: String,
+    #[serde(default)]
+    source_hash: String,
+    #[serde(default)]
+    lines_hash: String,
+    content: String,
+    #[serde(flatten)]
+    metadata: Metadata,
+}
+
+impl ImportedChunk {
+    /// Turn the imported fields into a chunk, keeping its id if it has one
+    fn into_chunk(self) -> Chunk {
+        let mut chunk = Chunk::new(self.file_path, self.start_line, self.end_line, self.content, self.edited)
+            .with_columns(self.start_column, self.end_column);
+        if !self.id.is_empty() {
+            chunk.id = self.id;
+        }
+        if self.timestamp > 0 {
+            chunk.timestamp = self.timestamp;
+        }
+        chunk.labels = self.labels;
+        chunk.window_id = self.window_id;
+        chunk.source_hash = self.source_hash;
+        chunk.lines_hash = self.lines_hash;
+        chunk.metadata = self.metadata;
+        // The rendered prompt is derived from the chunk, not part of it
+        chunk.metadata.remove(PROMPT_FIELD);
+        chunk
+    }
+}
+
+/// Import the chunks of a file written by `export_chunks` into storage
+/// 
+/// Chunks keep their ids, so importing the same export twice adds nothing
+/// the second time; chunks without an id get a new one. Chunks keep their
+/// sliding-window pass and the fingerprint of their source, so windows still
+/// overlap on purpose and changed sources are still flagged as stale (exports
+/// from before these fields were added have neither).
+/// CSV metadata cells don't say what type they are, so they're read back
+/// as booleans or numbers when they look like one and as text otherwise.
+/// Everything is added as one batch, which a single undo removes.
+pub fn import_chunks(storage: &mut ChunkStorage, path: &Path, format: ExportFormat) -> Result<ImportReport> {
+    let imported = match format {
+        ExportFormat::Jsonl => read_jsonl(path)?,
+        ExportFormat::Csv => read_csv(path)?,
+    };
+    
+    let mut known: HashSet<String> = storage.get_chunks()?.into_iter().map(|chunk| chunk.id).collect();
+    let mut report = ImportReport::default();
+    let mut chunks = Vec::new();
+    for chunk in imported.into_iter().map(ImportedChunk::into_chunk) {
+        if known.insert(chunk.id.clone()) {
+            chunks.push(chunk);
+        } else {
+            report.skipped += 1;
+        }
+    }
+    
+    report.imported = chunks.len();
+    storage.add_chunks(chunks)?;
+    Ok(report)
+}
+
+/// Read a JSONL export, one chunk per non-empty line
+fn read_jsonl(path: &Path) -> Result<Vec<ImportedChunk>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut chunks = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let chunk = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid chunk on line {} of {}", index + 1, path.display()))?;
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// Read a CSV export, turning each row into the fields a JSONL export would have
+fn read_csv(path: &Path) -> Result<Vec<ImportedChunk>> {
+    let mut reader = csv::Reader::from_path(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let headers = reader.headers().with_context(|| format!("Failed to read the header of {}", path.display()))?.clone();
+    
+    let mut chunks = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("Failed to read {}", path.display()))?;
+        let fields: Map<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .filter_map(|(field, cell)| Some((field.to_string(), csv_value(field, cell)?)))
+            .collect();
+        // Row 1 is the header
+        let chunk = serde_json::from_value(Value::Object(fields))
+            .with_context(|| format!("Invalid chunk on row {} of {}", index + 2, path.display()))?;
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// The JSON value of a CSV cell, or `None` for a cell left empty
+fn csv_value(field: &str, cell: &str) -> Option<Value> {
+    match field {
+        "id" | "file_path" | "content" | "window_id" | "source_hash" | "lines_hash" => Some(Value::from(cell)),
+        "labels" => Some(Value::from(decode_labels(cell))),
+        _ if cell.is_empty() => None,
+        _ => Some(typed_value(cell)),
+    }
+}
+
+/// A boolean or number if the text is one, otherwise the text itself
+fn typed_value(text: &str) -> Value {
+    if let Ok(value) = text.parse::<bool>() {
+        Value::from(value)
+    } else if let Ok(value) = text.parse::<i64>() {
+        Value::from(value)
+    } else if let Some(value) = text.parse::<f64>().ok().filter(|value| value.is_finite()) {
+        Value::from(value)
+    } else {
+        Value::from(text)
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::{write_atomically, Chunk, ChunkStore};
+
+/// Maximum number of operations kept on each of the undo and redo stacks
+const MAX_JOURNAL_DEPTH: usize = 200;
+
+/// Compact the journal once it holds this many records per operation it keeps
+const COMPACTION_RATIO: usize = 2;
+
+/// A reversible change made to chunk storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// One or more chunks were saved
+    Create { chunks: Vec<Chunk> },
+    /// A chunk was deleted
+    Delete { chunk: Box<Chunk> },
+    /// A chunk's content, range or labels were changed
+    Edit { before: Box<Chunk>, after: Box<Chunk> },
+    /// Several chunks were changed together (e.g. re-anchored after a source edit)
+    EditMany { before: Vec<Chunk>, after: Vec<Chunk> },
+}
+
+impl Operation {
+    /// Short human-readable description for status messages
+    pub fn describe(&self) -> String {
+        match self {
+            Operation::Create { chunks } if chunks.len() == 1 => format!("create of chunk {}", chunks[0].id),
+            Operation::Create { chunks } => format!("create of {} chunks", chunks.len()),
+            Operation::Delete { chunk } => format!("delete of chunk {}", chunk.id),
+            Operation::Edit { after, .. } => format!("edit of chunk {}", after.id),
+            Operation::EditMany { after, .. } => format!("edit of {} chunks", after.len()),
+        }
+    }
+    
+    /// Files whose chunks are touched by this operation (relative to root)
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = match self {
+            Operation::Create { chunks } => chunks.iter().map(|chunk| chunk.file_path.clone()).collect(),
+            Operation::Delete { chunk } => vec![chunk.file_path.clone()],
+            Operation::Edit { before, after } => vec![before.file_path.clone(), after.file_path.clone()],
+            Operation::EditMany { before, after } => before
+                .iter()
+                .chain(after)
+                .map(|chunk| chunk.file_path.clone())
+                .collect(),
+        };
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+    
+    /// Apply the operation to a store
+    fn apply(&self, store: &mut dyn ChunkStore) -> Result<()> {
+        match self {
+            Operation::Create { chunks } => store.add_many(chunks.clone()),
+            Operation::Delete { chunk } => store.delete(&chunk.id).map(|_| ()),
+            Operation::Edit { after, .. } => store.update(after.as_ref().clone()),
+            Operation::EditMany { after, .. } => store.update_many(after.clone()),
+        }
+    }
+    
+    /// Reverse the effect of the operation on a store
+    fn revert(&self, store: &mut dyn ChunkStore) -> Result<()> {
+        match self {
+            Operation::Create { chunks } => {
+                for chunk in chunks {
+                    store.delete(&chunk.id)?;
+                }
+                Ok(())
+            },
+            Operation::Delete { chunk } => store.add(chunk.as_ref().clone()),
+            Operation::Edit { before, .. } => store.update(before.as_ref().clone()),
+            Operation::EditMany { before, .. } => store.update_many(before.clone()),
+        }
+    }
+}
+
+/// A single entry in the append-only journal
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum JournalRecord {
+    /// A newly performed operation
+    Record { operation: Operation },
+    /// The most recent operation was undone
+    Undo,
+    /// The most recently undone operation was redone
+    Redo,
+}
+
+/// Undo and redo stacks, replayed from the journal
+/// 
+/// Journals written before the journal was append-only hold this as a
+/// single JSON object.
+#[derive(Default, Serialize, Deserialize)]
+struct JournalState {
+    undo: Vec<Operation>,
+    redo: Vec<Operation>,
+}
+
+impl JournalState {
+    /// Apply a journal record to the stacks
+    /// 
+    /// The stacks aren't trimmed here: a compacted journal records every
+    /// operation that can be redone before undoing them again, so the undo
+    /// stack briefly holds more than `MAX_JOURNAL_DEPTH` while replaying.
+    fn apply(&mut self, record: JournalRecord) {
+        match record {
+            JournalRecord::Record { operation } => {
+                self.undo.push(operation);
+                self.redo.clear();
+            },
+            JournalRecord::Undo => {
+                if let Some(operation) = self.undo.pop() {
+                    self.redo.push(operation);
+                }
+            },
+            JournalRecord::Redo => {
+                if let Some(operation) = self.redo.pop() {
+                    self.undo.push(operation);
+                }
+            },
+        }
+    }
+    
+    /// Drop the oldest entries of stacks grown past the maximum depth
+    fn trim(&mut self) {
+        for stack in [&mut self.undo, &mut self.redo] {
+            if stack.len() > MAX_JOURNAL_DEPTH {
+                stack.drain(..stack.len() - MAX_JOURNAL_DEPTH);
+            }
+        }
+    }
+    
+    /// Number of operations on both stacks
+    fn len(&self) -> usize {
+        self.undo.len() + self.redo.len()
+    }
+}
+
+/// Persistent undo/redo history of chunk operations
+/// 
+/// The journal lives next to the chunk file (e.g. `chunks.csv.journal`) as
+/// a JSON Lines log: each operation, undo and redo appends one record, so
+/// recording an operation costs the size of that operation, not of the
+/// whole history. Once the log holds more than `COMPACTION_RATIO` records
+/// per operation kept, it's rewritten atomically with just the stacks.
+pub struct Journal {
+    /// Path to the journal file
+    path: PathBuf,
+    
+    /// Current undo and redo stacks
+    state: JournalState,
+    
+    /// Number of records currently in the journal file
+    record_count: usize,
+}
+
+impl Journal {
+    /// Journal path used for a given chunk file
+    pub fn path_for(chunk_file: &Path) -> PathBuf {
+        let mut file_name = chunk_file
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "chunks".into());
+        file_name.push(".journal");
+        chunk_file.with_file_name(file_name)
+    }
+    
+    /// Load the journal for a chunk file
+    /// 
+    /// An unreadable journal is replaced with an empty one; the returned
+    /// warning explains why history was lost. A record cut off mid-write
+    /// (at the end of the journal) is dropped with a warning.
+    pub fn open(chunk_file: &Path) -> (Self, Option<String>) {
+        let path = Self::path_for(chunk_file);
+        let mut journal = Self { path, state: JournalState::default(), record_count: 0 };
+        
+        let Ok(text) = fs::read_to_string(&journal.path) else {
+            return (journal, None);
+        };
+        
+        // Journals from before it was append-only hold both stacks in one object
+        let (mut warning, rewrite) = if let Ok(state) = serde_json::from_str::<JournalState>(&text) {
+            journal.state = state;
+            (None, true)
+        } else {
+            match Self::replay(&text) {
+                Ok((state, record_count, truncated)) => {
+                    journal.state = state;
+                    journal.record_count = record_count;
+                    let warning = truncated
+                        .then(|| format!("Ignored truncated record at the end of undo journal {}", journal.path.display()));
+                    (warning, truncated)
+                },
+                Err(e) => (Some(format!("Ignored unreadable undo journal {}: {:#}", journal.path.display(), e)), true),
+            }
+        };
+        
+        // Rewrite the journal in the current format, so later appends start on a clean line
+        if rewrite {
+            if let Err(e) = journal.compact() {
+                warning = Some(format!("{:#}", e));
+            }
+        }
+        (journal, warning)
+    }
+    
+    /// Replay the records of a journal
+    /// 
+    /// Returns the stacks, the number of records and whether the last
+    /// record was cut off and dropped.
+    fn replay(text: &str) -> Result<(JournalState, usize, bool)> {
+        let mut state = JournalState::default();
+        let mut record_count = 0;
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<JournalRecord>(line.trim_end()) {
+                Ok(record) if line.ends_with('\n') => {
+                    state.apply(record);
+                    record_count += 1;
+                },
+                Err(e) if i + 1 < lines.len() => {
+                    return Err(anyhow::Error::new(e).context(format!("corrupt record at line {}", i + 1)));
+                },
+                // The final record was cut off mid-write
+                _ => {
+                    state.trim();
+                    return Ok((state, record_count, true));
+                },
+            }
+        }
+        
+        state.trim();
+        Ok((state, record_count, false))
+    }
+    
+    /// Record a newly performed operation, discarding anything that could be redone
+    pub fn record(&mut self, operation: Operation) -> Result<()> {
+        let record = JournalRecord::Record { operation };
+        self.append(&record)?;
+        self.state.apply(record);
+        self.state.trim();
+        self.compact_if_oversized();
+        Ok(())
+    }
+    
+    /// Revert the most recent operation, returning it if there was one
+    /// 
+    /// The undo is journaled before the store is touched, so a journal that
+    /// can't be written leaves both the store and the history as they were.
+    pub fn undo(&mut self, store: &mut dyn ChunkStore) -> Result<Option<Operation>> {
+        let Some(operation) = self.state.undo.last().cloned() else {
+            return Ok(None);
+        };
+        
+        self.append(&JournalRecord::Undo).context("Failed to undo chunk operation")?;
+        if let Err(e) = operation.revert(store) {
+            // Keep the history consistent with what is actually stored
+            return Err(self.cancel(JournalRecord::Redo, e.context("Failed to undo chunk operation")));
+        }
+        
+        self.state.apply(JournalRecord::Undo);
+        self.state.trim();
+        self.compact_if_oversized();
+        Ok(Some(operation))
+    }
+    
+    /// Re-apply the most recently undone operation, returning it if there was one
+    /// 
+    /// Like `undo`, the redo is journaled before the store is touched.
+    pub fn redo(&mut self, store: &mut dyn ChunkStore) -> Result<Option<Operation>> {
+        let Some(operation) = self.state.redo.last().cloned() else {
+            return Ok(None);
+        };
+        
+        self.append(&JournalRecord::Redo).context("Failed to redo chunk operation")?;
+        if let Err(e) = operation.apply(store) {
+            return Err(self.cancel(JournalRecord::Undo, e.context("Failed to redo chunk operation")));
+        }
+        
+        self.state.apply(JournalRecord::Redo);
+        self.state.trim();
+        self.compact_if_oversized();
+        Ok(Some(operation))
+    }
+    
+    /// Journal the record reversing an undo or redo the store refused
+    fn cancel(&mut self, record: JournalRecord, error: anyhow::Error) -> anyhow::Error {
+        match self.append(&record) {
+            Ok(()) => error,
+            Err(e) => error.context(format!("The undo journal no longer matches the chunks: {:#}", e)),
+        }
+    }
+    
+    /// Append one record to the journal and sync it to disk
+    fn append(&mut self, record: &JournalRecord) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open undo journal: {}", self.path.display()))?;
+        
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        writer.get_ref().sync_data()
+            .with_context(|| format!("Failed to write undo journal: {}", self.path.display()))?;
+        
+        self.record_count += 1;
+        Ok(())
+    }
+    
+    /// Compact the journal once superseded records outnumber the operations kept
+    /// 
+    /// A compaction that fails leaves the log as it was, which still holds
+    /// the whole history, so it's simply tried again after the next record.
+    fn compact_if_oversized(&mut self) {
+        if self.record_count > COMPACTION_RATIO * self.state.len().max(1) {
+            let _ = self.compact();
+        }
+    }
+    
+    /// Rewrite the journal with one record per operation kept
+    /// 
+    /// Operations that can be redone are recorded after the undo stack, in
+    /// the order they were performed, and followed by one `undo` record each.
+    fn compact(&mut self) -> Result<()> {
+        let operations = self.state.undo.iter().chain(self.state.redo.iter().rev());
+        let undone = self.state.redo.len();
+        write_atomically(&self.path, |writer| {
+            for operation in operations {
+                serde_json::to_writer(&mut *writer, &JournalRecord::Record { operation: operation.clone() })?;
+                writer.write_all(b"\n")?;
+            }
+            for _ in 0..undone {
+                serde_json::to_writer(&mut *writer, &JournalRecord::Undo)?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        })
+        .with_context(|| format!("Failed to write undo journal: {}", self.path.display()))?;
+        
+        self.record_count = self.state.len() + undone;
+        Ok(())
+    }
+}
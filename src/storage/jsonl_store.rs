@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::schema::{self, SCHEMA_VERSION};
+use super::{write_atomically, Chunk, ChunkStore};
+
+/// Compact the log on open once it holds this many records per live chunk
+const COMPACTION_RATIO: usize = 2;
+
+/// A single entry in the append-only chunk log
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum LogRecord {
+    /// The schema version of the log, always the first record
+    Schema { version: u32 },
+    /// A newly saved chunk
+    Add { chunk: Chunk },
+    /// A replacement for an existing chunk with the same id
+    Update { chunk: Chunk },
+    /// Removal of a chunk by id
+    Delete { id: String },
+}
+
+/// Chunk store backed by an append-only JSON Lines log
+/// 
+/// Every change is appended as one JSON record and synced to disk before
+/// returning, so a crash can at most lose the record being written.
+/// `compact` rewrites the log atomically with one record per live chunk.
+/// The log starts with a `schema` record holding its schema version.
+pub struct JsonlChunkStore {
+    /// Path to the JSONL log
+    log_path: PathBuf,
+    
+    /// In-memory state replayed from the log
+    chunks: Vec<Chunk>,
+    
+    /// Number of chunk records currently in the log
+    record_count: usize,
+    
+    /// Whether the log starts with a schema record
+    has_schema_record: bool,
+    
+    /// Problems recovered from while loading the log
+    warnings: Vec<String>,
+}
+
+impl JsonlChunkStore {
+    /// Open a JSONL chunk log, replaying it into memory
+    /// 
+    /// A truncated final record (e.g. from a crash mid-write) is dropped from
+    /// the log and reported through `warnings` instead of failing the load.
+    /// Logs from an older schema version are backed up and migrated in place;
+    /// logs from a newer version are refused.
+    pub fn open<P: AsRef<Path>>(log_path: P) -> Result<Self> {
+        let log_path = log_path.as_ref().to_path_buf();
+        
+        let mut store = Self {
+            log_path,
+            chunks: Vec::new(),
+            record_count: 0,
+            has_schema_record: false,
+            warnings: Vec::new(),
+        };
+        
+        if store.log_path.exists() {
+            let version = Self::log_version(&store.log_path)?;
+            schema::ensure_supported(&store.log_path, version)?;
+            
+            if version < SCHEMA_VERSION {
+                let backup_path = schema::backup(&store.log_path, version)?;
+                Self::migrate(&store.log_path, version)
+                    .with_context(|| format!("Failed to upgrade chunk log {}", store.log_path.display()))?;
+                store.warnings.push(schema::upgrade_message(&store.log_path, version, &backup_path));
+            }
+            
+            store.replay()?;
+        } else if let Some(parent) = store.log_path.parent() {
+            // Create parent directories if they don't exist
+            fs::create_dir_all(parent).context("Failed to create parent directories for JSONL log")?;
+        }
+        
+        // Keep the log from growing without bound when chunks are edited a lot,
+        // and give logs written before versioning their schema record
+        let oversized = store.record_count > COMPACTION_RATIO * store.chunks.len().max(1);
+        if oversized || (store.record_count > 0 && !store.has_schema_record) {
+            store.compact()?;
+        }
+        
+        Ok(store)
+    }
+    
+    /// Read the schema version of an existing log
+    /// 
+    /// Logs written before versions were recorded are dated by the fields
+    /// of their first chunk record.
+    fn log_version(log_path: &Path) -> Result<u32> {
+        let reader = BufReader::new(File::open(log_path)
+            .with_context(|| format!("Failed to open chunk log: {}", log_path.display()))?);
+        
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            
+            // Unreadable records are left for `replay` to report
+            let Ok(record) = serde_json::from_str::<serde_json::Value>(&line) else {
+                break;
+            };
+            if let Some(version) = record.get("version").and_then(|version| version.as_u64()) {
+                return Ok(version as u32);
+            }
+            if let Some(chunk) = record.get("chunk").and_then(|chunk| chunk.as_object()) {
+                return Ok(schema::infer_version(|field| chunk.contains_key(field)));
+            }
+        }
+        
+        Ok(SCHEMA_VERSION)
+    }
+    
+    /// Rewrite a log from an older schema version with the current chunk fields
+    /// 
+    /// Records that can't be parsed are copied unchanged so `replay` can
+    /// report them as usual.
+    fn migrate(log_path: &Path, version: u32) -> Result<()> {
+        let text = fs::read_to_string(log_path)
+            .with_context(|| format!("Failed to read chunk log: {}", log_path.display()))?;
+        
+        write_atomically(log_path, |writer| {
+            serde_json::to_writer(&mut *writer, &LogRecord::Schema { version: SCHEMA_VERSION })?;
+            writer.write_all(b"\n")?;
+            
+            for line in text.split_inclusive('\n') {
+                let mut record = match serde_json::from_str::<serde_json::Value>(line.trim_end()) {
+                    Ok(record) => record,
+                    Err(_) => {
+                        writer.write_all(line.as_bytes())?;
+                        continue;
+                    }
+                };
+                if record.get("op").and_then(|op| op.as_str()) == Some("schema") {
+                    continue;
+                }
+                if let Some(chunk) = record.get_mut("chunk").and_then(|chunk| chunk.as_object_mut()) {
+                    schema::upgrade_json_chunk(chunk, version);
+                }
+                
+                serde_json::to_writer(&mut *writer, &record)?;
+                if line.ends_with('\n') {
+                    writer.write_all(b"\n")?;
+                }
+            }
+            Ok(())
+        })
+    }
+    
+    /// Rewrite the log with one `add` record per live chunk
+    /// 
+    /// The new log is written to a temporary file and renamed over the old
+    /// one, so the log on disk is always either the old or the new version.
+    pub fn compact(&mut self) -> Result<()> {
+        write_atomically(&self.log_path, |writer| {
+            serde_json::to_writer(&mut *writer, &LogRecord::Schema { version: SCHEMA_VERSION })?;
+            writer.write_all(b"\n")?;
+            for chunk in &self.chunks {
+                serde_json::to_writer(&mut *writer, &LogRecord::Add { chunk: chunk.clone() })?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+        
+        self.record_count = self.chunks.len();
+        self.has_schema_record = true;
+        Ok(())
+    }
+    
+    /// Read every record in the log and apply it to the in-memory state
+    fn replay(&mut self) -> Result<()> {
+        let mut reader = BufReader::new(File::open(&self.log_path)
+            .with_context(|| format!("Failed to open chunk log: {}", self.log_path.display()))?);
+        
+        // Read raw lines so we know where the last complete record ends
+        let mut lines = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            lines.push(line);
+        }
+        
+        let mut valid_len = 0u64;
+        let line_count = lines.len();
+        for (i, line) in lines.into_iter().enumerate() {
+            let is_last = i + 1 == line_count;
+            let complete = line.ends_with(b"\n");
+            let text = String::from_utf8_lossy(&line);
+            
+            if text.trim().is_empty() {
+                valid_len += line.len() as u64;
+                continue;
+            }
+            
+            match serde_json::from_str::<LogRecord>(text.trim_end()) {
+                Ok(LogRecord::Schema { .. }) if complete => {
+                    self.has_schema_record = true;
+                    valid_len += line.len() as u64;
+                },
+                Ok(record) if complete => {
+                    self.apply(record);
+                    self.record_count += 1;
+                    valid_len += line.len() as u64;
+                },
+                Err(e) if !is_last => {
+                    return Err(anyhow!(
+                        "Corrupt record at line {} of {}: {}",
+                        i + 1,
+                        self.log_path.display(),
+                        e
+                    ));
+                },
+                // The final record was cut off mid-write: drop it and keep going
+                _ => {
+                    self.warnings.push(format!(
+                        "Ignored truncated record at line {} of {}",
+                        i + 1,
+                        self.log_path.display()
+                    ));
+                }
+            }
+        }
+        
+        // Cut off the partial record so later appends start on a clean line
+        if !self.warnings.is_empty() {
+            let file = OpenOptions::new().write(true).open(&self.log_path)?;
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Apply a log record to the in-memory chunk list
+    fn apply(&mut self, record: LogRecord) {
+        match record {
+            LogRecord::Add { chunk } => self.chunks.push(chunk),
+            LogRecord::Update { chunk } => {
+                if let Some(existing) = self.chunks.iter_mut().find(|existing| existing.id == chunk.id) {
+                    *existing = chunk;
+                }
+            },
+            LogRecord::Delete { id } => self.chunks.retain(|chunk| chunk.id != id),
+            LogRecord::Schema { .. } => {},
+        }
+    }
+    
+    /// Append records to the log and fsync before returning
+    fn append(&mut self, records: &[LogRecord]) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+            .with_context(|| format!("Failed to open chunk log: {}", self.log_path.display()))?;
+        
+        // A new log starts with its schema version
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_new {
+            serde_json::to_writer(&mut writer, &LogRecord::Schema { version: SCHEMA_VERSION })?;
+            writer.write_all(b"\n")?;
+            self.has_schema_record = true;
+        }
+        for record in records {
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_data()
+            .with_context(|| format!("Failed to sync chunk log: {}", self.log_path.display()))?;
+        
+        self.record_count += records.len();
+        Ok(())
+    }
+}
+
+impl ChunkStore for JsonlChunkStore {
+    fn add(&mut self, chunk: Chunk) -> Result<()> {
+        self.add_many(vec![chunk])
+    }
+    
+    fn add_many(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        let records: Vec<LogRecord> = chunks
+            .iter()
+            .map(|chunk| LogRecord::Add { chunk: chunk.clone() })
+            .collect();
+        self.append(&records)?;
+        self.chunks.extend(chunks);
+        Ok(())
+    }
+    
+    fn update(&mut self, chunk: Chunk) -> Result<()> {
+        if !self.chunks.iter().any(|existing| existing.id == chunk.id) {
+            return Err(anyhow!("No chunk with id {}", chunk.id));
+        }
+        self.append(&[LogRecord::Update { chunk: chunk.clone() }])?;
+        self.apply(LogRecord::Update { chunk });
+        Ok(())
+    }
+    
+    fn delete(&mut self, id: &str) -> Result<Option<Chunk>> {
+        let Some(index) = self.chunks.iter().position(|chunk| chunk.id == id) else {
+            return Ok(None);
+        };
+        self.append(&[LogRecord::Delete { id: id.to_string() }])?;
+        Ok(Some(self.chunks.remove(index)))
+    }
+    
+    fn get(&self, id: &str) -> Result<Option<Chunk>> {
+        Ok(self.chunks.iter().find(|chunk| chunk.id == id).cloned())
+    }
+    
+    fn chunks_for_file(&self, file_path: &Path) -> Result<Vec<Chunk>> {
+        Ok(self.chunks
+            .iter()
+            .filter(|chunk| chunk.file_path == file_path)
+            .cloned()
+            .collect())
+    }
+    
+    fn for_each_chunk(&self, f: &mut dyn FnMut(&Chunk)) -> Result<()> {
+        self.chunks.iter().for_each(f);
+        Ok(())
+    }
+    
+    fn compact(&mut self) -> Result<()> {
+        JsonlChunkStore::compact(self)
+    }
+    
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
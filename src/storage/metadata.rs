@@ -0,0 +1,165 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use super::export::{EXPORT_FIELDS, PROMPT_FIELD};
+
+/// Structured metadata of a chunk, keyed by field name
+/// 
+/// A `BTreeMap` keeps keys sorted, so stored and exported metadata has a
+/// stable order.
+pub type Metadata = BTreeMap<String, MetadataValue>;
+
+/// Type of the values a metadata field accepts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataType {
+    /// Any text
+    #[default]
+    Text,
+    /// A whole number
+    Integer,
+    /// Any number
+    Number,
+    /// true/false (yes/no are accepted when typing)
+    Boolean,
+}
+
+/// A single metadata value
+/// 
+/// Stored as plain JSON, so the type survives a round trip through storage.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataValue::Boolean(value) => write!(f, "{}", value),
+            MetadataValue::Integer(value) => write!(f, "{}", value),
+            MetadataValue::Number(value) => write!(f, "{}", value),
+            MetadataValue::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// A metadata field declared in the config file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetadataField {
+    /// Key the value is stored (and exported) under
+    pub name: String,
+    
+    /// Type of the value
+    #[serde(rename = "type", default)]
+    pub value_type: MetadataType,
+    
+    /// Whether a chunk can't be saved without a value
+    #[serde(default)]
+    pub required: bool,
+    
+    /// Allowed values for text fields (any text if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+}
+
+impl MetadataField {
+    /// Parse a value typed into the metadata form
+    /// 
+    /// Returns `None` for an empty optional field, and an error naming the
+    /// field if the text doesn't fit its type or choices.
+    pub fn parse(&self, text: &str) -> Result<Option<MetadataValue>> {
+        let text = text.trim();
+        if text.is_empty() {
+            if self.required {
+                bail!("{} is required", self.name);
+            }
+            return Ok(None);
+        }
+        
+        let value = match self.value_type {
+            MetadataType::Text => {
+                if !self.choices.is_empty() && !self.choices.iter().any(|choice| choice == text) {
+                    bail!("{} must be one of: {}", self.name, self.choices.join(", "));
+                }
+                MetadataValue::Text(text.to_string())
+            },
+            MetadataType::Integer => MetadataValue::Integer(
+                text.parse().with_context(|| format!("{} must be a whole number", self.name))?,
+            ),
+            MetadataType::Number => MetadataValue::Number(
+                // JSON has no NaN or infinity, so they couldn't be stored
+                text.parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .with_context(|| format!("{} must be a number", self.name))?,
+            ),
+            MetadataType::Boolean => match text.to_lowercase().as_str() {
+                "true" | "yes" | "y" => MetadataValue::Boolean(true),
+                "false" | "no" | "n" => MetadataValue::Boolean(false),
+                _ => bail!("{} must be true or false", self.name),
+            },
+        };
+        
+        Ok(Some(value))
+    }
+    
+    /// Short description of what the field accepts, e.g. `integer, required`
+    pub fn describe(&self) -> String {
+        let mut description = match self.value_type {
+            MetadataType::Text if !self.choices.is_empty() => self.choices.join("/"),
+            MetadataType::Text => "text".to_string(),
+            MetadataType::Integer => "integer".to_string(),
+            MetadataType::Number => "number".to_string(),
+            MetadataType::Boolean => "true/false".to_string(),
+        };
+        if self.required {
+            description.push_str(", required");
+        }
+        description
+    }
+}
+
+/// Check the metadata fields declared in the config file
+/// 
+/// Names must be unique and must not clash with the columns chunks are
+/// exported with (including the rendered prompt), because exports flatten
+/// metadata into the same row.
+pub fn validate_metadata_fields(fields: &[MetadataField]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for field in fields {
+        if field.name.trim().is_empty() {
+            bail!("Metadata fields need a name");
+        }
+        if EXPORT_FIELDS.contains(&field.name.as_str()) || field.name == PROMPT_FIELD {
+            bail!("Metadata field {} clashes with a built-in chunk field", field.name);
+        }
+        if !seen.insert(field.name.as_str()) {
+            bail!("Metadata field {} is declared more than once", field.name);
+        }
+        if !field.choices.is_empty() && field.value_type != MetadataType::Text {
+            bail!("Metadata field {} has choices, which only text fields support", field.name);
+        }
+    }
+    Ok(())
+}
+
+/// Encode chunk metadata as a single string (a JSON object)
+pub fn encode_metadata(metadata: &Metadata) -> String {
+    serde_json::to_string(metadata).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Decode a metadata string written by `encode_metadata`
+/// 
+/// Chunks saved before metadata existed have an empty string.
+pub fn decode_metadata(text: &str) -> Result<Metadata> {
+    if text.is_empty() {
+        return Ok(Metadata::new());
+    }
+    serde_json::from_str(text).with_context(|| format!("Invalid chunk metadata: {}", text))
+}
@@ -1,8 +1,42 @@
-use anyhow::{Context, Result};
-use csv;
+mod csv_store;
+mod export;
+mod import;
+mod journal;
+mod jsonl_store;
+mod metadata;
+mod reanchor;
+mod schema;
+mod span;
+mod sqlite_store;
+mod stats;
+mod validate;
+
+pub use csv_store::CsvChunkStore;
+pub use export::{default_export_path, export_chunks, ExportFormat, ExportOptions};
+pub use import::import_chunks;
+pub use journal::{Journal, Operation};
+pub use jsonl_store::JsonlChunkStore;
+pub use metadata::{decode_metadata, encode_metadata, validate_metadata_fields, Metadata, MetadataField};
+// Only used through the library, e.g. to build metadata in tests
+#[allow(unused_imports)]
+pub use metadata::{MetadataType, MetadataValue};
+pub use reanchor::{reanchor_chunks, MatchKind, Placement, ReanchorReport};
+pub use span::{coverage_percentage, line_lengths, ChunkSpan};
+pub use sqlite_store::SqliteChunkStore;
+pub use stats::ChunkStats;
+pub use validate::validate_chunks;
+// Results of the functions above, only named through the library
+#[allow(unused_imports)]
+pub use import::ImportReport;
+#[allow(unused_imports)]
+pub use validate::{ChunkProblem, Problem};
+
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize, Serializer, Deserializer};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
@@ -22,6 +56,16 @@ pub struct Chunk {
     /// Ending line number (1-indexed)
     pub end_line: usize,
     
+    /// First character of the chunk on `start_line` (0-indexed)
+    /// 
+    /// `None` when the chunk starts at the beginning of the line.
+    pub start_column: Option<usize>,
+    
+    /// One past the last character of the chunk on `end_line` (0-indexed)
+    /// 
+    /// `None` when the chunk runs to the end of the line.
+    pub end_column: Option<usize>,
+    
     /// The actual chunk text content
     pub content: String,
     
@@ -33,9 +77,28 @@ pub struct Chunk {
     
     /// Optional user-provided labels
     pub labels: Vec<String>,
+    
+    /// Structured key/value metadata, with keys declared in the config
+    pub metadata: Metadata,
+    
+    /// SHA-256 of the whole source file when the chunk was saved
+    /// 
+    /// Empty for chunks saved before fingerprinting was added.
+    pub source_hash: String,
+    
+    /// SHA-256 of the original source lines covered by the chunk
+    /// 
+    /// Empty for chunks saved before fingerprinting was added.
+    pub lines_hash: String,
+    
+    /// Id shared by the chunks of one sliding-window pass
+    /// 
+    /// Chunks with the same window id overlap on purpose. Empty for chunks
+    /// that aren't sliding windows.
+    pub window_id: String,
 }
 
-// Custom serialization for Chunk to handle Vec<String> labels field
+// Custom serialization for Chunk to store labels and metadata as single encoded fields
 impl Serialize for Chunk {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -43,11 +106,10 @@ impl Serialize for Chunk {
     {
         use serde::ser::SerializeStruct;
         
-        // Convert labels Vec<String> to a single string with vertical bar separator
-        // Using a non-comma separator to better handle labels containing commas
-        let labels_str = self.labels.join("|");
+        // CSV rows can't hold a list, so labels are stored as a JSON array string
+        let labels_str = encode_labels(&self.labels);
         
-        let mut state = serializer.serialize_struct("Chunk", 8)?;
+        let mut state = serializer.serialize_struct("Chunk", 14)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("file_path", &self.file_path)?;
         state.serialize_field("start_line", &self.start_line)?;
@@ -56,11 +118,17 @@ impl Serialize for Chunk {
         state.serialize_field("timestamp", &self.timestamp)?;
         state.serialize_field("edited", &self.edited)?;
         state.serialize_field("labels", &labels_str)?;
+        state.serialize_field("source_hash", &self.source_hash)?;
+        state.serialize_field("lines_hash", &self.lines_hash)?;
+        state.serialize_field("start_column", &self.start_column)?;
+        state.serialize_field("end_column", &self.end_column)?;
+        state.serialize_field("metadata", &encode_metadata(&self.metadata))?;
+        state.serialize_field("window_id", &self.window_id)?;
         state.end()
     }
 }
 
-// Custom deserialization for Chunk to handle separator-delimited labels string
+// Custom deserialization for Chunk to decode the labels and metadata strings
 impl<'de> Deserialize<'de> for Chunk {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -76,27 +144,44 @@ impl<'de> Deserialize<'de> for Chunk {
             timestamp: u64,
             edited: bool,
             labels: String,
+            // Missing in chunk files written before fingerprinting
+            #[serde(default)]
+            source_hash: String,
+            #[serde(default)]
+            lines_hash: String,
+            // Missing in chunk files written before column boundaries
+            #[serde(default)]
+            start_column: Option<usize>,
+            #[serde(default)]
+            end_column: Option<usize>,
+            // Missing in chunk files written before metadata
+            #[serde(default)]
+            metadata: String,
+            // Missing in chunk files written before sliding windows
+            #[serde(default)]
+            window_id: String,
         }
         
         let helper = ChunkHelper::deserialize(deserializer)?;
         
-        // Parse the labels string back to Vec<String>
-        // Using vertical bar separator to better handle labels containing commas
-        let labels = if helper.labels.is_empty() {
-            Vec::new()
-        } else {
-            helper.labels.split('|').map(String::from).collect()
-        };
+        let labels = decode_labels(&helper.labels);
+        let metadata = decode_metadata(&helper.metadata).map_err(serde::de::Error::custom)?;
         
         Ok(Chunk {
             id: helper.id,
             file_path: helper.file_path,
             start_line: helper.start_line,
             end_line: helper.end_line,
+            start_column: helper.start_column,
+            end_column: helper.end_column,
             content: helper.content,
             timestamp: helper.timestamp,
             edited: helper.edited,
             labels,
+            metadata,
+            source_hash: helper.source_hash,
+            lines_hash: helper.lines_hash,
+            window_id: helper.window_id,
         })
     }
 }
@@ -115,6 +200,8 @@ impl Chunk {
             file_path,
             start_line,
             end_line,
+            start_column: None,
+            end_column: None,
             content,
             timestamp: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -122,155 +209,416 @@ impl Chunk {
                 .as_secs(),
             edited,
             labels: Vec::new(),
+            metadata: Metadata::new(),
+            source_hash: String::new(),
+            lines_hash: String::new(),
+            window_id: String::new(),
+        }
+    }
+    
+    /// Limit the chunk to part of its first and last lines
+    /// 
+    /// See `ChunkSpan` for how columns are counted.
+    pub fn with_columns(mut self, start_column: Option<usize>, end_column: Option<usize>) -> Self {
+        self.start_column = start_column;
+        self.end_column = end_column;
+        self
+    }
+    
+    /// The position of the chunk in its source file
+    pub fn span(&self) -> ChunkSpan {
+        ChunkSpan {
+            start_line: self.start_line,
+            start_column: self.start_column,
+            end_line: self.end_line,
+            end_column: self.end_column,
+        }
+    }
+    
+    /// Attach fingerprints of the source file and of the chunk's original lines
+    pub fn with_fingerprint(mut self, source_hash: String, lines_hash: String) -> Self {
+        self.source_hash = source_hash;
+        self.lines_hash = lines_hash;
+        self
+    }
+    
+    /// Mark the chunk as one of the overlapping windows of a sliding-window pass
+    pub fn with_window(mut self, window_id: String) -> Self {
+        self.window_id = window_id;
+        self
+    }
+    
+    /// Check whether the given source lines still match this chunk's fingerprint
+    /// 
+    /// `source_hash` is the fingerprint of the whole current file and `lines` the
+    /// current lines at the chunk's range (`None` if the range is out of bounds).
+    /// Chunks without a fingerprint are never considered stale.
+    pub fn is_stale(&self, source_hash: &str, lines: Option<&[String]>) -> bool {
+        if self.lines_hash.is_empty() || self.source_hash == source_hash {
+            return false;
+        }
+        lines.map(hash_lines).as_deref() != Some(self.lines_hash.as_str())
+    }
+}
+
+/// Whether two window ids mark chunks of the same sliding-window pass
+pub fn same_window(a: &str, b: &str) -> bool {
+    !a.is_empty() && a == b
+}
+
+/// Fingerprint a sequence of lines as a hex-encoded SHA-256 digest
+/// 
+/// Lines are joined with `\n`, so the whole-file hash and the hash of a
+/// line range are computed the same way.
+pub fn hash_lines<S: AsRef<str>>(lines: &[S]) -> String {
+    let mut hasher = Sha256::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            hasher.update(b"\n");
+        }
+        hasher.update(line.as_ref().as_bytes());
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Encode chunk labels as a single string (a JSON array)
+/// 
+/// Any label text survives the round trip, including separators and quotes.
+pub fn encode_labels(labels: &[String]) -> String {
+    serde_json::to_string(labels).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Decode a labels string written by `encode_labels`
+/// 
+/// Strings from before labels were JSON-encoded (joined with `|`) are
+/// split on `|`, so chunks in old undo journals still load.
+pub fn decode_labels(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    serde_json::from_str(text).unwrap_or_else(|_| text.split('|').map(String::from).collect())
+}
+
+/// Storage backend used to persist chunks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// A single CSV file with one row per chunk
+    #[default]
+    Csv,
+    /// An embedded SQLite database with indexed lookups
+    Sqlite,
+    /// An append-only JSON Lines log that is synced after every change
+    Jsonl,
+}
+
+/// Operations a chunk storage backend has to support
+/// 
+/// Line numbers in the chunks passed to and returned from a store are 1-indexed.
+pub trait ChunkStore {
+    /// Persist a new chunk
+    fn add(&mut self, chunk: Chunk) -> Result<()>;
+    
+    /// Persist several chunks at once
+    /// 
+    /// Backends that support transactions store the whole batch or nothing.
+    fn add_many(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        for chunk in chunks {
+            self.add(chunk)?;
         }
+        Ok(())
+    }
+    
+    /// Replace the stored chunk that has the same id
+    fn update(&mut self, chunk: Chunk) -> Result<()>;
+    
+    /// Replace several stored chunks
+    /// 
+    /// The default implementation updates them one at a time.
+    fn update_many(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        for chunk in chunks {
+            self.update(chunk)?;
+        }
+        Ok(())
+    }
+    
+    /// Delete a chunk by id, returning the removed chunk if it existed
+    fn delete(&mut self, id: &str) -> Result<Option<Chunk>>;
+    
+    /// Look up a single chunk by id
+    fn get(&self, id: &str) -> Result<Option<Chunk>>;
+    
+    /// Get all chunks recorded for a specific file
+    fn chunks_for_file(&self, file_path: &Path) -> Result<Vec<Chunk>>;
+    
+    /// Get the spans chunked for a specific file
+    fn chunk_spans(&self, file_path: &Path) -> Result<Vec<ChunkSpan>> {
+        Ok(self.chunks_for_file(file_path)?
+            .iter()
+            .map(Chunk::span)
+            .collect())
+    }
+    
+    /// Get the chunked spans of every file, keyed by file path
+    fn all_chunk_spans(&self) -> Result<HashMap<PathBuf, Vec<ChunkSpan>>> {
+        let mut spans: HashMap<PathBuf, Vec<ChunkSpan>> = HashMap::new();
+        self.for_each_chunk(&mut |chunk| {
+            spans
+                .entry(chunk.file_path.clone())
+                .or_default()
+                .push(chunk.span());
+        })?;
+        Ok(spans)
+    }
+    
+    /// Visit every stored chunk in insertion order
+    fn for_each_chunk(&self, f: &mut dyn FnMut(&Chunk)) -> Result<()>;
+    
+    /// Rewrite the backing file without superseded records
+    fn compact(&mut self) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Problems that were recovered from while opening the store
+    fn warnings(&self) -> &[String] {
+        &[]
     }
 }
 
-/// Manages chunk storage using CSV
+/// Replace a file's contents atomically
+/// 
+/// The new contents are written to a temporary file in the same directory,
+/// synced to disk and then renamed over `path`, so readers only ever see
+/// the old or the new version of the file.
+pub(crate) fn write_atomically<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "chunks".to_string());
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    
+    let mut writer = BufWriter::new(File::create(&temp_path)
+        .with_context(|| format!("Failed to create temporary file: {}", temp_path.display()))?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    drop(writer);
+    
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    
+    // Sync the directory so the rename itself survives a crash
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+    
+    Ok(())
+}
+
+/// Manages chunk storage through the backend selected in the configuration
+/// 
+/// Every create, delete and edit goes through the undo journal, so changes
+/// made through `ChunkStorage` can be undone and redone across sessions.
 pub struct ChunkStorage {
-    /// Path to the CSV file
-    csv_path: PathBuf,
+    /// The backend that actually persists chunks
+    store: Box<dyn ChunkStore>,
+    
+    /// Undo/redo history of chunk operations
+    journal: Journal,
     
-    /// In-memory cache of chunks
-    chunks: Vec<Chunk>,
+    /// Problems that were recovered from while opening storage
+    warnings: Vec<String>,
 }
 
 impl ChunkStorage {
-    /// Create a new storage manager
+    /// Create a new storage manager backed by a CSV file
     pub fn new<P: AsRef<Path>>(csv_path: P) -> Result<Self> {
-        let csv_path = csv_path.as_ref().to_path_buf();
-        let chunks = if csv_path.exists() {
-            Self::load_chunks(&csv_path)?
-        } else {
-            // Create parent directories if they don't exist
-            if let Some(parent) = csv_path.parent() {
-                fs::create_dir_all(parent).context("Failed to create parent directories for CSV file")?;
-            }
-            // Return empty chunks for new file
-            Vec::new()
+        Self::open(csv_path, StorageBackend::Csv)
+    }
+    
+    /// Open chunk storage at the given path using a specific backend
+    pub fn open<P: AsRef<Path>>(path: P, backend: StorageBackend) -> Result<Self> {
+        let path = path.as_ref();
+        let store: Box<dyn ChunkStore> = match backend {
+            StorageBackend::Csv => Box::new(CsvChunkStore::open(path)?),
+            StorageBackend::Sqlite => Box::new(SqliteChunkStore::open(path)?),
+            StorageBackend::Jsonl => Box::new(JsonlChunkStore::open(path)?),
         };
         
-        Ok(Self {
-            csv_path,
-            chunks,
-        })
+        let mut warnings = store.warnings().to_vec();
+        let (journal, journal_warning) = Journal::open(path);
+        warnings.extend(journal_warning);
+        
+        Ok(Self { store, journal, warnings })
     }
     
     /// Add a new chunk to storage
     pub fn add_chunk(&mut self, chunk: Chunk) -> Result<()> {
-        self.chunks.push(chunk);
-        self.save()
-    }
-    
-    /// Get all chunks
-    pub fn get_chunks(&self) -> &[Chunk] {
-        &self.chunks
+        self.store.add(chunk.clone())?;
+        self.record(Operation::Create { chunks: vec![chunk] });
+        Ok(())
     }
     
-    /// Get chunks for a specific file
-    pub fn get_chunks_for_file<P: AsRef<Path>>(&self, file_path: P) -> Vec<&Chunk> {
-        let path = file_path.as_ref();
-        self.chunks
-            .iter()
-            .filter(|chunk| chunk.file_path == path)
-            .collect()
+    /// Add several chunks to storage in one batch
+    /// 
+    /// The batch is undone and redone as a single operation.
+    pub fn add_chunks(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        self.store.add_many(chunks.clone())?;
+        self.record(Operation::Create { chunks });
+        Ok(())
     }
     
-    /// Get ranges of chunked lines for a specific file
-    pub fn get_chunked_ranges<P: AsRef<Path>>(&self, file_path: P) -> Vec<(usize, usize)> {
-        let path = file_path.as_ref();
-        self.chunks
-            .iter()
-            .filter(|chunk| chunk.file_path == path)
-            .map(|chunk| (chunk.start_line, chunk.end_line))
-            .collect()
+    /// Replace an existing chunk, matched by id
+    pub fn update_chunk(&mut self, chunk: Chunk) -> Result<()> {
+        let before = self.store.get(&chunk.id)?
+            .ok_or_else(|| anyhow!("No chunk with id {}", chunk.id))?;
+        self.store.update(chunk.clone())?;
+        self.record(Operation::Edit { before: Box::new(before), after: Box::new(chunk) });
+        Ok(())
     }
     
-    /// Calculate chunking percentage for a file
+    /// Replace several existing chunks in one batch
     /// 
-    /// Note: Chunks have 1-indexed line numbers, but we need to convert
-    /// to 0-indexed when tracking which lines are chunked in our array.
-    pub fn calculate_chunking_percentage<P: AsRef<Path>>(&self, file_path: P, total_lines: usize) -> f64 {
-        if total_lines == 0 {
-            return 0.0;
-        }
-        
-        let path = file_path.as_ref();
-        
-        // Get all chunks for this file
-        let file_chunks: Vec<_> = self.chunks
-            .iter()
-            .filter(|chunk| chunk.file_path == path)
-            .collect();
-        
-        if file_chunks.is_empty() {
-            return 0.0;
+    /// The batch is undone and redone as a single operation.
+    pub fn update_chunks(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
         }
         
-        // Count unique chunked lines
-        let mut chunked_lines = vec![false; total_lines];
-        
-        for chunk in file_chunks {
-            // Convert from 1-indexed (storage) to 0-indexed (for the boolean array)
-            let start_idx = chunk.start_line.saturating_sub(1);
-            let end_idx = chunk.end_line.saturating_sub(1).min(total_lines - 1);
-            
-            for i in start_idx..=end_idx {
-                chunked_lines[i] = true;
-            }
+        let mut before = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            before.push(self.store.get(&chunk.id)?
+                .ok_or_else(|| anyhow!("No chunk with id {}", chunk.id))?);
         }
         
-        // Calculate percentage
-        let chunked_count = chunked_lines.iter().filter(|&&chunked| chunked).count();
-        (chunked_count as f64 / total_lines as f64) * 100.0
+        self.store.update_many(chunks.clone())?;
+        self.record(Operation::EditMany { before, after: chunks });
+        Ok(())
     }
     
-    /// Save all chunks to the CSV file
-    pub fn save(&self) -> Result<()> {
-        // Create writer with BufWriter for better performance
-        let writer = BufWriter::new(File::create(&self.csv_path)?);
-        
-        // Create a CSV writer with custom options for better quoting
-        let mut csv_writer = csv::WriterBuilder::new()
-            .quote_style(csv::QuoteStyle::Always)  // Always quote all fields
-            .double_quote(true)                    // Ensure quotes inside fields are escaped properly
-            .from_writer(writer);
-        
-        // Write each chunk to CSV
-        for chunk in &self.chunks {
-            csv_writer.serialize(chunk)?;
+    /// Delete a chunk by id, returning the removed chunk if it existed
+    pub fn delete_chunk(&mut self, id: &str) -> Result<Option<Chunk>> {
+        let removed = self.store.delete(id)?;
+        if let Some(chunk) = &removed {
+            self.record(Operation::Delete { chunk: Box::new(chunk.clone()) });
         }
-        
-        // Flush writer
-        csv_writer.flush()?;
-        
-        Ok(())
+        Ok(removed)
     }
     
-    /// Load chunks from CSV file
-    fn load_chunks(csv_path: &Path) -> Result<Vec<Chunk>> {
-        // If file doesn't exist, return empty vector
-        if !csv_path.exists() {
-            return Ok(Vec::new());
+    /// Add a stored change to the undo journal
+    /// 
+    /// The change is already saved, so a journal that can't be written only
+    /// loses its undo history; that's reported through `warnings` instead of
+    /// failing the save.
+    fn record(&mut self, operation: Operation) {
+        if let Err(e) = self.journal.record(operation) {
+            self.warnings.push(format!("Saved, but the change can't be undone: {:#}", e));
         }
-        
-        // Open file with BufReader for better performance
-        let reader = BufReader::new(File::open(csv_path)?);
-        
-        // Create a CSV reader with custom options to match our writer
-        let mut csv_reader = csv::ReaderBuilder::new()
-            .flexible(true)              // Be more lenient with parsing
-            .double_quote(true)          // Handle double-quoted quotes
-            .from_reader(reader);
-        
-        // Parse CSV into Chunk records
+    }
+    
+    /// Undo the most recent chunk operation, returning it if there was one
+    pub fn undo(&mut self) -> Result<Option<Operation>> {
+        self.journal.undo(self.store.as_mut())
+    }
+    
+    /// Redo the most recently undone chunk operation, returning it if there was one
+    pub fn redo(&mut self) -> Result<Option<Operation>> {
+        self.journal.redo(self.store.as_mut())
+    }
+    
+    /// Get a single chunk by id
+    pub fn get_chunk(&self, id: &str) -> Result<Option<Chunk>> {
+        self.store.get(id)
+    }
+    
+    /// Compact the backing file, dropping superseded records
+    pub fn compact(&mut self) -> Result<()> {
+        self.store.compact()
+    }
+    
+    /// Problems that were recovered from while opening or saving to storage
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+    
+    /// Take the warnings reported so far, so each is only shown once
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+    
+    /// Get all chunks
+    pub fn get_chunks(&self) -> Result<Vec<Chunk>> {
         let mut chunks = Vec::new();
-        for result in csv_reader.deserialize() {
-            let chunk: Chunk = result?;
-            chunks.push(chunk);
+        self.store.for_each_chunk(&mut |chunk| chunks.push(chunk.clone()))?;
+        Ok(chunks)
+    }
+    
+    /// Get every label used by stored chunks, most used first
+    pub fn all_labels(&self) -> Result<Vec<String>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        self.store.for_each_chunk(&mut |chunk| {
+            for label in &chunk.labels {
+                *counts.entry(label.clone()).or_default() += 1;
+            }
+        })?;
+        
+        let mut labels: Vec<(String, usize)> = counts.into_iter().collect();
+        labels.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(labels.into_iter().map(|(label, _)| label).collect())
+    }
+    
+    /// Get the files (relative to root) that have a chunk with any of the given labels
+    pub fn files_with_labels(&self, labels: &[String]) -> Result<HashSet<PathBuf>> {
+        let mut files = HashSet::new();
+        self.store.for_each_chunk(&mut |chunk| {
+            if chunk.labels.iter().any(|label| labels.contains(label)) {
+                files.insert(chunk.file_path.clone());
+            }
+        })?;
+        Ok(files)
+    }
+    
+    /// Get chunks for a specific file
+    pub fn get_chunks_for_file<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<Chunk>> {
+        self.store.chunks_for_file(file_path.as_ref())
+    }
+    
+    /// Get ranges of chunked lines for a specific file
+    pub fn get_chunked_ranges<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<(usize, usize)>> {
+        Ok(self.get_chunk_spans(file_path)?
+            .iter()
+            .map(|span| (span.start_line, span.end_line))
+            .collect())
+    }
+    
+    /// Get the chunked spans, including column boundaries, for a specific file
+    pub fn get_chunk_spans<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<ChunkSpan>> {
+        self.store.chunk_spans(file_path.as_ref())
+    }
+    
+    /// Get chunked spans for every file that has chunks
+    pub fn get_all_chunk_spans(&self) -> Result<HashMap<PathBuf, Vec<ChunkSpan>>> {
+        self.store.all_chunk_spans()
+    }
+    
+    /// Calculate chunking percentage for a file
+    /// 
+    /// `line_lengths` holds the length in characters of every line of the
+    /// file. Lines only partly covered by column-bounded chunks count by the
+    /// share of their characters that are chunked.
+    pub fn calculate_chunking_percentage<P: AsRef<Path>>(&self, file_path: P, line_lengths: &[usize]) -> Result<f64> {
+        if line_lengths.is_empty() {
+            return Ok(0.0);
         }
         
-        Ok(chunks)
+        let spans = self.get_chunk_spans(file_path)?;
+        Ok(coverage_percentage(&spans, line_lengths))
     }
 }
-
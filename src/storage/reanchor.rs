@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use super::{hash_lines, Chunk};
+
+/// Minimum share of matching lines for a fuzzy placement to be accepted
+const MIN_FUZZY_SIMILARITY: f64 = 0.6;
+
+/// Most times a line may occur in the file and still anchor a chunk to it
+const MAX_ANCHOR_OCCURRENCES: usize = 8;
+
+/// How a chunk was located in the changed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The chunk's original lines were found unchanged
+    Exact,
+    /// The closest similar block of lines was used
+    Fuzzy,
+}
+
+/// A chunk that was found at a new position
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// The chunk with its new line range and refreshed fingerprints
+    pub chunk: Chunk,
+    /// The 1-indexed line range the chunk had before re-anchoring
+    pub old_range: (usize, usize),
+    /// How the new position was found
+    pub kind: MatchKind,
+}
+
+/// Result of re-anchoring a file's chunks against its current content
+#[derive(Debug, Clone, Default)]
+pub struct ReanchorReport {
+    /// Stale chunks that were located again
+    pub placed: Vec<Placement>,
+    /// Stale chunks that could not be located in the current file
+    pub unplaced: Vec<Chunk>,
+}
+
+impl ReanchorReport {
+    /// Whether there is nothing to write back to storage
+    pub fn is_empty(&self) -> bool {
+        self.placed.is_empty()
+    }
+    
+    /// The updated chunks to write back to storage
+    pub fn updated_chunks(&self) -> Vec<Chunk> {
+        self.placed.iter().map(|placement| placement.chunk.clone()).collect()
+    }
+}
+
+/// Relocate stale chunks of a file within its current lines
+/// 
+/// Chunks that still match their fingerprint are left alone. The current
+/// lines are indexed once, and every stale chunk is lined up against the
+/// file through the lines it shares with it, like a diff of the old and new
+/// file: each occurrence of a chunk line proposes the block that would put
+/// that line back at its place in the chunk. Those blocks are first checked
+/// by hash for the original lines (an exact move), then scored by the
+/// share of the chunk's lines they contain (a fuzzy match). Ties are broken
+/// by distance from the old position. Line numbers in the chunks are
+/// 1-indexed.
+pub fn reanchor_chunks(chunks: &[Chunk], lines: &[String]) -> ReanchorReport {
+    let source_hash = hash_lines(lines);
+    let index = LineIndex::new(lines);
+    let mut report = ReanchorReport::default();
+    
+    for chunk in chunks {
+        let start = chunk.start_line.saturating_sub(1);
+        let end = chunk.end_line.saturating_sub(1);
+        if !chunk.is_stale(&source_hash, lines.get(start..=end)) {
+            continue;
+        }
+        
+        let found = find_exact(chunk, lines, &index)
+            .map(|start| (start, MatchKind::Exact))
+            .or_else(|| find_fuzzy(chunk, lines, &index).map(|start| (start, MatchKind::Fuzzy)));
+        
+        match found {
+            Some((new_start, kind)) => {
+                let len = match kind {
+                    MatchKind::Exact => chunk.end_line - chunk.start_line + 1,
+                    MatchKind::Fuzzy => chunk.content.lines().count().max(1),
+                };
+                let new_end = new_start + len - 1;
+                
+                let mut moved = chunk.clone();
+                moved.start_line = new_start + 1;
+                moved.end_line = new_end + 1;
+                moved.source_hash = source_hash.clone();
+                moved.lines_hash = hash_lines(&lines[new_start..=new_end]);
+                
+                report.placed.push(Placement {
+                    chunk: moved,
+                    old_range: (chunk.start_line, chunk.end_line),
+                    kind,
+                });
+            },
+            None => report.unplaced.push(chunk.clone()),
+        }
+    }
+    
+    report
+}
+
+/// Where each line of a file occurs, by its trimmed text
+struct LineIndex<'a> {
+    /// 0-indexed positions of each line, in ascending order
+    positions: HashMap<&'a str, Vec<usize>>,
+    
+    /// Number of lines in the file
+    len: usize,
+}
+
+impl<'a> LineIndex<'a> {
+    /// Index the lines of a file
+    fn new(lines: &'a [String]) -> Self {
+        let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (position, line) in lines.iter().enumerate() {
+            positions.entry(line.trim()).or_default().push(position);
+        }
+        Self { positions, len: lines.len() }
+    }
+    
+    /// Positions of a (trimmed) line
+    fn positions(&self, line: &str) -> &[usize] {
+        self.positions.get(line).map_or(&[], Vec::as_slice)
+    }
+    
+    /// Number of times a line occurs in the block of lines `start..end`
+    fn count_in(&self, line: &str, start: usize, end: usize) -> usize {
+        let positions = self.positions(line);
+        positions.partition_point(|&position| position < end) - positions.partition_point(|&position| position < start)
+    }
+    
+    /// Starts of the `len`-line blocks that line the chunk's lines up with the file
+    /// 
+    /// Lines that occur at most `MAX_ANCHOR_OCCURRENCES` times anchor the
+    /// chunk, as unique lines do in a patience diff; blank lines and lines
+    /// repeated all over the file only propose blocks when nothing else does.
+    /// Candidates are ordered by distance from `old_start`.
+    fn candidates(&self, wanted: &[&str], len: usize, old_start: usize) -> Vec<usize> {
+        if self.len < len {
+            return Vec::new();
+        }
+        let last_start = self.len - len;
+        
+        let proposals = |anchor: &dyn Fn(&[usize]) -> bool| {
+            let mut starts: Vec<usize> = wanted
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| !line.is_empty())
+                .map(|(offset, line)| (offset, self.positions(line)))
+                .filter(|(_, positions)| anchor(positions))
+                .flat_map(|(offset, positions)| positions.iter().map(move |&position| position.saturating_sub(offset).min(last_start)))
+                .collect();
+            starts.sort_by_key(|&start| (start.abs_diff(old_start), start));
+            starts.dedup();
+            starts
+        };
+        
+        let anchored = proposals(&|positions| !positions.is_empty() && positions.len() <= MAX_ANCHOR_OCCURRENCES);
+        if anchored.is_empty() {
+            proposals(&|positions| !positions.is_empty())
+        } else {
+            anchored
+        }
+    }
+}
+
+/// Find the 0-indexed start of a block whose hash matches the chunk's original lines
+fn find_exact(chunk: &Chunk, lines: &[String], index: &LineIndex) -> Option<usize> {
+    let len = chunk.end_line.checked_sub(chunk.start_line)? + 1;
+    let wanted: Vec<&str> = chunk.content.lines().map(str::trim).collect();
+    
+    index
+        .candidates(&wanted, len, chunk.start_line.saturating_sub(1))
+        .into_iter()
+        .find(|&start| hash_lines(&lines[start..start + len]) == chunk.lines_hash)
+}
+
+/// Find the 0-indexed start of the block most similar to the chunk's content
+fn find_fuzzy(chunk: &Chunk, lines: &[String], index: &LineIndex) -> Option<usize> {
+    let wanted: Vec<&str> = chunk.content.lines().map(str::trim).collect();
+    let len = wanted.len();
+    if len == 0 || lines.len() < len {
+        return None;
+    }
+    
+    let mut wanted_counts: HashMap<&str, usize> = HashMap::new();
+    for line in &wanted {
+        *wanted_counts.entry(line).or_default() += 1;
+    }
+    
+    // Candidates come closest first, so only a higher share replaces the best so far
+    let mut best: Option<(usize, usize)> = None;
+    for start in index.candidates(&wanted, len, chunk.start_line.saturating_sub(1)) {
+        // Lines of the block that also appear in the chunk, ignoring order
+        let common: usize = wanted_counts
+            .iter()
+            .map(|(line, &count)| count.min(index.count_in(line, start, start + len)))
+            .sum();
+        if best.is_none_or(|(best_common, _)| common > best_common) {
+            best = Some((common, start));
+        }
+    }
+    
+    best.filter(|&(common, _)| common as f64 / len as f64 >= MIN_FUZZY_SIMILARITY)
+        .map(|(_, start)| start)
+}
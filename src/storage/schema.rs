@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{decode_labels, encode_labels};
+
+/// Version of the chunk schema written by this build
+/// 
+/// Bump this and add a `Migration` to `MIGRATIONS` whenever the stored
+/// chunk fields change.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// Newest version that chunk files were written at without recording it
+const LAST_UNVERSIONED: u32 = 3;
+
+/// Value given to a field for chunks stored before the field existed
+#[derive(Debug, Clone, Copy)]
+pub enum FieldDefault {
+    /// An empty string
+    EmptyText,
+    /// No value (`NULL` in SQLite, `null` in JSON, an empty CSV cell)
+    Missing,
+}
+
+/// A chunk field introduced by a migration
+#[derive(Debug)]
+pub struct AddedField {
+    /// Field (and column) name
+    pub name: &'static str,
+    /// SQLite column definition
+    pub sql: &'static str,
+    /// Value used for chunks written before the field existed
+    pub default: FieldDefault,
+}
+
+/// A chunk field whose stored text changes encoding in a migration
+#[derive(Debug)]
+pub struct RewrittenField {
+    /// Field (and column) name
+    pub name: &'static str,
+    /// Convert stored text to the new encoding
+    /// 
+    /// Must leave text that is already in the new encoding unchanged.
+    pub rewrite: fn(&str) -> String,
+}
+
+/// An upgrade from the previous schema version
+#[derive(Debug)]
+pub struct Migration {
+    /// Version the chunk file has after this migration
+    pub version: u32,
+    /// What changed, for upgrade messages
+    pub description: &'static str,
+    /// Fields added to every chunk
+    pub added_fields: &'static [AddedField],
+    /// Existing fields whose stored text is converted
+    pub rewritten_fields: &'static [RewrittenField],
+}
+
+/// Every migration since version 1, oldest first
+/// 
+/// Version 1 is the original set of fields: `id`, `file_path`, `start_line`,
+/// `end_line`, `content`, `timestamp`, `edited` and `labels`.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "source fingerprints",
+        added_fields: &[
+            AddedField { name: "source_hash", sql: "TEXT NOT NULL DEFAULT ''", default: FieldDefault::EmptyText },
+            AddedField { name: "lines_hash", sql: "TEXT NOT NULL DEFAULT ''", default: FieldDefault::EmptyText },
+        ],
+        rewritten_fields: &[],
+    },
+    Migration {
+        version: 3,
+        description: "column boundaries",
+        added_fields: &[
+            AddedField { name: "start_column", sql: "INTEGER", default: FieldDefault::Missing },
+            AddedField { name: "end_column", sql: "INTEGER", default: FieldDefault::Missing },
+        ],
+        rewritten_fields: &[],
+    },
+    Migration {
+        version: 4,
+        description: "JSON-encoded labels",
+        added_fields: &[],
+        rewritten_fields: &[
+            RewrittenField { name: "labels", rewrite: reencode_labels },
+        ],
+    },
+    Migration {
+        version: 5,
+        description: "chunk metadata",
+        added_fields: &[
+            AddedField { name: "metadata", sql: "TEXT NOT NULL DEFAULT ''", default: FieldDefault::EmptyText },
+        ],
+        rewritten_fields: &[],
+    },
+    Migration {
+        version: 6,
+        description: "sliding windows",
+        added_fields: &[
+            AddedField { name: "window_id", sql: "TEXT NOT NULL DEFAULT ''", default: FieldDefault::EmptyText },
+        ],
+        rewritten_fields: &[],
+    },
+];
+
+/// Convert `|`-joined labels to the JSON array encoding
+fn reencode_labels(text: &str) -> String {
+    encode_labels(&decode_labels(text))
+}
+
+impl FieldDefault {
+    /// The default as a CSV cell
+    pub fn as_csv(&self) -> &'static str {
+        ""
+    }
+    
+    /// The default as a JSON value
+    pub fn as_json(&self) -> Value {
+        match self {
+            FieldDefault::EmptyText => Value::String(String::new()),
+            FieldDefault::Missing => Value::Null,
+        }
+    }
+}
+
+/// Migrations needed to bring a chunk file at `version` up to date
+pub fn migrations_from(version: u32) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |migration| migration.version > version)
+}
+
+/// Guess the version of a chunk file written before versions were recorded
+/// 
+/// `has_field` reports whether the stored chunks have a given field. The
+/// result is the newest unversioned schema whose fields are all present.
+pub fn infer_version(has_field: impl Fn(&str) -> bool) -> u32 {
+    MIGRATIONS
+        .iter()
+        .take_while(|migration| migration.version <= LAST_UNVERSIONED)
+        .take_while(|migration| migration.added_fields.iter().all(|field| has_field(field.name)))
+        .last()
+        .map_or(1, |migration| migration.version)
+}
+
+/// Fail with a clear message if a chunk file was written by a newer packrat
+pub fn ensure_supported(path: &Path, version: u32) -> Result<()> {
+    if version > SCHEMA_VERSION {
+        bail!(
+            "{} uses chunk schema version {}, but this version of packrat only understands up to version {}. \
+             Upgrade packrat to open it",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+/// Bring a chunk stored as a JSON object from `version` up to date
+pub fn upgrade_json_chunk(chunk: &mut Map<String, Value>, version: u32) {
+    for migration in migrations_from(version) {
+        for field in migration.added_fields {
+            chunk.entry(field.name).or_insert_with(|| field.default.as_json());
+        }
+        for field in migration.rewritten_fields {
+            if let Some(Value::String(text)) = chunk.get_mut(field.name) {
+                *text = (field.rewrite)(text);
+            }
+        }
+    }
+}
+
+/// Copy a chunk file aside before migrating it, returning the backup path
+/// 
+/// Backups are named after the version they hold (e.g. `chunks.csv.v1.bak`)
+/// and never overwrite an earlier backup.
+pub fn backup(path: &Path, version: u32) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "chunks".to_string());
+    
+    let mut backup_path = path.with_file_name(format!("{}.v{}.bak", file_name, version));
+    let mut attempt = 1;
+    while backup_path.exists() {
+        attempt += 1;
+        backup_path = path.with_file_name(format!("{}.v{}.{}.bak", file_name, version, attempt));
+    }
+    
+    fs::copy(path, &backup_path)
+        .with_context(|| format!("Failed to back up {} to {}", path.display(), backup_path.display()))?;
+    
+    Ok(backup_path)
+}
+
+/// Message reported after a chunk file was migrated
+pub fn upgrade_message(path: &Path, from: u32, backup_path: &Path) -> String {
+    let changes: Vec<&str> = migrations_from(from).map(|migration| migration.description).collect();
+    format!(
+        "Upgraded {} from schema version {} to {} ({}); the old file was saved as {}",
+        path.display(),
+        from,
+        SCHEMA_VERSION,
+        changes.join(", "),
+        backup_path.display()
+    )
+}
@@ -0,0 +1,133 @@
+/// Where a chunk sits in its source file
+/// 
+/// Lines are 1-indexed, like in `Chunk`. Columns are 0-indexed character
+/// offsets: `start_column` is the first character of the chunk and
+/// `end_column` is one past its last character. `None` means the chunk
+/// starts at the beginning of its first line or runs to the end of its last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkSpan {
+    /// First line of the chunk (1-indexed)
+    pub start_line: usize,
+    /// First character on the first line, or `None` for the whole line
+    pub start_column: Option<usize>,
+    /// Last line of the chunk (1-indexed)
+    pub end_line: usize,
+    /// One past the last character on the last line, or `None` for the whole line
+    pub end_column: Option<usize>,
+}
+
+impl ChunkSpan {
+    /// A span covering whole lines
+    pub fn lines(start_line: usize, end_line: usize) -> Self {
+        Self {
+            start_line,
+            start_column: None,
+            end_line,
+            end_column: None,
+        }
+    }
+    
+    /// Whether the span starts or ends part-way through a line
+    pub fn is_partial(&self) -> bool {
+        self.start_column.is_some() || self.end_column.is_some()
+    }
+    
+    /// Whether two spans share at least one character
+    pub fn overlaps(&self, other: &ChunkSpan) -> bool {
+        self.start_key() < other.end_key() && other.start_key() < self.end_key()
+    }
+    
+    /// Whether the character at a 1-indexed line and 0-indexed column is inside the span
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        self.start_key() <= (line, column) && (line, column) < self.end_key()
+    }
+    
+    /// Character columns of a line covered by the span, as `[start, end)`
+    /// 
+    /// `line` is 1-indexed and `line_length` is the line's length in characters.
+    /// Returns `None` if the span doesn't touch the line. Empty lines inside a
+    /// span are reported as `(0, 0)`.
+    pub fn columns_on_line(&self, line: usize, line_length: usize) -> Option<(usize, usize)> {
+        if line < self.start_line || line > self.end_line {
+            return None;
+        }
+        
+        let start = if line == self.start_line { self.start_column.unwrap_or(0) } else { 0 };
+        let end = if line == self.end_line { self.end_column.unwrap_or(line_length) } else { line_length };
+        let (start, end) = (start.min(line_length), end.min(line_length));
+        
+        (start < end || line_length == 0).then_some((start, end))
+    }
+    
+    /// Start position as (line, column) for ordering
+    fn start_key(&self) -> (usize, usize) {
+        (self.start_line, self.start_column.unwrap_or(0))
+    }
+    
+    /// Exclusive end position as (line, column) for ordering
+    fn end_key(&self) -> (usize, usize) {
+        match self.end_column {
+            Some(column) => (self.end_line, column),
+            None => (self.end_line + 1, 0),
+        }
+    }
+}
+
+/// Percentage of a file covered by chunk spans
+/// 
+/// Every line counts equally. A line that is only partly covered counts by
+/// the share of its characters that are covered, so whole-line chunks give
+/// the same result as counting chunked lines. `line_lengths` holds the length
+/// of each line in characters.
+pub fn coverage_percentage(spans: &[ChunkSpan], line_lengths: &[usize]) -> f64 {
+    if line_lengths.is_empty() {
+        return 0.0;
+    }
+    
+    // Covered column intervals for every line
+    let mut covered: Vec<Vec<(usize, usize)>> = vec![Vec::new(); line_lengths.len()];
+    for span in spans {
+        let first = span.start_line.max(1);
+        let last = span.end_line.min(line_lengths.len());
+        for line in first..=last {
+            if let Some(columns) = span.columns_on_line(line, line_lengths[line - 1]) {
+                covered[line - 1].push(columns);
+            }
+        }
+    }
+    
+    let mut total = 0.0;
+    for (intervals, &length) in covered.iter_mut().zip(line_lengths) {
+        if intervals.is_empty() {
+            continue;
+        }
+        if length == 0 {
+            total += 1.0;
+            continue;
+        }
+        
+        // Merge overlapping intervals and count each character once
+        intervals.sort_unstable();
+        let mut count = 0;
+        let mut reached = 0;
+        for &(start, end) in intervals.iter() {
+            let start = start.max(reached);
+            if end > start {
+                count += end - start;
+                reached = end;
+            }
+        }
+        total += count as f64 / length as f64;
+    }
+    
+    (total / line_lengths.len() as f64) * 100.0
+}
+
+/// Length in characters of every line, for use with `coverage_percentage`
+pub fn line_lengths<I, S>(lines: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    lines.into_iter().map(|line| line.as_ref().chars().count()).collect()
+}
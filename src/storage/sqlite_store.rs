@@ -0,0 +1,451 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::schema::{self, SCHEMA_VERSION};
+use super::{decode_metadata, encode_labels, encode_metadata, Chunk, ChunkSpan, ChunkStore, CsvChunkStore};
+
+/// Magic bytes at the start of every SQLite database file
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Columns selected when loading full chunks, in `row_to_chunk` order
+const CHUNK_COLUMNS: &str =
+    "id, file_path, start_line, end_line, content, timestamp, edited, labels, source_hash, lines_hash, \
+     start_column, end_column, metadata, window_id";
+
+/// Chunk store backed by an embedded SQLite database
+/// 
+/// Chunks are queried on demand instead of being cached in memory, and
+/// lookups by file use an index on `(file_path, start_line, end_line)`.
+/// The schema version is kept in SQLite's `user_version` pragma.
+pub struct SqliteChunkStore {
+    /// Open database connection
+    conn: Connection,
+    
+    /// Notes about upgrades made while opening the database
+    warnings: Vec<String>,
+}
+
+impl SqliteChunkStore {
+    /// Open (or create) a SQLite chunk store
+    /// 
+    /// When a new database is created next to a CSV file with the same name
+    /// (e.g. `chunks.db` and `chunks.csv`), the CSV chunks are imported.
+    /// Databases from an older schema version are backed up and migrated;
+    /// databases from a newer version are refused.
+    pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        let db_path = db_path.as_ref();
+        let existed = db_path.exists();
+        
+        if !existed {
+            // Create parent directories if they don't exist
+            if let Some(parent) = db_path.parent() {
+                fs::create_dir_all(parent).context("Failed to create parent directories for SQLite database")?;
+            }
+        } else if !Self::is_sqlite_file(db_path)? {
+            bail!(
+                "{} is not a SQLite database. Point chunk_file at a new .db file to migrate existing CSV chunks into it",
+                db_path.display()
+            );
+        }
+        
+        let conn = Connection::open(db_path)
+            .with_context(|| format!("Failed to open SQLite database: {}", db_path.display()))?;
+        
+        let mut store = Self { conn, warnings: Vec::new() };
+        
+        // A database without a chunks table or version was never set up, or
+        // its setup didn't finish, so the CSV migration runs (again) with it
+        if store.column_names()?.is_empty() && store.user_version()? == 0 {
+            let csv_path = db_path.with_extension("csv");
+            let csv_path = (csv_path != db_path && csv_path.exists()).then_some(csv_path);
+            if let Err(e) = store.initialize(csv_path.as_deref()) {
+                // Leave no half-made database behind to be mistaken for a migrated one
+                drop(store);
+                if !existed {
+                    let _ = fs::remove_file(db_path);
+                }
+                return Err(e);
+            }
+            return Ok(store);
+        }
+        
+        let version = store.schema_version()?;
+        schema::ensure_supported(db_path, version)?;
+        
+        if version < SCHEMA_VERSION {
+            let backup_path = schema::backup(db_path, version)?;
+            store.migrate(version)
+                .with_context(|| format!("Failed to upgrade SQLite database {}", db_path.display()))?;
+            store.warnings.push(schema::upgrade_message(db_path, version, &backup_path));
+        }
+        Self::create_schema(&store.conn)?;
+        
+        Ok(store)
+    }
+    
+    /// Create the schema and import the chunks of `csv_path`, if given, in one transaction
+    fn initialize(&mut self, csv_path: Option<&Path>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        Self::create_schema(&tx)?;
+        if let Some(csv_path) = csv_path {
+            Self::import_csv(&tx, csv_path)
+                .with_context(|| format!("Failed to migrate chunks from {}", csv_path.display()))?;
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Insert every chunk of a CSV chunk file through an open transaction
+    /// 
+    /// Chunks whose id already exists in the database are skipped.
+    /// Returns the number of chunks imported.
+    fn import_csv(tx: &Connection, csv_path: &Path) -> Result<usize> {
+        let csv_store = CsvChunkStore::open(csv_path)?;
+        
+        let mut chunks = Vec::new();
+        csv_store.for_each_chunk(&mut |chunk| chunks.push(chunk.clone()))?;
+        
+        let mut imported = 0;
+        let mut stmt = tx.prepare(&format!(
+            "INSERT OR IGNORE INTO chunks ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            CHUNK_COLUMNS
+        ))?;
+        for chunk in &chunks {
+            imported += Self::insert_with(&mut stmt, chunk)?;
+        }
+        
+        Ok(imported)
+    }
+    
+    /// Create the chunks table and its indexes if they don't exist yet
+    fn create_schema(conn: &Connection) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS chunks (
+                id         TEXT PRIMARY KEY NOT NULL,
+                file_path  TEXT NOT NULL,
+                start_line INTEGER NOT NULL,
+                end_line   INTEGER NOT NULL,
+                content    TEXT NOT NULL,
+                timestamp  INTEGER NOT NULL,
+                edited     INTEGER NOT NULL,
+                labels      TEXT NOT NULL,
+                source_hash  TEXT NOT NULL DEFAULT '',
+                lines_hash   TEXT NOT NULL DEFAULT '',
+                start_column INTEGER,
+                end_column   INTEGER,
+                metadata     TEXT NOT NULL DEFAULT '',
+                window_id    TEXT NOT NULL DEFAULT ''
+            );
+            CREATE INDEX IF NOT EXISTS idx_chunks_file_lines
+                ON chunks (file_path, start_line, end_line);"
+        ).context("Failed to create SQLite chunk schema")?;
+        
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(())
+    }
+    
+    /// Read the schema version of an existing database
+    /// 
+    /// Databases written before versions were recorded are dated by the
+    /// columns of their chunks table.
+    fn schema_version(&self) -> Result<u32> {
+        let version = self.user_version()?;
+        if version > 0 {
+            return Ok(version);
+        }
+        
+        let columns = self.column_names()?;
+        Ok(schema::infer_version(|field| columns.iter().any(|column| column == field)))
+    }
+    
+    /// The version recorded in the `user_version` pragma (0 if none is)
+    fn user_version(&self) -> Result<u32> {
+        Ok(self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+    
+    /// Add the columns introduced after `version` in a single transaction
+    fn migrate(&mut self, version: u32) -> Result<()> {
+        // Columns may already exist in databases that predate versioning
+        let columns = self.column_names()?;
+        let missing: Vec<_> = schema::migrations_from(version)
+            .flat_map(|migration| migration.added_fields)
+            .filter(|field| !columns.iter().any(|column| column == field.name))
+            .collect();
+        
+        let tx = self.conn.transaction()?;
+        for field in missing {
+            tx.execute_batch(&format!("ALTER TABLE chunks ADD COLUMN {} {}", field.name, field.sql))
+                .with_context(|| format!("Failed to add {} column to SQLite chunk schema", field.name))?;
+        }
+        
+        for field in schema::migrations_from(version).flat_map(|migration| migration.rewritten_fields) {
+            let rows = {
+                let mut stmt = tx.prepare(&format!("SELECT id, {} FROM chunks", field.name))?;
+                let rows = stmt
+                    .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                rows
+            };
+            for (id, text) in rows {
+                tx.execute(
+                    &format!("UPDATE chunks SET {} = ?2 WHERE id = ?1", field.name),
+                    params![id, (field.rewrite)(&text)],
+                )?;
+            }
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    /// Names of the columns of the chunks table (empty if it doesn't exist)
+    fn column_names(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT name FROM pragma_table_info('chunks')")?;
+        let names = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        Ok(names)
+    }
+    
+    /// Check whether an existing file starts with the SQLite header
+    fn is_sqlite_file(path: &Path) -> Result<bool> {
+        let mut header = [0u8; 16];
+        let mut file = File::open(path)?;
+        
+        // Empty files are fine - SQLite will initialize them
+        let read = file.read(&mut header)?;
+        Ok(read == 0 || (read == SQLITE_HEADER.len() && header[..] == *SQLITE_HEADER))
+    }
+    
+    /// Run a prepared insert statement for a chunk, returning the number of inserted rows
+    fn insert_with(stmt: &mut rusqlite::Statement<'_>, chunk: &Chunk) -> Result<usize> {
+        let labels = encode_labels(&chunk.labels);
+        let inserted = stmt.execute(params![
+            chunk.id,
+            chunk.file_path.to_string_lossy(),
+            chunk.start_line as i64,
+            chunk.end_line as i64,
+            chunk.content,
+            chunk.timestamp as i64,
+            chunk.edited,
+            labels,
+            chunk.source_hash,
+            chunk.lines_hash,
+            chunk.start_column.map(|column| column as i64),
+            chunk.end_column.map(|column| column as i64),
+            encode_metadata(&chunk.metadata),
+            chunk.window_id,
+        ])?;
+        Ok(inserted)
+    }
+    
+    /// Update a chunk through a connection or open transaction
+    fn update_with(conn: &Connection, chunk: &Chunk) -> Result<()> {
+        let labels = encode_labels(&chunk.labels);
+        let updated = conn.execute(
+            "UPDATE chunks
+                SET file_path = ?2, start_line = ?3, end_line = ?4, content = ?5,
+                    timestamp = ?6, edited = ?7, labels = ?8,
+                    source_hash = ?9, lines_hash = ?10, start_column = ?11, end_column = ?12,
+                    metadata = ?13, window_id = ?14
+              WHERE id = ?1",
+            params![
+                chunk.id,
+                chunk.file_path.to_string_lossy(),
+                chunk.start_line as i64,
+                chunk.end_line as i64,
+                chunk.content,
+                chunk.timestamp as i64,
+                chunk.edited,
+                labels,
+                chunk.source_hash,
+                chunk.lines_hash,
+                chunk.start_column.map(|column| column as i64),
+                chunk.end_column.map(|column| column as i64),
+                encode_metadata(&chunk.metadata),
+                chunk.window_id,
+            ],
+        )?;
+        
+        if updated == 0 {
+            return Err(anyhow!("No chunk with id {}", chunk.id));
+        }
+        
+        Ok(())
+    }
+    
+    /// Convert a row selected with `CHUNK_COLUMNS` back into a chunk
+    fn row_to_chunk(row: &Row<'_>) -> rusqlite::Result<Chunk> {
+        let file_path: String = row.get(1)?;
+        let labels: String = row.get(7)?;
+        let metadata: String = row.get(12)?;
+        let metadata = decode_metadata(&metadata).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(12, rusqlite::types::Type::Text, e.into())
+        })?;
+        // Labels are always JSON here (older encodings are rewritten when the
+        // database is migrated), so anything else is corruption, not legacy data
+        let labels: Vec<String> = if labels.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&labels).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, e.into())
+            })?
+        };
+        
+        Ok(Chunk {
+            id: row.get(0)?,
+            file_path: PathBuf::from(file_path),
+            start_line: row.get::<_, i64>(2)? as usize,
+            end_line: row.get::<_, i64>(3)? as usize,
+            content: row.get(4)?,
+            timestamp: row.get::<_, i64>(5)? as u64,
+            edited: row.get(6)?,
+            labels,
+            source_hash: row.get(8)?,
+            lines_hash: row.get(9)?,
+            start_column: row.get::<_, Option<i64>>(10)?.map(|column| column as usize),
+            end_column: row.get::<_, Option<i64>>(11)?.map(|column| column as usize),
+            metadata,
+            window_id: row.get(13)?,
+        })
+    }
+    
+    /// Convert a row of `start_line, start_column, end_line, end_column` into a span
+    fn row_to_span(row: &Row<'_>, offset: usize) -> rusqlite::Result<ChunkSpan> {
+        Ok(ChunkSpan {
+            start_line: row.get::<_, i64>(offset)? as usize,
+            start_column: row.get::<_, Option<i64>>(offset + 1)?.map(|column| column as usize),
+            end_line: row.get::<_, i64>(offset + 2)? as usize,
+            end_column: row.get::<_, Option<i64>>(offset + 3)?.map(|column| column as usize),
+        })
+    }
+}
+
+impl ChunkStore for SqliteChunkStore {
+    fn add(&mut self, chunk: Chunk) -> Result<()> {
+        self.add_many(vec![chunk])
+    }
+    
+    fn add_many(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(&format!(
+                "INSERT INTO chunks ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                CHUNK_COLUMNS
+            ))?;
+            for chunk in &chunks {
+                Self::insert_with(&mut stmt, chunk)
+                    .with_context(|| format!("Failed to insert chunk {}", chunk.id))?;
+            }
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    fn update(&mut self, chunk: Chunk) -> Result<()> {
+        Self::update_with(&self.conn, &chunk)
+    }
+    
+    fn update_many(&mut self, chunks: Vec<Chunk>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        for chunk in &chunks {
+            Self::update_with(&tx, chunk)?;
+        }
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    fn delete(&mut self, id: &str) -> Result<Option<Chunk>> {
+        let tx = self.conn.transaction()?;
+        let removed = tx.query_row(
+            &format!("SELECT {} FROM chunks WHERE id = ?1", CHUNK_COLUMNS),
+            params![id],
+            Self::row_to_chunk,
+        ).optional()?;
+        
+        if removed.is_some() {
+            tx.execute("DELETE FROM chunks WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+        
+        Ok(removed)
+    }
+    
+    fn get(&self, id: &str) -> Result<Option<Chunk>> {
+        Ok(self.conn.query_row(
+            &format!("SELECT {} FROM chunks WHERE id = ?1", CHUNK_COLUMNS),
+            params![id],
+            Self::row_to_chunk,
+        ).optional()?)
+    }
+    
+    fn chunks_for_file(&self, file_path: &Path) -> Result<Vec<Chunk>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM chunks WHERE file_path = ?1 ORDER BY rowid",
+            CHUNK_COLUMNS
+        ))?;
+        let chunks = stmt
+            .query_map(params![file_path.to_string_lossy()], Self::row_to_chunk)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        Ok(chunks)
+    }
+    
+    fn chunk_spans(&self, file_path: &Path) -> Result<Vec<ChunkSpan>> {
+        // Looked up and ordered through the (file_path, start_line, end_line) index
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT start_line, start_column, end_line, end_column FROM chunks
+              WHERE file_path = ?1 ORDER BY start_line, end_line"
+        )?;
+        let spans = stmt
+            .query_map(params![file_path.to_string_lossy()], |row| Self::row_to_span(row, 0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        Ok(spans)
+    }
+    
+    fn all_chunk_spans(&self) -> Result<HashMap<PathBuf, Vec<ChunkSpan>>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT file_path, start_line, start_column, end_line, end_column FROM chunks
+              ORDER BY file_path, start_line, end_line"
+        )?;
+        let mut rows = stmt.query([])?;
+        
+        let mut spans: HashMap<PathBuf, Vec<ChunkSpan>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let file_path: String = row.get(0)?;
+            spans
+                .entry(PathBuf::from(file_path))
+                .or_default()
+                .push(Self::row_to_span(row, 1)?);
+        }
+        
+        Ok(spans)
+    }
+    
+    fn for_each_chunk(&self, f: &mut dyn FnMut(&Chunk)) -> Result<()> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM chunks ORDER BY rowid",
+            CHUNK_COLUMNS
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            f(&Self::row_to_chunk(row)?);
+        }
+        
+        Ok(())
+    }
+    
+    fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
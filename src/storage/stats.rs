@@ -0,0 +1,53 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::Chunk;
+
+/// Counts describing a set of stored chunks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkStats {
+    /// Number of chunks
+    pub chunks: usize,
+    /// Number of source files with at least one chunk
+    pub files: usize,
+    /// Chunks whose content was edited before saving
+    pub edited: usize,
+    /// Chunks that start or end part-way through a line
+    pub partial: usize,
+    /// Sliding-window passes, each counted once however many windows it saved
+    pub window_passes: usize,
+    /// Chunks without any label
+    pub unlabelled: usize,
+    /// Chunks per label
+    pub labels: BTreeMap<String, usize>,
+    /// Chunks per metadata field they have a value for
+    pub metadata_fields: BTreeMap<String, usize>,
+}
+
+impl ChunkStats {
+    /// Count the chunks
+    pub fn collect(chunks: &[Chunk]) -> Self {
+        let mut stats = ChunkStats { chunks: chunks.len(), ..Default::default() };
+        let mut files = HashSet::new();
+        let mut windows = HashSet::new();
+        
+        for chunk in chunks {
+            files.insert(&chunk.file_path);
+            if !chunk.window_id.is_empty() {
+                windows.insert(&chunk.window_id);
+            }
+            stats.edited += usize::from(chunk.edited);
+            stats.partial += usize::from(chunk.span().is_partial());
+            stats.unlabelled += usize::from(chunk.labels.is_empty());
+            for label in &chunk.labels {
+                *stats.labels.entry(label.clone()).or_default() += 1;
+            }
+            for key in chunk.metadata.keys() {
+                *stats.metadata_fields.entry(key.clone()).or_default() += 1;
+            }
+        }
+        
+        stats.files = files.len();
+        stats.window_passes = windows.len();
+        stats
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::{hash_lines, same_window, Chunk};
+
+/// Something wrong with a stored chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The chunk's source file can't be read
+    MissingFile,
+    /// The chunk's lines run past the end of its source file, which has `lines` lines
+    OutOfRange { lines: usize },
+    /// The chunk's lines changed in the source file since it was saved
+    Stale,
+    /// The chunk shares characters with chunk `other`, which isn't a window of the same pass
+    Overlap { other: String },
+    /// The chunk has more tokens than the budget
+    OverBudget { tokens: usize, max_tokens: usize },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingFile => write!(f, "source file is missing"),
+            Problem::OutOfRange { lines } => write!(f, "runs past the end of the file ({} lines)", lines),
+            Problem::Stale => write!(f, "source lines changed since the chunk was saved"),
+            Problem::Overlap { other } => write!(f, "overlaps chunk {}", other),
+            Problem::OverBudget { tokens, max_tokens } => write!(f, "{} tokens, over the budget of {}", tokens, max_tokens),
+        }
+    }
+}
+
+/// A problem found with one chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkProblem {
+    /// Id of the chunk
+    pub id: String,
+    /// Source file of the chunk, as stored
+    pub file_path: PathBuf,
+    /// First line of the chunk (1-indexed)
+    pub start_line: usize,
+    /// Last line of the chunk (1-indexed)
+    pub end_line: usize,
+    /// What's wrong with it
+    pub problem: Problem,
+}
+
+impl fmt::Display for ChunkProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}-{} ({}): {}", self.file_path.display(), self.start_line, self.end_line, self.id, self.problem)
+    }
+}
+
+/// Check stored chunks against their source files and the token budget
+/// 
+/// Source files are looked up under `root_dir`. `count` gives the tokens
+/// of a chunk as it's sent (e.g. rendered in the prompt template). Chunks
+/// are checked file by file, in path order, then by position.
+pub fn validate_chunks(
+    chunks: &[Chunk],
+    root_dir: &Path,
+    max_tokens: usize,
+    count: impl Fn(&Chunk) -> usize,
+) -> Vec<ChunkProblem> {
+    let mut files: BTreeMap<&Path, Vec<&Chunk>> = BTreeMap::new();
+    for chunk in chunks {
+        files.entry(chunk.file_path.as_path()).or_default().push(chunk);
+    }
+    
+    let mut problems = Vec::new();
+    for (file_path, mut chunks) in files {
+        chunks.sort_by_key(|chunk| (chunk.start_line, chunk.start_column, chunk.end_line));
+        let report = |problems: &mut Vec<ChunkProblem>, chunk: &Chunk, problem: Problem| {
+            problems.push(ChunkProblem {
+                id: chunk.id.clone(),
+                file_path: chunk.file_path.clone(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                problem,
+            });
+        };
+        
+        // The file's lines and their fingerprint, if it can be read
+        let source = std::fs::read_to_string(root_dir.join(file_path)).ok().map(|content| {
+            let lines: Vec<String> = content.lines().map(String::from).collect();
+            let source_hash = hash_lines(&lines);
+            (lines, source_hash)
+        });
+        
+        for (index, chunk) in chunks.iter().enumerate() {
+            match &source {
+                None => report(&mut problems, chunk, Problem::MissingFile),
+                Some((lines, _)) if chunk.start_line == 0 || chunk.start_line > chunk.end_line || chunk.end_line > lines.len() => {
+                    report(&mut problems, chunk, Problem::OutOfRange { lines: lines.len() });
+                },
+                Some((lines, source_hash)) => {
+                    if chunk.is_stale(source_hash, lines.get(chunk.start_line - 1..chunk.end_line)) {
+                        report(&mut problems, chunk, Problem::Stale);
+                    }
+                },
+            }
+            
+            // Each overlapping pair is reported once, on the later chunk
+            let earlier = chunks[..index]
+                .iter()
+                .find(|other| other.span().overlaps(&chunk.span()) && !same_window(&other.window_id, &chunk.window_id));
+            if let Some(other) = earlier {
+                report(&mut problems, chunk, Problem::Overlap { other: other.id.clone() });
+            }
+            
+            let tokens = count(chunk);
+            if tokens > max_tokens {
+                report(&mut problems, chunk, Problem::OverBudget { tokens, max_tokens });
+            }
+        }
+    }
+    
+    problems
+}
@@ -5,7 +5,7 @@ use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, ListState, Wra
 use ratatui::Frame;
 use std::fmt::Write;
 
-use crate::app::state::{AppMode, AppState};
+use crate::app::state::{AppMode, AppState, LabelPrompt, MetadataPrompt, MetadataPurpose};
 use packrat::editor::Editor;
 use crate::explorer::Explorer;
 use crate::viewer::Viewer;
@@ -23,6 +23,14 @@ pub fn render(frame: &mut Frame, state: &AppState, explorer: &Explorer, viewer:
     if let Some(message) = &state.debug_message {
         render_debug_overlay(frame, message);
     }
+    
+    // Render the label prompt and metadata form on top of everything else
+    if let Some(prompt) = &state.label_prompt {
+        render_label_prompt(frame, prompt);
+    }
+    if let Some(prompt) = &state.metadata_prompt {
+        render_metadata_prompt(frame, prompt);
+    }
 }
 
 /// Render the explorer mode UI
@@ -74,8 +82,12 @@ fn render_viewer_mode(frame: &mut Frame, state: &AppState, viewer: &Viewer) {
 
 /// Render the file explorer content
 fn render_explorer_content(frame: &mut Frame, area: Rect, explorer: &Explorer) {
-    // Create a title with a square character on both sides
-    let title_text = "□ Packrat □";
+    // Create a title with a square character on both sides, noting any label filter
+    let title_text = if explorer.label_filter().is_empty() {
+        "□ Packrat □".to_string()
+    } else {
+        format!("□ Packrat [labels: {}] □", explorer.label_filter().join(", "))
+    };
     
     // Center align the title
     let centered_title = Line::from(title_text).centered();
@@ -170,34 +182,69 @@ fn render_viewer_content(frame: &mut Frame, area: Rect, viewer: &Viewer) {
     // Add chunking status to title with consistent square character
     let chunking_percent = viewer.chunking_percentage();
     let title_text = if chunking_percent > 0.0 {
-        format!("□ {} [{:.1}% Chunked] □", file_name, chunking_percent)
+        format!("□ {} [{:.1}% Chunked] ", file_name, chunking_percent)
     } else {
-        format!("□ {} □", file_name)
+        format!("□ {} ", file_name)
     };
     
-    // Add token count for the current selection with squares on both sides
-    let token_info = if let Some(token_count) = viewer.selection_token_count() {
+    // Follow the name with a sparkline of token density across the file,
+    // marking the part the cursor is in
+    let mut title_spans = vec![Span::raw(title_text)];
+    if let Some(profile) = viewer.token_density_profile(SPARKLINE_WIDTH).filter(|profile| profile.len() > 1) {
+        let cursor_bucket = viewer.cursor_position() * profile.len() / viewer.content().len().max(1);
+        for (bucket, density) in profile.iter().enumerate() {
+            let style = if bucket == cursor_bucket {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(heat_color(*density))
+            };
+            let level = (density * (SPARKLINE_BARS.len() - 1) as f64).round() as usize;
+            title_spans.push(Span::styled(SPARKLINE_BARS[level].to_string(), style));
+        }
+        title_spans.push(Span::raw(" "));
+    }
+    title_spans.push(Span::raw("□"));
+    
+    // Add token count for the current selection with squares on both sides,
+    // marked with ~ while it's estimated and the exact count is being worked out
+    let approximate = if viewer.token_count_pending() { "~" } else { "" };
+    let preview_noun = if viewer.chunk_preview_is_windowed() { "window" } else { "chunk" };
+    let preview_budget = viewer.chunk_preview_budget().unwrap_or(viewer.max_tokens_per_chunk());
+    let token_info = if let Some(proposal) = viewer.chunk_preview() {
+        // While reviewing proposed chunks, show the one under the cursor
+        match viewer.proposed_chunk_at(viewer.cursor_position()) {
+            Some(index) => format!(
+                "□ PREVIEW: {} {}/{} - {} / {} tokens □",
+                preview_noun, index + 1, proposal.len(), proposal[index].tokens, preview_budget
+            ),
+            None => format!("□ PREVIEW: {} {}s □", proposal.len(), preview_noun),
+        }
+    } else if let Some((counted, total)) = viewer.token_counting_progress().filter(|_| viewer.token_count_pending()) {
+        // Lines are still being counted in the background
+        format!("□ TOKENS: counting… {}/{} lines □", counted, total)
+    } else if let Some(token_count) = viewer.selection_token_count() {
         let percentage = (token_count as f64 / viewer.max_tokens_per_chunk() as f64) * 100.0;
         
         // Token percentage info - debug message for over limit is set in app code
         
-        // Format token info consistently
-        format!("□ TOKENS: {} / {} ({}%) □", token_count, viewer.max_tokens_per_chunk(), percentage as usize)
+        // Format token info consistently, noting when the count includes the prompt template
+        let prompt = if viewer.has_prompt_template() { " incl. prompt" } else { "" };
+        format!("□ TOKENS: {}{}{} / {} ({}%) □", approximate, token_count, prompt, viewer.max_tokens_per_chunk(), percentage as usize)
     } else {
         let total = viewer.total_token_count();
-        format!("□ TOTAL TOKENS: {} □", total)
+        format!("□ TOTAL TOKENS: {}{} □", approximate, total)
     };
     
     // Use default style for consistent appearance
     let token_style = Style::default();
     
     // Left and right titles using ratatui's built-in title support
-    let left_title = title_text;
+    let left_title = Line::from(title_spans);
     
     // No special border styling needed for consistency
     
     // Use left-aligned and right-aligned titles with Ratatui's alignment methods
-    let left_aligned_title = Line::from(left_title).left_aligned();
+    let left_aligned_title = left_title.left_aligned();
     let right_aligned_title = Line::from(Span::styled(token_info, token_style)).right_aligned();
     
     // Create the block with both titles
@@ -213,14 +260,18 @@ fn render_viewer_content(frame: &mut Frame, area: Rect, viewer: &Viewer) {
     let content_height = inner_area.height as usize;
     let visible_content = viewer.visible_content(content_height);
     
-    // Get selection range if any
-    let selection_range = viewer.selection_range();
-    
     // Determine cursor position relative to the visible area
     let cursor_position = viewer.cursor_position();
     let scroll_position = viewer.scroll_position();
     
-    // We'll calculate the number of lines as needed in our loop
+    // With token boundaries shown, a gutter after the line numbers holds each
+    // line's token count, wide enough for the largest count on screen
+    let token_gutter_width = viewer.shows_token_boundaries().then(|| {
+        (0..visible_content.len())
+            .filter_map(|i| viewer.line_token_count(scroll_position + i))
+            .max()
+            .map_or(1, |count| count.to_string().len())
+    });
     
     // Create text content for the paragraph with selection highlighting
     let content: Vec<Line> = visible_content
@@ -230,20 +281,9 @@ fn render_viewer_content(frame: &mut Frame, area: Rect, viewer: &Viewer) {
             let line_position = scroll_position + i;
             let is_cursor_line = line_position == cursor_position;
             
-            // Check if this line is in the selection range
-            let is_selected = selection_range
-                .map(|(start, end)| line_position >= start && line_position <= end)
-                .unwrap_or(false);
-            
-            // Define style based on selection and chunk status
-            let style = if is_selected {
-                Style::default().bg(Color::Yellow).fg(Color::Black)
-            } else if viewer.is_line_chunked(line_position) {
-                // Use yellow highlight for chunked lines
-                Style::default().bg(Color::Yellow).fg(Color::Black)
-            } else {
-                Style::default().fg(Color::Reset)
-            };
+            // Chunks and character-wise selections may cover only part of a line,
+            // so the line is styled character by character
+            let content_spans = styled_line_spans(viewer, line_position, line, is_cursor_line);
             
             // Calculate line number width based on total content lines
             // Use at least 3 chars width for line numbers
@@ -253,56 +293,45 @@ fn render_viewer_content(frame: &mut Frame, area: Rect, viewer: &Viewer) {
             // Create the line number for this line (1-indexed for display)
             let absolute_line_number = scroll_position + i + 1;
             
-            // Create the line's content span with appropriate style
-            let content_span = Span::styled(line.as_str(), style);
-            
             // Cursor and line number handling
-            if is_cursor_line {
+            let (line_number_style, marker) = if is_cursor_line {
                 // Choose appropriate cursor style based on selection mode
                 let cursor_style = if viewer.is_selection_mode() {
                     Style::default().bg(Color::Yellow).fg(Color::Black)
                 } else {
                     Style::default().bg(Color::DarkGray).fg(Color::White)
                 };
-                
-                // Format with line number, cursor arrow, and content
-                let line_number_with_cursor = format!("{:>width$} > ", absolute_line_number, width = line_number_width);
-                
-                if line.is_empty() {
-                    // For empty lines with cursor
-                    Line::from(vec![
-                        Span::styled(line_number_with_cursor, cursor_style),
-                        Span::raw("") // Empty content but maintain structure
-                    ])
-                } else {
-                    // For lines with content
-                    Line::from(vec![
-                        Span::styled(line_number_with_cursor, cursor_style),
-                        content_span
-                    ])
-                }
+                (cursor_style, "> ")
             } else {
-                // Non-cursor lines get a subtle line number style
-                let line_number_style = Style::default().fg(Color::DarkGray);
-                
-                // Format with line number and proper spacing to align with cursor lines
-                // Add two spaces where the cursor arrow would be (> )
-                let line_number = format!("{:>width$}   ", absolute_line_number, width = line_number_width);
-                
-                if line.is_empty() {
-                    // Empty lines still need the line number
-                    Line::from(vec![
-                        Span::styled(line_number, line_number_style),
-                        Span::raw("")
-                    ])
-                } else {
-                    // Standard lines with content
-                    Line::from(vec![
-                        Span::styled(line_number, line_number_style),
-                        content_span
-                    ])
-                }
+                // Non-cursor lines get a subtle line number style, with two
+                // spaces where the cursor arrow would be (> )
+                (Style::default().fg(Color::DarkGray), "  ")
+            };
+            
+            // The heatmap column shades each line by how many tokens it holds
+            let heat = match viewer.line_token_density(line_position) {
+                Some(density) if density > 0.0 => Span::styled("▐", Style::default().fg(heat_color(density))),
+                _ => Span::raw(" "),
+            };
+            
+            let mut spans = vec![heat, Span::styled(
+                format!("{:>width$} ", absolute_line_number, width = line_number_width),
+                line_number_style,
+            )];
+            if let Some(gutter_width) = token_gutter_width {
+                // Lines still being counted in the background show a dot
+                let count = viewer
+                    .line_token_count(line_position)
+                    .map_or_else(|| "·".to_string(), |count| count.to_string());
+                spans.push(Span::styled(
+                    format!("{:>width$} ", count, width = gutter_width),
+                    line_number_style.fg(Color::Cyan),
+                ));
             }
+            spans.push(Span::styled(marker, line_number_style));
+            
+            spans.extend(content_spans);
+            Line::from(spans)
         })
         .collect();
     
@@ -316,9 +345,124 @@ fn render_viewer_content(frame: &mut Frame, area: Rect, viewer: &Viewer) {
     frame.render_widget(content_widget, inner_area);
 }
 
+/// Number of bars in the token density sparkline of the viewer title
+const SPARKLINE_WIDTH: usize = 24;
+
+/// Bars of the sparkline, from the sparsest to the densest part of a file
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Colors of the token density heatmap, from sparse to dense
+const HEAT_COLORS: [Color; 5] = [Color::DarkGray, Color::Green, Color::Yellow, Color::LightRed, Color::Red];
+
+/// Pick the heatmap color for a density between 0.0 and 1.0
+fn heat_color(density: f64) -> Color {
+    let level = (density * HEAT_COLORS.len() as f64).ceil() as usize;
+    HEAT_COLORS[level.clamp(1, HEAT_COLORS.len()) - 1]
+}
+
+/// Background colors alternated between proposed chunks while they're reviewed
+const PREVIEW_COLORS: [Color; 2] = [Color::Cyan, Color::Green];
+
+/// Background colors alternated between tokens when token boundaries are shown
+const TOKEN_COLORS: [Color; 2] = [Color::Blue, Color::Magenta];
+
+/// Split a viewer line into spans styled by selection and chunk status
+/// 
+/// Selection takes precedence over stale chunks, which take precedence over
+/// chunked text. During a character-wise selection, or once the cursor has
+/// moved along the line, the cursor character is shown reversed. With token boundaries shown, tokens
+/// get alternating backgrounds and chunks are told apart by their text color.
+/// Proposed chunks under review alternate colors, with those over the token
+/// budget in red.
+fn styled_line_spans(viewer: &Viewer, line_position: usize, line: &str, is_cursor_line: bool) -> Vec<Span<'static>> {
+    let selected = viewer.selection_columns_on_line(line_position);
+    let chunks = viewer.chunk_columns_on_line(line_position);
+    let show_cursor = is_cursor_line
+        && (viewer.is_char_selection() || (!viewer.is_selection_mode() && viewer.cursor_column() > 0));
+    let tokens = if viewer.shows_token_boundaries() {
+        viewer.token_columns_on_line(line_position)
+    } else {
+        Vec::new()
+    };
+    let budget = viewer.chunk_preview_budget().unwrap_or(viewer.max_tokens_per_chunk());
+    let proposed = viewer.proposed_chunk_at(line_position).zip(viewer.chunk_preview()).map(|(index, proposal)| {
+        if proposal[index].tokens > budget {
+            Color::Red
+        } else if viewer.proposed_chunks_covering(line_position) > 1 {
+            // Lines shared by overlapping windows
+            Color::Magenta
+        } else {
+            PREVIEW_COLORS[index % 2]
+        }
+    });
+    
+    // Tokens are in order, so walk them alongside the columns
+    let mut next_token = 0;
+    let mut style_at = |column: usize| {
+        let within = |(start, end): (usize, usize)| column >= start && column < end;
+        let stale = chunks.iter().any(|&(start, end, stale)| stale && within((start, end)));
+        let chunked = chunks.iter().any(|&(start, end, _)| within((start, end)));
+        while tokens.get(next_token).is_some_and(|&(_, end)| end <= column) {
+            next_token += 1;
+        }
+        let token = tokens.get(next_token).is_some_and(|&span| within(span)).then_some(next_token);
+        let style = if selected.is_some_and(within) {
+            Style::default().bg(Color::Yellow).fg(Color::Black)
+        } else if let Some(color) = proposed {
+            Style::default().bg(color).fg(Color::Black)
+        } else if let Some(token) = token {
+            let foreground = if stale {
+                Color::LightRed
+            } else if chunked {
+                Color::Yellow
+            } else {
+                Color::White
+            };
+            Style::default().bg(TOKEN_COLORS[token % 2]).fg(foreground)
+        } else if stale {
+            // Chunks whose source lines changed since saving stand out in red
+            Style::default().bg(Color::LightRed).fg(Color::Black)
+        } else if chunked {
+            // Use yellow highlight for chunked text
+            Style::default().bg(Color::Yellow).fg(Color::Black)
+        } else {
+            Style::default().fg(Color::Reset)
+        };
+        
+        if show_cursor && column == viewer.cursor_column() {
+            style.add_modifier(Modifier::REVERSED)
+        } else {
+            style
+        }
+    };
+    
+    // Group runs of characters sharing a style into one span
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut run = String::new();
+    let mut run_style = None;
+    for (column, ch) in line.chars().enumerate() {
+        let style = style_at(column);
+        if run_style.is_some_and(|current| current != style) {
+            spans.push(Span::styled(std::mem::take(&mut run), run_style.unwrap_or_default()));
+        }
+        run_style = Some(style);
+        run.push(ch);
+    }
+    if let Some(style) = run_style {
+        spans.push(Span::styled(run, style));
+    }
+    
+    // Keep proposed chunks visible across blank lines, where they often end
+    if let Some(color) = proposed.filter(|_| line.is_empty()) {
+        spans.push(Span::styled(" ", Style::default().bg(color)));
+    }
+    
+    spans
+}
+
 /// Render the explorer status line - more compact to fit in small terminals
 fn render_explorer_status(frame: &mut Frame, area: Rect) {
-    let status = Paragraph::new(" ?:Help | q/Esc:Quit | ↑↓/kj:Nav | PgUp/Dn:Page | Enter/→:Open | ←:Back")
+    let status = Paragraph::new(" ?:Help | q/Esc:Quit | ↑↓/kj:Nav | PgUp/Dn:Page | Enter/→:Open | ←:Back | f:Labels | x:Export")
         .style(Style::default().fg(Color::Reset));
     
     frame.render_widget(status, area);
@@ -326,7 +470,9 @@ fn render_explorer_status(frame: &mut Frame, area: Rect) {
 
 /// Render the viewer status line - more compact to fit in small terminals
 fn render_viewer_status(frame: &mut Frame, area: Rect, viewer: &Viewer) {
-    let selection_info = if viewer.is_selection_mode() {
+    let selection_info = if viewer.is_char_selection() {
+        "CHAR SELECTION | "
+    } else if viewer.is_selection_mode() {
         "SELECTION MODE | "
     } else {
         if viewer.selection_range().is_some() {
@@ -338,18 +484,30 @@ fn render_viewer_status(frame: &mut Frame, area: Rect, viewer: &Viewer) {
     
     // Add chunking percentage info if any chunks exist
     let chunking_percent = viewer.chunking_percentage();
-    let chunk_info = if chunking_percent > 0.0 {
+    let stale_count = viewer.stale_chunk_count();
+    let overlap_count = viewer.unintended_overlap_count();
+    let chunk_info = if stale_count > 0 {
+        format!("{:.1}% CHUNKED ({} STALE) | ", chunking_percent, stale_count)
+    } else if overlap_count > 0 {
+        // Overlapping windows of one sliding-window pass aren't counted
+        format!("{:.1}% CHUNKED ({} OVERLAPPING) | ", chunking_percent, overlap_count)
+    } else if chunking_percent > 0.0 {
         format!("{:.1}% CHUNKED | ", chunking_percent)
     } else {
         "".to_string()
     };
     
-    // Create status line with default styling for consistency
-    let status_line = if chunk_info.is_empty() {
-        Line::from(format!(" ?:Help | Space:Toggle Selection | s:Save Chunk | {} q/Esc:Back | ↑↓/kj:Move", selection_info))
+    // Create status line with default styling for consistency, starting with the active tokenizer
+    let status_line = if let Some(proposal) = viewer.chunk_preview() {
+        let noun = if viewer.chunk_preview_is_windowed() { "WINDOWS" } else { "CHUNKS" };
+        Line::from(format!(" {} | PREVIEW: {} {} | Enter:Save All | Esc:Discard | [/]:Move End | x/m:Split/Merge | {{/}}:Jump | ↑↓/kj:Move",
+            viewer.tokenizer_name(), proposal.len(), noun))
+    } else if chunk_info.is_empty() {
+        Line::from(format!(" {} | ?:Help | Space:Toggle Selection | s:Save Chunk | d/r:Delete/Reopen | u:Undo | {} q/Esc:Back | ↑↓/kj:Move",
+            viewer.tokenizer_name(), selection_info))
     } else {
-        Line::from(format!(" ?:Help | Space:Toggle Selection | s:Save Chunk | {} | {} q/Esc:Back | ↑↓/kj:Move", 
-            chunk_info, selection_info))
+        Line::from(format!(" {} | ?:Help | Space:Toggle Selection | s:Save Chunk | d/r:Delete/Reopen | u:Undo | {} | {} q/Esc:Back | ↑↓/kj:Move", 
+            viewer.tokenizer_name(), chunk_info, selection_info))
     };
     
     let status = Paragraph::new(status_line);
@@ -364,8 +522,8 @@ fn render_help_panel(frame: &mut Frame, mode: AppMode) {
     // Create a centered box for the help panel
     let width = 60.min(area.width.saturating_sub(4));
     let height = match mode {
-        AppMode::Explorer => 15.min(area.height.saturating_sub(4)),
-        AppMode::Viewer => 15.min(area.height.saturating_sub(4)),
+        AppMode::Explorer => 17.min(area.height.saturating_sub(4)),
+        AppMode::Viewer => 38.min(area.height.saturating_sub(4)),
         AppMode::Editor => 13.min(area.height.saturating_sub(4)),
     };
     
@@ -397,6 +555,8 @@ fn render_help_panel(frame: &mut Frame, mode: AppMode) {
                 ]),
                 Line::from("    Enter, l, →         Open selected file/directory"),
                 Line::from("    h, ←                Go to parent directory"),
+                Line::from("    f                   Show only files with chunk labels"),
+                Line::from("    x                   Export (filtered) chunks to JSONL"),
                 Line::from("    q, Esc              Quit application"),
                 Line::from(""),
                 Line::from(vec![
@@ -422,8 +582,27 @@ fn render_help_panel(frame: &mut Frame, mode: AppMode) {
                     Span::styled("  Selection & Chunking", Style::default().add_modifier(Modifier::BOLD))
                 ]),
                 Line::from("    Space               Toggle selection mode"),
+                Line::from("    v                   Toggle character selection"),
+                Line::from("    ←/→, h/l, Shift     Move by 1/10 characters (where v starts)"),
                 Line::from("    s                   Save selected text as chunk"),
                 Line::from("    e                   Open selected text in editor"),
+                Line::from("    d                   Delete chunk under cursor"),
+                Line::from("    r                   Re-open chunk under cursor in editor"),
+                Line::from("    t                   Edit labels of chunk under cursor"),
+                Line::from("    m                   Edit metadata of chunk under cursor"),
+                Line::from("    u, Ctrl+R           Undo/redo last chunk operation"),
+                Line::from("    a                   Re-anchor stale chunks"),
+                Line::from("    b                   Show token boundaries and counts"),
+                Line::from("    c                   Propose chunks up to the token budget"),
+                Line::from("    w                   Propose overlapping sliding windows"),
+                Line::from(""),
+                Line::from(vec![
+                    Span::styled("  Proposed Chunks", Style::default().add_modifier(Modifier::BOLD))
+                ]),
+                Line::from("    Enter, Esc          Save/discard the proposed chunks"),
+                Line::from("    [, ]                Move end of chunk under cursor up/down"),
+                Line::from("    x, m                Split chunk at cursor / merge with next"),
+                Line::from("    {, }                Jump to previous/next proposed chunk"),
                 Line::from(""),
                 Line::from(vec![
                     Span::styled("  Other Actions", Style::default().add_modifier(Modifier::BOLD))
@@ -507,8 +686,10 @@ fn render_editor_mode(frame: &mut Frame, state: &AppState, editor: &mut Editor)
     let max_tokens = editor.max_tokens();
     let percentage = (token_count as f64 / max_tokens as f64) * 100.0;
     
-    // Format token info consistently
-    let token_info = format!("□ TOKENS: {} / {} ({}%) □", token_count, max_tokens, percentage as usize);
+    // Format token info consistently (~ marks an estimate while the exact count is worked out)
+    let approximate = if editor.token_count_pending() { "~" } else { "" };
+    let prompt = if editor.has_prompt() { " incl. prompt" } else { "" };
+    let token_info = format!("□ TOKENS: {}{}{} / {} ({}%) □", approximate, token_count, prompt, max_tokens, percentage as usize);
     
     // Token percentage info - debug message for over limit is set in app code
     
@@ -548,13 +729,14 @@ fn render_editor_status(frame: &mut Frame, area: Rect, editor: &Editor) {
         ""
     };
     
-    // Create status line with appropriate guidance based on mode
+    // Create status line with appropriate guidance based on mode, ending with the active tokenizer
+    let tokenizer = editor.tokenizer_name();
     let status_line = if mode == "NORMAL" {
-        format!(" {} | {}?:Help | i:Insert Mode | Ctrl+S:Save | Esc:Cancel", mode, modified)
+        format!(" {} | {}?:Help | i:Insert Mode | Ctrl+S:Save | Esc:Cancel | {}", mode, modified, tokenizer)
     } else if mode == "INSERT" {
-        format!(" {} | {}?:Help | Esc:Normal Mode | Ctrl+S:Save", mode, modified)
+        format!(" {} | {}?:Help | Esc:Normal Mode | Ctrl+S:Save | {}", mode, modified, tokenizer)
     } else {
-        format!(" {} | {}?:Help | Ctrl+S:Save | Esc:Cancel", mode, modified)
+        format!(" {} | {}?:Help | Ctrl+S:Save | Esc:Cancel | {}", mode, modified, tokenizer)
     };
     
     let status = Paragraph::new(status_line);
@@ -562,6 +744,99 @@ fn render_editor_status(frame: &mut Frame, area: Rect, editor: &Editor) {
     frame.render_widget(status, area);
 }
 
+/// Render the label prompt in a box at the bottom of the screen
+/// 
+/// Shows the labels typed so far and the known labels Tab would complete to.
+fn render_label_prompt(frame: &mut Frame, prompt: &LabelPrompt) {
+    let area = frame.area();
+    let height = 4.min(area.height);
+    
+    let prompt_area = Rect {
+        x: area.x,
+        y: area.y + area.height - height,
+        width: area.width,
+        height,
+    };
+    
+    frame.render_widget(Clear, prompt_area);
+    
+    let block = Block::default()
+        .title(format!(" {} (comma-separated, Enter:OK, Esc:Cancel) ", prompt.title()))
+        .borders(Borders::ALL);
+    
+    let suggestions = prompt.input.suggestions();
+    let suggestion_line = if suggestions.is_empty() {
+        Line::from("")
+    } else {
+        Line::from(Span::styled(
+            format!("Tab: {}", suggestions.join(" | ")),
+            Style::default().fg(Color::DarkGray),
+        ))
+    };
+    
+    let content = vec![
+        Line::from(vec![
+            Span::raw(prompt.input.text().to_string()),
+            Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+        ]),
+        suggestion_line,
+    ];
+    
+    frame.render_widget(Paragraph::new(content).block(block), prompt_area);
+}
+
+/// Render the metadata form in a centered box, one line per configured field
+fn render_metadata_prompt(frame: &mut Frame, prompt: &MetadataPrompt) {
+    let area = frame.area();
+    let entries: Vec<_> = prompt.form.entries().collect();
+    
+    let width = 70.min(area.width);
+    let height = (entries.len() as u16 + 2).min(area.height);
+    let form_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    
+    frame.render_widget(Clear, form_area);
+    
+    let title = match prompt.purpose {
+        MetadataPurpose::SaveChunk { .. } => "Metadata for new chunk",
+        MetadataPurpose::EditChunk { .. } => "Edit chunk metadata",
+        MetadataPurpose::SaveProposedChunks { .. } => "Metadata for all proposed chunks",
+    };
+    let block = Block::default()
+        .title(format!(" {} (Tab/↑↓:Field, Enter:OK, Esc:Cancel) ", title))
+        .borders(Borders::ALL);
+    
+    // Align the values after the longest field name
+    let name_width = entries.iter().map(|(field, _)| field.name.chars().count()).max().unwrap_or(0);
+    let lines: Vec<Line> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, (field, value))| {
+            let focused = index == prompt.form.focused();
+            let name_style = if focused {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![
+                Span::styled(format!("{:>width$}: ", field.name, width = name_width), name_style),
+                Span::raw(value.to_string()),
+            ];
+            if focused {
+                spans.push(Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)));
+            }
+            spans.push(Span::styled(format!("  ({})", field.describe()), Style::default().fg(Color::DarkGray)));
+            Line::from(spans)
+        })
+        .collect();
+    
+    frame.render_widget(Paragraph::new(lines).block(block), form_area);
+}
+
 /// Render a debug message overlay at the bottom of the screen
 fn render_debug_overlay(frame: &mut Frame, message: &str) {
     let area = frame.area();
@@ -600,7 +875,7 @@ impl UiSerializer {
         writeln!(&mut output, "Mode: Explorer").unwrap();
         writeln!(&mut output, "Time: {:?}", std::time::SystemTime::now()).unwrap();
         writeln!(&mut output, "Show Help: {}", state.show_help).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Explorer state
         writeln!(&mut output, "Explorer State:").unwrap();
@@ -608,7 +883,8 @@ impl UiSerializer {
         writeln!(&mut output, "Current Directory: {}", explorer.current_path().display()).unwrap();
         writeln!(&mut output, "Root Directory: {}", explorer.root_dir().display()).unwrap();
         writeln!(&mut output, "Selected Index: {}", explorer.selected_index()).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output, "Label Filter: {}", explorer.label_filter().join(", ")).unwrap();
+        writeln!(&mut output).unwrap();
         
         // Entries
         writeln!(&mut output, "Directory Entries:").unwrap();
@@ -628,13 +904,13 @@ impl UiSerializer {
                 chunking
             ).unwrap();
         }
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Status line
         writeln!(&mut output, "Status Line:").unwrap();
         writeln!(&mut output, "------------").unwrap();
-        writeln!(&mut output, "?:Help | q/Esc:Quit | ↑↓/kj:Nav | PgUp/Dn:Page | Enter/→:Open | ←:Back").unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output, "?:Help | q/Esc:Quit | ↑↓/kj:Nav | PgUp/Dn:Page | Enter/→:Open | ←:Back | f:Labels | x:Export").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Debug info
         writeln!(&mut output, "Terminal Info:").unwrap();
@@ -654,7 +930,7 @@ impl UiSerializer {
         writeln!(&mut output, "Mode: Viewer").unwrap();
         writeln!(&mut output, "Time: {:?}", std::time::SystemTime::now()).unwrap();
         writeln!(&mut output, "Show Help: {}", state.show_help).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Viewer state
         writeln!(&mut output, "Viewer State:").unwrap();
@@ -670,7 +946,7 @@ impl UiSerializer {
             writeln!(&mut output, "Selection Range: None").unwrap();
         }
         writeln!(&mut output, "Cursor Position: Line {}", viewer.cursor_position() + 1).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Content
         writeln!(&mut output, "File Content Preview:").unwrap();
@@ -682,7 +958,7 @@ impl UiSerializer {
         let content = viewer.content();
         let selection_range = viewer.selection_range();
         
-        let start = if cursor_pos > 5 { cursor_pos - 5 } else { 0 };
+        let start = cursor_pos.saturating_sub(5);
         let end = (start + 15).min(content.len());
         
         for i in start..end {
@@ -694,6 +970,10 @@ impl UiSerializer {
                 if viewer.is_selection_mode() { " => " } else { " -> " } 
             } else if is_selected {
                 " ** "
+            } else if viewer.is_line_stale(i) {
+                " !! "
+            } else if viewer.is_line_chunked(i) {
+                " ## "
             } else { 
                 "    " 
             };
@@ -702,7 +982,7 @@ impl UiSerializer {
             let line_content = content.get(i).map_or("", |s| s.as_str());
             writeln!(&mut output, "{}{}: {}", marker, line_num, line_content).unwrap();
         }
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Status
         writeln!(&mut output, "Status Line:").unwrap();
@@ -720,12 +1000,12 @@ impl UiSerializer {
         
         writeln!(&mut output, "?:Help | Space:Toggle Selection | {} q/Esc:Back | ↑↓/kj:Move | PgUp/Dn:Page | Home/End:Jump", 
             selection_info).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Token information
         writeln!(&mut output, "Token Information:").unwrap();
         writeln!(&mut output, "------------------").unwrap();
-        writeln!(&mut output, "Total tokens in file: {}", viewer.total_token_count()).unwrap();
+        writeln!(&mut output, "Total tokens in file: {} ({})", viewer.total_token_count(), viewer.tokenizer_name()).unwrap();
         if let Some(count) = viewer.selection_token_count() {
             let percentage = (count as f64 / viewer.max_tokens_per_chunk() as f64) * 100.0;
             writeln!(&mut output, "Selection tokens: {} ({:.1}% of limit {})", 
@@ -734,7 +1014,7 @@ impl UiSerializer {
                 writeln!(&mut output, "WARNING: Selection exceeds token limit!").unwrap();
             }
         }
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Debug info
         writeln!(&mut output, "Terminal Info:").unwrap();
@@ -754,17 +1034,17 @@ impl UiSerializer {
         writeln!(&mut output, "Mode: Editor").unwrap();
         writeln!(&mut output, "Time: {:?}", std::time::SystemTime::now()).unwrap();
         writeln!(&mut output, "Show Help: {}", state.show_help).unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Status info
         writeln!(&mut output, "Editing selected text - content not shown in debug view").unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Status line
         writeln!(&mut output, "Status Line:").unwrap();
         writeln!(&mut output, "------------").unwrap();
         writeln!(&mut output, "?:Help | Ctrl+S:Save | Esc:Cancel | Arrow keys:Navigate | Type to edit").unwrap();
-        writeln!(&mut output, "").unwrap();
+        writeln!(&mut output).unwrap();
         
         // Debug info
         writeln!(&mut output, "Terminal Info:").unwrap();
@@ -0,0 +1,137 @@
+/// Maximum number of completions offered for the label being typed
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Split a comma-separated list of labels as typed by the user
+/// 
+/// Labels are trimmed, empty entries and duplicates are dropped, and a
+/// comma or backslash can be included in a label by escaping it (`\,`, `\\`).
+pub fn parse_labels(text: &str) -> Vec<String> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars();
+    
+    loop {
+        let next = chars.next();
+        match next {
+            Some('\\') => {
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            },
+            Some(',') | None => {
+                let label = current.trim().to_string();
+                if !label.is_empty() && !labels.contains(&label) {
+                    labels.push(label);
+                }
+                current.clear();
+                if next.is_none() {
+                    break;
+                }
+            },
+            Some(c) => current.push(c),
+        }
+    }
+    
+    labels
+}
+
+/// Format labels for editing, the inverse of `parse_labels`
+pub fn format_labels(labels: &[String]) -> String {
+    labels
+        .iter()
+        .map(|label| label.replace('\\', "\\\\").replace(',', "\\,"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Text typed into a label prompt, with completion from known labels
+#[derive(Debug, Clone, Default)]
+pub struct LabelInput {
+    /// Comma-separated labels as typed
+    text: String,
+    
+    /// Labels already in use, most used first
+    known: Vec<String>,
+}
+
+impl LabelInput {
+    /// Start a prompt with some labels filled in
+    /// 
+    /// `known` holds the labels offered as completions.
+    pub fn new(initial: &[String], known: Vec<String>) -> Self {
+        let mut text = format_labels(initial);
+        if !text.is_empty() {
+            text.push_str(", ");
+        }
+        Self { text, known }
+    }
+    
+    /// The text typed so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    
+    /// Type a character
+    pub fn push(&mut self, c: char) {
+        self.text.push(c);
+    }
+    
+    /// Delete the last character
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+    
+    /// The labels entered so far
+    pub fn labels(&self) -> Vec<String> {
+        parse_labels(&self.text)
+    }
+    
+    /// The label currently being typed (after the last unescaped comma)
+    fn fragment_start(&self) -> usize {
+        let mut start = 0;
+        let mut escaped = false;
+        for (i, c) in self.text.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                ',' => start = i + 1,
+                _ => {},
+            }
+        }
+        start
+    }
+    
+    /// Known labels that start with the label being typed (ignoring case)
+    /// 
+    /// Labels that were already entered are left out.
+    pub fn suggestions(&self) -> Vec<&str> {
+        let fragment = parse_labels(&self.text[self.fragment_start()..]);
+        let fragment = fragment.first().map_or(String::new(), |label| label.to_lowercase());
+        let entered = parse_labels(&self.text[..self.fragment_start()]);
+        
+        self.known
+            .iter()
+            .filter(|label| label.to_lowercase().starts_with(&fragment) && !entered.contains(label))
+            .take(MAX_SUGGESTIONS)
+            .map(String::as_str)
+            .collect()
+    }
+    
+    /// Replace the label being typed with the first suggestion
+    /// 
+    /// Returns false if there was nothing to complete.
+    pub fn complete(&mut self) -> bool {
+        let Some(suggestion) = self.suggestions().first().map(|label| label.to_string()) else {
+            return false;
+        };
+        
+        let start = self.fragment_start();
+        self.text.truncate(start);
+        if start > 0 {
+            self.text.push(' ');
+        }
+        self.text.push_str(&format_labels(&[suggestion]));
+        self.text.push_str(", ");
+        true
+    }
+}
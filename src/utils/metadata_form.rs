@@ -0,0 +1,94 @@
+use anyhow::Result;
+
+use crate::storage::{Metadata, MetadataField};
+
+/// Values typed into the metadata form, one text box per configured field
+#[derive(Debug, Clone)]
+pub struct MetadataForm {
+    /// Fields declared in the config
+    fields: Vec<MetadataField>,
+    
+    /// Text typed for each field
+    values: Vec<String>,
+    
+    /// Index of the field being typed into
+    focused: usize,
+    
+    /// Existing values for keys no longer declared in the config, kept as they are
+    extra: Metadata,
+}
+
+impl MetadataForm {
+    /// Start a form for the given fields, prefilled from existing metadata
+    pub fn new(fields: Vec<MetadataField>, initial: &Metadata) -> Self {
+        let values = fields
+            .iter()
+            .map(|field| initial.get(&field.name).map(|value| value.to_string()).unwrap_or_default())
+            .collect();
+        let extra = initial
+            .iter()
+            .filter(|(key, _)| !fields.iter().any(|field| &field.name == *key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        
+        Self { fields, values, focused: 0, extra }
+    }
+    
+    /// The fields with the text typed for each
+    pub fn entries(&self) -> impl Iterator<Item = (&MetadataField, &str)> {
+        self.fields.iter().zip(self.values.iter().map(String::as_str))
+    }
+    
+    /// Index of the field being typed into
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+    
+    /// Move to the next field, wrapping around
+    pub fn next_field(&mut self) {
+        if !self.fields.is_empty() {
+            self.focused = (self.focused + 1) % self.fields.len();
+        }
+    }
+    
+    /// Move to the previous field, wrapping around
+    pub fn previous_field(&mut self) {
+        if !self.fields.is_empty() {
+            self.focused = (self.focused + self.fields.len() - 1) % self.fields.len();
+        }
+    }
+    
+    /// Type a character into the focused field
+    pub fn push(&mut self, c: char) {
+        if let Some(value) = self.values.get_mut(self.focused) {
+            value.push(c);
+        }
+    }
+    
+    /// Delete the last character of the focused field
+    pub fn backspace(&mut self) {
+        if let Some(value) = self.values.get_mut(self.focused) {
+            value.pop();
+        }
+    }
+    
+    /// Check every field and collect the typed values
+    /// 
+    /// On error the first invalid field is focused, so it can be fixed.
+    pub fn metadata(&mut self) -> Result<Metadata> {
+        let mut metadata = self.extra.clone();
+        for (index, (field, text)) in self.fields.iter().zip(&self.values).enumerate() {
+            match field.parse(text) {
+                Ok(Some(value)) => {
+                    metadata.insert(field.name.clone(), value);
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    self.focused = index;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
@@ -1,3 +1,7 @@
+pub mod labels;
+pub mod metadata_form;
+pub mod prompt;
+pub mod token_cache;
 pub mod tokenizer;
 pub use tokenizer::*;
 
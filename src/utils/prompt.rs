@@ -0,0 +1,121 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Placeholders a prompt template can use
+const PLACEHOLDERS: &[&str] = &["content", "file_path", "start_line", "end_line"];
+
+/// Where a chunk comes from, filled into a prompt template
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PromptContext {
+    /// Path of the source file, as stored with the chunk
+    pub file_path: String,
+    /// First line of the chunk (1-indexed)
+    pub start_line: usize,
+    /// Last line of the chunk (1-indexed)
+    pub end_line: usize,
+}
+
+/// A piece of a parsed template
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    /// Text copied as it is
+    Text(String),
+    /// A `{name}` placeholder
+    Placeholder(String),
+}
+
+/// Text every chunk is wrapped in when it's sent to a model
+/// 
+/// Written in the config file with `{content}` where the chunk goes and
+/// `{file_path}`, `{start_line}` and `{end_line}` for where it comes from.
+/// Literal braces are written `{{` and `}}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PromptTemplate(String);
+
+impl PromptTemplate {
+    /// Wrap a template's text (see `validate` for checking it)
+    #[allow(dead_code)]
+    pub fn new(template: impl Into<String>) -> Self {
+        Self(template.into())
+    }
+    
+    /// The template as written
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    
+    /// Split the template into text and placeholders
+    fn parts(&self) -> Result<Vec<Part>> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = self.0.chars().peekable();
+        
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => bail!("Unclosed {{{} (write {{{{ for a literal brace)", name),
+                        }
+                    }
+                    if !PLACEHOLDERS.contains(&name.as_str()) {
+                        bail!("Unknown placeholder {{{}}} (expected one of: {{{}}})", name, PLACEHOLDERS.join("}, {"));
+                    }
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Placeholder(name));
+                },
+                '}' => bail!("Unmatched }} (write }}}} for a literal brace)"),
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        
+        Ok(parts)
+    }
+    
+    /// Check that the template parses and places the chunk's content
+    pub fn validate(&self) -> Result<()> {
+        let parts = self.parts()?;
+        if !parts.contains(&Part::Placeholder("content".to_string())) {
+            bail!("The prompt template needs a {{content}} placeholder for the chunk");
+        }
+        Ok(())
+    }
+    
+    /// Fill in the template for a chunk
+    /// 
+    /// An invalid template (rejected when the config is loaded) renders as
+    /// the bare content.
+    pub fn render(&self, context: &PromptContext, content: &str) -> String {
+        let Ok(parts) = self.parts() else {
+            return content.to_string();
+        };
+        
+        let mut rendered = String::with_capacity(self.0.len() + content.len());
+        for part in parts {
+            match part {
+                Part::Text(text) => rendered.push_str(&text),
+                Part::Placeholder(name) => match name.as_str() {
+                    "content" => rendered.push_str(content),
+                    "file_path" => rendered.push_str(&context.file_path),
+                    "start_line" => rendered.push_str(&context.start_line.to_string()),
+                    "end_line" => rendered.push_str(&context.end_line.to_string()),
+                    _ => {},
+                },
+            }
+        }
+        rendered
+    }
+}
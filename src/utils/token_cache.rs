@@ -0,0 +1,327 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::Tokenizer;
+
+/// Lines remembered by a cache before it starts over, so it can't grow without bound
+const MAX_CACHED_LINES: usize = 200_000;
+
+/// Lines counted between progress updates when counting in the background
+const BATCH_SIZE: usize = 256;
+
+/// Token count of each line text seen so far, keyed by its hash
+type LineCache = Arc<Mutex<HashMap<u64, usize>>>;
+
+/// Hash a piece of text, used as a cache and job key
+pub fn content_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Token counts of individual lines, with prefix sums for quick range totals
+/// 
+/// Counts are cached by the hash of each line's text, so lines that didn't
+/// change (or repeat elsewhere in the file) are never encoded twice. Range
+/// totals add up the lines and their newlines, which is close to, but not
+/// always exactly, what encoding the joined text gives: tokens can merge
+/// across a line break.
+/// 
+/// Large texts can be counted on a background thread, in which case the
+/// counts fill in from the first line on as `poll` picks them up.
+pub struct LineTokenCounts {
+    /// Tokenizer the counts come from
+    tokenizer: Arc<dyn Tokenizer>,
+    
+    /// Counts of lines seen so far, shared with the background job
+    cache: LineCache,
+    
+    /// Token count of each line (only the lines counted so far while a job runs)
+    counts: Vec<usize>,
+    
+    /// Tokens in all lines before each index (one longer than `counts`)
+    prefix: Vec<usize>,
+    
+    /// Most tokens on any single line counted so far
+    densest: usize,
+    
+    /// Background job counting the remaining lines, if any
+    job: Option<LineCountJob>,
+}
+
+/// A background job counting lines, cancelled when dropped
+struct LineCountJob {
+    /// Tells the worker to stop
+    cancelled: Arc<AtomicBool>,
+    
+    /// Counts of consecutive lines, a batch at a time
+    batches: Receiver<Vec<usize>>,
+    
+    /// Number of lines being counted
+    total: usize,
+}
+
+impl Drop for LineCountJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Count a line through the cache, encoding it only if its text wasn't seen before
+fn cached_count(tokenizer: &dyn Tokenizer, cache: &Mutex<HashMap<u64, usize>>, line: &str) -> usize {
+    let key = content_hash(line);
+    if let Some(&count) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return count;
+    }
+    let count = tokenizer.count(line);
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= MAX_CACHED_LINES {
+        cache.clear();
+    }
+    cache.insert(key, count);
+    count
+}
+
+impl LineTokenCounts {
+    /// Start with no lines
+    pub fn new(tokenizer: Arc<dyn Tokenizer>) -> Self {
+        Self {
+            tokenizer,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            counts: Vec::new(),
+            prefix: vec![0],
+            densest: 0,
+            job: None,
+        }
+    }
+    
+    /// The tokenizer counts come from
+    pub fn tokenizer(&self) -> &Arc<dyn Tokenizer> {
+        &self.tokenizer
+    }
+    
+    /// Count a single line, encoding it only if its text wasn't seen before
+    pub fn count_line(&self, line: &str) -> usize {
+        cached_count(self.tokenizer.as_ref(), &self.cache, line)
+    }
+    
+    /// Count every line of a new text
+    #[allow(dead_code)]
+    pub fn recount(&mut self, lines: &[String]) {
+        self.job = None;
+        self.counts = lines.iter().map(|line| self.count_line(line)).collect();
+        self.rebuild_prefix(0);
+    }
+    
+    /// Count every line of a new text on a background thread
+    /// 
+    /// Any job still counting an older text is cancelled. The counts start
+    /// out empty and fill in as `poll` picks up finished batches.
+    pub fn recount_in_background(&mut self, lines: Vec<String>) {
+        self.job = None;
+        self.counts.clear();
+        self.rebuild_prefix(0);
+        
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, batches) = mpsc::channel();
+        let total = lines.len();
+        let tokenizer = self.tokenizer.clone();
+        let cache = self.cache.clone();
+        let stop = cancelled.clone();
+        
+        thread::spawn(move || {
+            for batch in lines.chunks(BATCH_SIZE) {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let counts = batch.iter().map(|line| cached_count(tokenizer.as_ref(), &cache, line)).collect();
+                if sender.send(counts).is_err() {
+                    break;
+                }
+            }
+        });
+        
+        self.job = Some(LineCountJob { cancelled, batches, total });
+    }
+    
+    /// Pick up lines counted in the background, returning true if any arrived
+    pub fn poll(&mut self) -> bool {
+        let Some(job) = &self.job else {
+            return false;
+        };
+        
+        let from = self.counts.len();
+        while let Ok(batch) = job.batches.try_recv() {
+            self.counts.extend(batch);
+        }
+        if self.counts.len() >= job.total {
+            self.job = None;
+        }
+        
+        let updated = self.counts.len() > from;
+        if updated {
+            self.rebuild_prefix(from);
+        }
+        updated
+    }
+    
+    /// Lines counted and lines in total while a background job is counting
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        self.job.as_ref().map(|job| (self.counts.len(), job.total))
+    }
+    
+    /// Replace the counts of some lines, counting only the new lines
+    /// 
+    /// `start..end` are the replaced line indices and `lines` the text that
+    /// took their place. Only valid once background counting has finished.
+    pub fn splice(&mut self, start: usize, end: usize, lines: &[String]) {
+        let end = end.min(self.counts.len());
+        let start = start.min(end);
+        let counts: Vec<usize> = lines.iter().map(|line| self.count_line(line)).collect();
+        let replaced: Vec<usize> = self.counts.splice(start..end, counts).collect();
+        if replaced.contains(&self.densest) {
+            // The densest line may be gone, so look through every line again
+            self.rebuild_prefix(0);
+        } else {
+            self.rebuild_prefix(start);
+        }
+    }
+    
+    /// Update the counts after lines changed from `before` to `after`
+    /// 
+    /// Only the lines between the unchanged beginning and end are recounted,
+    /// which for a keystroke is usually a single line.
+    #[allow(dead_code)]
+    pub fn update(&mut self, before: &[String], after: &[String]) {
+        let common_start = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+        let common_end = before[common_start..]
+            .iter()
+            .rev()
+            .zip(after[common_start..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.splice(common_start, before.len() - common_end, &after[common_start..after.len() - common_end]);
+    }
+    
+    /// Recompute the prefix sums and the densest line from a line on
+    /// 
+    /// Lines before `from` must be unchanged and still all be there, which
+    /// holds when counts were only appended. Otherwise pass 0.
+    fn rebuild_prefix(&mut self, from: usize) {
+        let from = from.min(self.counts.len());
+        self.prefix.truncate(from + 1);
+        if from == 0 {
+            self.densest = 0;
+        }
+        let mut total = self.prefix.last().copied().unwrap_or(0);
+        for &count in &self.counts[from..] {
+            total += count;
+            self.prefix.push(total);
+            self.densest = self.densest.max(count);
+        }
+    }
+    
+    /// Token counts of the lines counted so far
+    pub fn lines(&self) -> &[usize] {
+        &self.counts
+    }
+    
+    /// Token count of a single line
+    pub fn line(&self, index: usize) -> usize {
+        self.counts.get(index).copied().unwrap_or(0)
+    }
+    
+    /// Most tokens on any single line counted so far
+    pub fn densest(&self) -> usize {
+        self.densest
+    }
+    
+    /// Estimated tokens in lines `start..=end` joined with newlines
+    pub fn range_total(&self, start: usize, end: usize) -> usize {
+        let end = end.min(self.counts.len().saturating_sub(1));
+        if self.counts.is_empty() || start > end {
+            return 0;
+        }
+        self.prefix[end + 1] - self.prefix[start] + (end - start)
+    }
+    
+    /// Estimated tokens in all lines joined with newlines
+    pub fn total(&self) -> usize {
+        self.range_total(0, usize::MAX)
+    }
+}
+
+/// Counts text exactly on a background thread
+/// 
+/// Encoding a large selection can take longer than a frame, so callers show
+/// an estimate and ask for an exact count under a key describing the text.
+/// Only the newest request is worked on; older ones are dropped unanswered.
+pub struct BackgroundCounter {
+    /// Texts to count, with their keys
+    jobs: Sender<(u64, String)>,
+    
+    /// Finished counts, with their keys
+    results: Receiver<(u64, usize)>,
+    
+    /// Key of the last request
+    requested: Option<u64>,
+    
+    /// Key and count of the newest finished request
+    latest: Option<(u64, usize)>,
+}
+
+impl BackgroundCounter {
+    /// Start a counting thread for a tokenizer
+    /// 
+    /// The thread ends when the counter is dropped.
+    pub fn new(tokenizer: Arc<dyn Tokenizer>) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(u64, String)>();
+        let (result_sender, results) = mpsc::channel();
+        
+        thread::spawn(move || {
+            while let Ok(mut job) = job_receiver.recv() {
+                // Skip to the newest request; the others are already outdated
+                while let Ok(newer) = job_receiver.try_recv() {
+                    job = newer;
+                }
+                let (key, text) = job;
+                if result_sender.send((key, tokenizer.count(&text))).is_err() {
+                    break;
+                }
+            }
+        });
+        
+        Self { jobs, results, requested: None, latest: None }
+    }
+    
+    /// Ask for an exact count of the text under `key`
+    /// 
+    /// `text` is only built if the key wasn't already requested.
+    pub fn request(&mut self, key: u64, text: impl FnOnce() -> String) {
+        if self.requested == Some(key) || self.result(key).is_some() {
+            return;
+        }
+        self.requested = Some(key);
+        let _ = self.jobs.send((key, text()));
+    }
+    
+    /// Pick up finished counts, returning true if a new one arrived
+    pub fn poll(&mut self) -> bool {
+        let mut updated = false;
+        while let Ok(result) = self.results.try_recv() {
+            self.latest = Some(result);
+            updated = true;
+        }
+        updated
+    }
+    
+    /// The exact count for `key`, if it has been counted
+    pub fn result(&self, key: u64) -> Option<usize> {
+        self.latest.filter(|(latest, _)| *latest == key).map(|(_, count)| count)
+    }
+}
@@ -1,22 +1,342 @@
-use std::sync::OnceLock;
-use tiktoken_rs::{cl100k_base, CoreBPE};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, CoreBPE};
 
-static TOKENIZER: OnceLock<CoreBPE> = OnceLock::new();
+/// Counts the tokens in a piece of text
+/// 
+/// Implementations are shared between the viewer and the editor, so they
+/// must be cheap to call from the UI and safe to share across threads.
+pub trait Tokenizer: Send + Sync {
+    /// Name shown in the status bars, e.g. `cl100k_base`
+    fn name(&self) -> &str;
 
-fn get_tokenizer() -> &'static CoreBPE {
-    TOKENIZER.get_or_init(|| {
-        cl100k_base().expect("Failed to initialize Claude tokenizer")
-    })
+    /// Count the tokens in `text`
+    fn count(&self, text: &str) -> usize;
+    
+    /// Count the tokens in lines joined with newlines
+    fn count_lines(&self, lines: &[String]) -> usize {
+        self.count(&lines.join("\n"))
+    }
+    
+    /// Split text into its tokens, as byte ranges in order
+    /// 
+    /// Ranges always fall on character boundaries. Text that isn't part of
+    /// any token (e.g. whitespace between words) is left out. By default the
+    /// whole text is a single span.
+    fn token_spans(&self, text: &str) -> Vec<Range<usize>> {
+        std::iter::once(0..text.len()).filter(|span| !span.is_empty()).collect()
+    }
+}
+
+/// A byte-pair encoding from tiktoken
+pub struct BpeTokenizer {
+    /// Name of the encoding (the file name for vocabularies loaded from disk)
+    name: String,
+    /// The encoder
+    bpe: CoreBPE,
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+    
+    fn token_spans(&self, text: &str) -> Vec<Range<usize>> {
+        // A token can end part-way through a multi-byte character, so it
+        // doesn't decode on its own; it then shares a span with the tokens up
+        // to the end of that character
+        let mut spans = Vec::new();
+        let mut pending = Vec::new();
+        let mut start = 0;
+        for token in self.bpe.encode_ordinary(text) {
+            pending.push(token);
+            if let Ok(decoded) = self.bpe.decode(pending.clone()) {
+                let end = (start + decoded.len()).min(text.len());
+                spans.push(start..end);
+                start = end;
+                pending.clear();
+            }
+        }
+        spans
+    }
+}
+
+/// Counts whitespace-separated words
+pub struct WordCounter;
+
+impl Tokenizer for WordCounter {
+    fn name(&self) -> &str {
+        "words"
+    }
+    
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+    
+    fn token_spans(&self, text: &str) -> Vec<Range<usize>> {
+        text.split_whitespace()
+            .map(|word| {
+                let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                start..start + word.len()
+            })
+            .collect()
+    }
+}
+
+/// Counts characters (Unicode scalar values, including whitespace)
+pub struct CharCounter;
+
+impl Tokenizer for CharCounter {
+    fn name(&self) -> &str {
+        "chars"
+    }
+    
+    fn count(&self, text: &str) -> usize {
+        text.chars().count()
+    }
+    
+    fn token_spans(&self, text: &str) -> Vec<Range<usize>> {
+        text.char_indices().map(|(start, c)| start..start + c.len_utf8()).collect()
+    }
+}
+
+/// Tokenizers that can be selected in the config file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenizerKind {
+    /// OpenAI's encoding for GPT-3.5 and GPT-4
+    #[default]
+    #[serde(rename = "cl100k_base")]
+    Cl100k,
+    /// OpenAI's encoding for GPT-4o
+    #[serde(rename = "o200k_base")]
+    O200k,
+    /// OpenAI's encoding for Codex and older GPT-3 models
+    #[serde(rename = "p50k_base")]
+    P50k,
+    /// Whitespace-separated words
+    #[serde(rename = "words")]
+    Words,
+    /// Characters
+    #[serde(rename = "chars")]
+    Chars,
+}
+
+/// BPE encoders take a moment to load, so each is built once and shared
+static CL100K: OnceLock<Arc<dyn Tokenizer>> = OnceLock::new();
+static O200K: OnceLock<Arc<dyn Tokenizer>> = OnceLock::new();
+static P50K: OnceLock<Arc<dyn Tokenizer>> = OnceLock::new();
+
+/// Get a shared BPE tokenizer, loading it the first time
+fn shared_bpe(
+    cache: &'static OnceLock<Arc<dyn Tokenizer>>,
+    name: &'static str,
+    load: fn() -> Result<CoreBPE>,
+) -> Result<Arc<dyn Tokenizer>> {
+    if let Some(tokenizer) = cache.get() {
+        return Ok(tokenizer.clone());
+    }
+    let bpe = load().with_context(|| format!("Failed to load the {} tokenizer", name))?;
+    Ok(cache.get_or_init(|| Arc::new(BpeTokenizer { name: name.to_string(), bpe })).clone())
 }
 
-pub fn count_tokens(text: &str) -> usize {
-    let tokenizer = get_tokenizer();
-    tokenizer.encode_ordinary(text).len()
+impl TokenizerKind {
+    /// Every selectable tokenizer
+    #[allow(dead_code)]
+    pub const ALL: [TokenizerKind; 5] = [
+        TokenizerKind::Cl100k,
+        TokenizerKind::O200k,
+        TokenizerKind::P50k,
+        TokenizerKind::Words,
+        TokenizerKind::Chars,
+    ];
+    
+    /// Name used in the config file and status bars
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenizerKind::Cl100k => "cl100k_base",
+            TokenizerKind::O200k => "o200k_base",
+            TokenizerKind::P50k => "p50k_base",
+            TokenizerKind::Words => "words",
+            TokenizerKind::Chars => "chars",
+        }
+    }
+    
+    /// Look up a tokenizer by the name used in the config file
+    #[allow(dead_code)]
+    pub fn from_name(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+            .ok_or_else(|| anyhow!("Unknown tokenizer {} (expected one of: cl100k_base, o200k_base, p50k_base, words, chars)", name))
+    }
+    
+    /// Get the tokenizer, loading its encoding the first time it's used
+    pub fn tokenizer(self) -> Result<Arc<dyn Tokenizer>> {
+        match self {
+            TokenizerKind::Cl100k => shared_bpe(&CL100K, self.name(), cl100k_base),
+            TokenizerKind::O200k => shared_bpe(&O200K, self.name(), o200k_base),
+            TokenizerKind::P50k => shared_bpe(&P50K, self.name(), p50k_base),
+            TokenizerKind::Words => Ok(Arc::new(WordCounter)),
+            TokenizerKind::Chars => Ok(Arc::new(CharCounter)),
+        }
+    }
 }
 
-pub fn count_tokens_in_lines(lines: &[String]) -> usize {
-    let text = lines.join("\n");
-    count_tokens(&text)
+/// Pre-tokenization pattern of cl100k_base, used for ranks files by default
+const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+
+/// Pre-tokenization pattern of GPT-2, used by byte-level `tokenizer.json` files
+const GPT2_PATTERN: &str = r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+/// Merge rank of each token, lower ranks merging first
+type Ranks = HashMap<Vec<u8>, usize>;
+
+/// Load a BPE vocabulary from a local file
+/// 
+/// `tokenizer.json` files (HuggingFace) are recognised by their extension;
+/// anything else is read as a tiktoken ranks file, one base64 token and its
+/// rank per line. `pattern` overrides the regex used to split text before
+/// merging, which otherwise comes from the `tokenizer.json` or defaults to
+/// cl100k_base's. Nothing is downloaded.
+pub fn load_bpe_file(path: &Path, pattern: Option<&str>) -> Result<BpeTokenizer> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tokenizer file: {}", path.display()))?;
+    
+    let is_json = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+    let (ranks, file_pattern) = if is_json {
+        parse_tokenizer_json(&text)
+    } else {
+        parse_tiktoken_ranks(&text).map(|ranks| (ranks, None))
+    }
+    .with_context(|| format!("Invalid tokenizer file: {}", path.display()))?;
+    
+    // Text is encoded byte by byte before merging, so every byte needs a token
+    if let Some(byte) = (0..=255u8).find(|byte| !ranks.contains_key(&vec![*byte])) {
+        bail!(
+            "Tokenizer file {} has no token for byte {:#04x} (only byte-level BPE vocabularies are supported)",
+            path.display(),
+            byte
+        );
+    }
+    
+    let pattern = pattern.or(file_pattern.as_deref()).unwrap_or(CL100K_PATTERN);
+    let bpe = CoreBPE::new(ranks.into_iter().collect(), Default::default(), pattern)
+        .with_context(|| format!("Failed to build a tokenizer from: {}", path.display()))?;
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+    
+    Ok(BpeTokenizer { name, bpe })
+}
+
+/// Parse a tiktoken ranks file (`<base64 token> <rank>` per line)
+fn parse_tiktoken_ranks(text: &str) -> Result<Ranks> {
+    let mut ranks = HashMap::new();
+    let mut seen_ranks = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (token, rank) = line
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("Line {}: expected a base64 token and a rank", index + 1))?;
+        let token = base64::engine::general_purpose::STANDARD
+            .decode(token)
+            .with_context(|| format!("Line {}: invalid base64 token", index + 1))?;
+        let rank: usize = rank
+            .trim()
+            .parse()
+            .with_context(|| format!("Line {}: invalid rank", index + 1))?;
+        if !seen_ranks.insert(rank) {
+            bail!("Line {}: rank {} is used more than once", index + 1, rank);
+        }
+        ranks.insert(token, rank);
+    }
+    Ok(ranks)
+}
+
+/// Map the characters byte-level BPE uses in its vocabulary back to bytes
+/// 
+/// GPT-2 style tokenizers store tokens as printable text: printable bytes
+/// stand for themselves and the rest are shifted to code points from 256 on.
+fn byte_level_chars() -> HashMap<char, u8> {
+    let printable = |byte: u8| matches!(byte, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    let mut shifted = 0;
+    (0..=255u8)
+        .map(|byte| {
+            if printable(byte) {
+                (char::from(byte), byte)
+            } else {
+                shifted += 1;
+                (char::from_u32(255 + shifted).unwrap_or_default(), byte)
+            }
+        })
+        .collect()
+}
+
+/// Parse a byte-level BPE `tokenizer.json` into merge ranks and its split pattern
+/// 
+/// Ranks follow the merge order, which is what decides which pair is merged
+/// first. Single bytes come before every merge.
+fn parse_tokenizer_json(text: &str) -> Result<(Ranks, Option<String>)> {
+    let json: Value = serde_json::from_str(text).context("Not valid JSON")?;
+    let model = &json["model"];
+    if model["type"].as_str() != Some("BPE") {
+        bail!("Only BPE models are supported (found {})", model["type"]);
+    }
+    
+    let chars = byte_level_chars();
+    let to_bytes = |token: &str| -> Result<Vec<u8>> {
+        token
+            .chars()
+            .map(|c| chars.get(&c).copied())
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| anyhow!("Token {:?} isn't byte-level BPE (only byte-level BPE vocabularies are supported)", token))
+    };
+    
+    let mut ranks: Ranks = (0..=255u8).map(|byte| (vec![byte], byte as usize)).collect();
+    let merges = model["merges"].as_array().ok_or_else(|| anyhow!("The model has no merges"))?;
+    for merge in merges {
+        let (left, right) = match merge {
+            Value::String(merge) => merge.split_once(' ').ok_or_else(|| anyhow!("Invalid merge {:?}", merge))?,
+            Value::Array(pair) => match pair.as_slice() {
+                [Value::String(left), Value::String(right)] => (left.as_str(), right.as_str()),
+                _ => bail!("Invalid merge {}", merge),
+            },
+            _ => bail!("Invalid merge {}", merge),
+        };
+        let mut token = to_bytes(left)?;
+        token.extend(to_bytes(right)?);
+        let rank = ranks.len();
+        ranks.entry(token).or_insert(rank);
+    }
+    
+    Ok((ranks, split_pattern(&json["pre_tokenizer"])))
+}
+
+/// Find the regex a `tokenizer.json` pre-tokenizer splits text with
+fn split_pattern(pre_tokenizer: &Value) -> Option<String> {
+    match pre_tokenizer["type"].as_str()? {
+        "Sequence" => pre_tokenizer["pretokenizers"].as_array()?.iter().find_map(split_pattern),
+        "Split" => pre_tokenizer["pattern"]["Regex"].as_str().map(str::to_string),
+        "ByteLevel" if pre_tokenizer["use_regex"].as_bool() != Some(false) => Some(GPT2_PATTERN.to_string()),
+        _ => None,
+    }
+}
+
+/// The tokenizer used until one is chosen from the config
+pub fn default_tokenizer() -> Arc<dyn Tokenizer> {
+    TokenizerKind::default().tokenizer().expect("Failed to initialize the default tokenizer")
 }
 
 #[allow(dead_code)]
@@ -27,4 +347,3 @@ pub fn format_token_count(count: usize) -> String {
         _ => format!("{} tokens", count),
     }
 }
-
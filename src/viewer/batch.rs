@@ -0,0 +1,182 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use walkdir::WalkDir;
+
+use super::Viewer;
+use crate::storage::{ChunkStorage, Metadata};
+use crate::utils::prompt::PromptTemplate;
+use crate::utils::Tokenizer;
+
+/// How files are chunked without the TUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Follow the file's structure (Hoon arms, Markdown headings, syntax trees), or blank lines without one
+    Structure,
+    /// End chunks at blank lines, whatever the file
+    Blank,
+    /// Overlapping sliding windows
+    Windows,
+}
+
+impl ChunkStrategy {
+    /// Every strategy, in the order they're listed in help
+    pub const ALL: [ChunkStrategy; 3] = [ChunkStrategy::Structure, ChunkStrategy::Blank, ChunkStrategy::Windows];
+    
+    /// Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            ChunkStrategy::Structure => "structure",
+            ChunkStrategy::Blank => "blank",
+            ChunkStrategy::Windows => "windows",
+        }
+    }
+    
+    /// Look up a strategy by the name used on the command line
+    pub fn from_name(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.name() == name)
+            .ok_or_else(|| anyhow!("Unknown strategy {} (expected one of: structure, blank, windows)", name))
+    }
+}
+
+/// How to chunk files and where to save them
+#[derive(Clone)]
+pub struct BatchOptions {
+    /// How chunks are proposed
+    pub strategy: ChunkStrategy,
+    
+    /// Largest token count a chunk may have
+    pub max_tokens: usize,
+    
+    /// Tokens per sliding window and tokens shared by neighbouring windows
+    pub sliding_window: (usize, usize),
+    
+    /// Prompt chunks are wrapped in, counted against the budget
+    pub prompt_template: Option<PromptTemplate>,
+    
+    /// Tokenizer chunks are counted with
+    pub tokenizer: Arc<dyn Tokenizer>,
+    
+    /// Labels every saved chunk gets
+    pub labels: Vec<String>,
+    
+    /// Directory stored chunk paths are relative to
+    pub root_dir: PathBuf,
+    
+    /// Files never chunked, along with anything named after them (e.g. the
+    /// chunk file and its journal, when they're under a chunked directory)
+    pub exclude: Vec<PathBuf>,
+}
+
+/// What happened to a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOutcome {
+    /// Chunks were proposed and saved
+    Chunked {
+        /// Number of chunks saved
+        chunks: usize,
+        /// Token count of the largest of them
+        largest: usize,
+    },
+    /// Every line already belongs to a chunk
+    AlreadyChunked,
+    /// The file has no lines
+    Empty,
+    /// The file isn't UTF-8 text
+    NotText,
+    /// The file couldn't be read or chunked
+    Failed(String),
+}
+
+/// What happened to one file of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// The file, as given or found under a given directory
+    pub path: PathBuf,
+    /// What was done with it
+    pub outcome: FileOutcome,
+}
+
+/// Chunk every file in `paths` and save the chunks, without the TUI
+/// 
+/// Directories are walked recursively, skipping hidden files and
+/// directories. Only lines no chunk covers yet are chunked, so files that
+/// are already fully chunked are left alone and a batch can be re-run as
+/// new files arrive. A file that fails doesn't stop the others; its report
+/// says why. Fails only if a path doesn't exist.
+pub fn chunk_files(paths: &[PathBuf], chunk_storage: &mut ChunkStorage, options: &BatchOptions) -> Result<Vec<FileReport>> {
+    let files = collect_files(paths, &options.exclude)?;
+    
+    Ok(files
+        .into_iter()
+        .map(|path| {
+            let outcome = chunk_file(&path, chunk_storage, options).unwrap_or_else(|e| FileOutcome::Failed(format!("{:#}", e)));
+            FileReport { path, outcome }
+        })
+        .collect())
+}
+
+/// Chunk the lines of one file that no chunk covers yet
+fn chunk_file(path: &Path, chunk_storage: &mut ChunkStorage, options: &BatchOptions) -> Result<FileOutcome> {
+    // Binary files would fail to open as lines; they aren't worth an error
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    if bytes.contains(&0) || std::str::from_utf8(&bytes).is_err() {
+        return Ok(FileOutcome::NotText);
+    }
+    
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(options.max_tokens);
+    viewer.set_prompt_template(options.prompt_template.clone(), &options.root_dir);
+    viewer.set_sliding_window(options.sliding_window.0, options.sliding_window.1);
+    viewer.set_tokenizer(options.tokenizer.clone());
+    viewer.open_file(std::path::absolute(path)?)?;
+    if viewer.content().is_empty() {
+        return Ok(FileOutcome::Empty);
+    }
+    viewer.load_chunked_ranges(chunk_storage, &options.root_dir)?;
+    if viewer.unchunked_runs().is_empty() {
+        return Ok(FileOutcome::AlreadyChunked);
+    }
+    
+    viewer.count_tokens_now();
+    match options.strategy {
+        ChunkStrategy::Structure => viewer.propose_chunks()?,
+        ChunkStrategy::Blank => viewer.propose_chunks_along(None)?,
+        ChunkStrategy::Windows => viewer.propose_windows()?,
+    };
+    let largest = viewer.chunk_preview().unwrap_or_default().iter().map(|chunk| chunk.tokens).max().unwrap_or(0);
+    let chunks = viewer.save_chunk_preview(chunk_storage, &options.root_dir, options.labels.clone(), Metadata::new())?;
+    
+    Ok(FileOutcome::Chunked { chunks, largest })
+}
+
+/// List the files in `paths`, walking directories in name order
+fn collect_files(paths: &[PathBuf], exclude: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let excluded = |path: &Path| {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        exclude.iter().any(|excluded| path.to_string_lossy().starts_with(&*excluded.to_string_lossy()))
+    };
+    
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.exists() {
+            bail!("No such file or directory: {}", path.display());
+        }
+        
+        // Hidden entries (e.g. .git) are skipped, unless they were asked for by name
+        let walk = WalkDir::new(path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+        for entry in walk {
+            let entry = entry.with_context(|| format!("Failed to read directory: {}", path.display()))?;
+            if entry.file_type().is_file() && !excluded(entry.path()) {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    
+    Ok(files)
+}
@@ -1,9 +1,70 @@
 use anyhow::{Context, Result, anyhow};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use crate::utils::{count_tokens, count_tokens_in_lines};
-use crate::storage::{ChunkStorage, Chunk};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::chunker::{ChunkPlanner, ProposedChunk, Structure, BREADCRUMB_FIELD};
+use crate::utils::prompt::{PromptContext, PromptTemplate};
+use crate::utils::token_cache::{content_hash, BackgroundCounter, LineTokenCounts};
+use crate::utils::{default_tokenizer, Tokenizer};
+use crate::storage::{
+    coverage_percentage, hash_lines, line_lengths, reanchor_chunks, same_window, ChunkSpan, ChunkStorage, Chunk, Metadata, MetadataValue, ReanchorReport,
+};
+
+mod batch;
+
+pub use batch::{chunk_files, BatchOptions, ChunkStrategy, FileOutcome, FileReport};
+
+/// A stored chunk as tracked by the viewer
+#[derive(Debug, Clone)]
+pub struct ChunkedRange {
+    /// Id of the chunk in storage
+    pub id: String,
+    /// First line of the chunk (0-indexed)
+    pub start: usize,
+    /// Last line of the chunk (0-indexed)
+    pub end: usize,
+    /// First character on the first line, or `None` for the whole line
+    pub start_column: Option<usize>,
+    /// One past the last character on the last line, or `None` for the whole line
+    pub end_column: Option<usize>,
+    /// Whether the source lines changed since the chunk was saved
+    pub stale: bool,
+    /// Id of the sliding-window pass the chunk belongs to, empty for other chunks
+    pub window_id: String,
+}
+
+impl ChunkedRange {
+    /// The chunk's position in storage terms (1-indexed lines)
+    pub fn span(&self) -> ChunkSpan {
+        ChunkSpan {
+            start_line: self.start + 1,
+            start_column: self.start_column,
+            end_line: self.end + 1,
+            end_column: self.end_column,
+        }
+    }
+    
+    /// Whether the chunk shares characters with another one without meaning to
+    /// 
+    /// Windows of the same sliding-window pass overlap on purpose.
+    pub fn overlaps_unintentionally(&self, other: &ChunkedRange) -> bool {
+        self.span().overlaps(&other.span()) && !same_window(&self.window_id, &other.window_id)
+    }
+}
+
+/// Chunks proposed for a file, shown for review before they're saved
+struct ChunkPreview {
+    /// The proposed chunks, ordered by start line
+    chunks: Vec<ProposedChunk>,
+    /// Token budget the chunks were planned for
+    max_tokens: usize,
+    /// Whether the chunks are overlapping sliding windows
+    windowed: bool,
+}
 
 /// Text viewer component
 pub struct Viewer {
@@ -19,18 +80,48 @@ pub struct Viewer {
     selection_mode: bool,
     /// The line where selection started
     selection_start: Option<usize>,
+    /// The character column where selection started (character-wise selection only)
+    selection_start_column: usize,
+    /// Whether the selection is character-wise instead of whole lines
+    char_selection: bool,
     /// The current cursor position (used for selection)
     cursor_position: usize,
-    /// Ranges of lines that have been chunked (start, end)
-    chunked_ranges: Vec<(usize, usize)>,
+    /// The current cursor column in characters (used for character-wise selection)
+    cursor_column: usize,
+    /// Chunks saved for the current file, with 0-indexed line ranges
+    chunked_ranges: Vec<ChunkedRange>,
+    /// Id of the stored chunk currently re-opened in the editor, if any
+    reopened_chunk_id: Option<String>,
     /// Whether the current selection contains edited content
     has_edited_content: bool,
-    /// Total token count for the entire file
-    total_tokens: usize,
-    /// Token counts per line
-    tokens_per_line: Vec<usize>,
+    /// Token counts per line (cached by line content), with the configured tokenizer
+    /// 
+    /// Filled in progressively by a background job after a file is opened.
+    line_tokens: LineTokenCounts,
+    /// Exact count of the whole file, done in the background
+    exact_total: BackgroundCounter,
+    /// Exact count of the selection, done in the background
+    exact_selection: BackgroundCounter,
+    /// Bumped whenever the content changes, so outdated exact counts are ignored
+    content_version: u64,
     /// Maximum tokens allowed per chunk (configurable)
     max_tokens_per_chunk: usize,
+    /// Prompt chunks are wrapped in, counted with the selection (configurable)
+    prompt_template: Option<PromptTemplate>,
+    /// Directory chunk paths are relative to, for the prompt's `{file_path}`
+    prompt_root: PathBuf,
+    /// Whether token boundaries and per-line token counts are shown
+    show_token_boundaries: bool,
+    /// Tokens per sliding window and tokens shared by neighbouring windows (configurable)
+    sliding_window: (usize, usize),
+    /// Chunks proposed for the file, shown for review before they're saved
+    chunk_preview: Option<ChunkPreview>,
+}
+
+impl Default for Viewer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Viewer {
@@ -43,12 +134,23 @@ impl Viewer {
             scroll_position: 0,
             selection_mode: false,
             selection_start: None,
+            selection_start_column: 0,
+            char_selection: false,
             cursor_position: 0,
+            cursor_column: 0,
             chunked_ranges: Vec::new(),
+            reopened_chunk_id: None,
             has_edited_content: false,
-            total_tokens: 0,
-            tokens_per_line: Vec::new(),
+            line_tokens: LineTokenCounts::new(default_tokenizer()),
+            exact_total: BackgroundCounter::new(default_tokenizer()),
+            exact_selection: BackgroundCounter::new(default_tokenizer()),
+            content_version: 0,
             max_tokens_per_chunk: 8192, // Default max tokens (configurable)
+            prompt_template: None,
+            prompt_root: PathBuf::new(),
+            show_token_boundaries: false,
+            sliding_window: (1024, 128),
+            chunk_preview: None,
         }
     }
     
@@ -80,6 +182,51 @@ impl Viewer {
         self.max_tokens_per_chunk
     }
     
+    /// Set the prompt template selections are counted in
+    /// 
+    /// `root_dir` is the directory chunk paths are stored relative to.
+    pub fn set_prompt_template(&mut self, template: Option<PromptTemplate>, root_dir: &Path) {
+        self.prompt_template = template;
+        self.prompt_root = root_dir.to_path_buf();
+        self.content_version += 1;
+    }
+    
+    /// Check whether selections are counted in a prompt template
+    pub fn has_prompt_template(&self) -> bool {
+        self.prompt_template.is_some()
+    }
+    
+    /// Describe the selection for the prompt template, as it would be stored
+    pub fn selection_prompt_context(&self) -> Option<PromptContext> {
+        let (start, end) = self.selection_range()?;
+        Some(PromptContext {
+            file_path: self.relative_file_path(&self.prompt_root)?.to_string_lossy().to_string(),
+            start_line: self.to_storage_index(start),
+            end_line: self.to_storage_index(end),
+        })
+    }
+    
+    /// Tokens the prompt template adds around the selection
+    fn prompt_overhead(&self) -> usize {
+        match (&self.prompt_template, self.selection_prompt_context()) {
+            (Some(template), Some(context)) => self.line_tokens.tokenizer().count(&template.render(&context, "")),
+            _ => 0,
+        }
+    }
+    
+    /// Set the tokenizer and recount the open file with it
+    pub fn set_tokenizer(&mut self, tokenizer: Arc<dyn Tokenizer>) {
+        self.line_tokens = LineTokenCounts::new(tokenizer.clone());
+        self.exact_total = BackgroundCounter::new(tokenizer.clone());
+        self.exact_selection = BackgroundCounter::new(tokenizer);
+        self.update_token_counts();
+    }
+    
+    /// Get the name of the tokenizer used for token counts
+    pub fn tokenizer_name(&self) -> &str {
+        self.line_tokens.tokenizer().name()
+    }
+    
     /// Open a file in the viewer
     pub fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         let path = path.as_ref().to_path_buf();
@@ -103,12 +250,16 @@ impl Viewer {
         self.original_content = content;
         self.scroll_position = 0;
         self.cursor_position = 0;
+        self.cursor_column = 0;
         self.selection_mode = false;
         self.selection_start = None;
+        self.char_selection = false;
         self.chunked_ranges = Vec::new();
+        self.reopened_chunk_id = None;
         self.has_edited_content = false;
+        self.chunk_preview = None;
         
-        // Count tokens
+        // Count tokens in the background, so the file shows up straight away
         self.update_token_counts();
         
         // Load existing chunks for this file if any exist
@@ -120,14 +271,89 @@ impl Viewer {
     }
     
     /// Update token counts for the entire file and per line
+    /// 
+    /// Lines are counted on a worker thread (cancelling the job for the
+    /// previous file) and through the cache; `poll_token_counts` picks up
+    /// their counts and the exact totals.
     fn update_token_counts(&mut self) {
-        // Count tokens for the whole file
-        self.total_tokens = count_tokens_in_lines(&self.content);
+        self.content_version += 1;
+        self.line_tokens.recount_in_background(self.content.clone());
+    }
+    
+    /// Queue exact recounts of the file and selection, and pick up finished ones
+    /// 
+    /// Called once per frame. Returns true if line counts or an exact count
+    /// arrived, so the screen shows them.
+    pub fn poll_token_counts(&mut self) -> bool {
+        let updated = self.line_tokens.poll() | self.exact_total.poll() | self.exact_selection.poll();
         
-        // Count tokens per line
-        self.tokens_per_line = Vec::with_capacity(self.content.len());
-        for line in &self.content {
-            self.tokens_per_line.push(count_tokens(line));
+        let content = &self.content;
+        self.exact_total.request(self.content_version, || content.join("\n"));
+        if let Some((key, start, end)) = self.selection_key() {
+            let (start_column, end_column) = self.selection_columns();
+            let prompt = self.prompt_template.as_ref().zip(self.selection_prompt_context());
+            self.exact_selection.request(key, || {
+                let selected = Self::slice_lines(&content[start..=end], start_column, end_column).join("\n");
+                match prompt {
+                    Some((template, context)) => template.render(&context, &selected),
+                    None => selected,
+                }
+            });
+        }
+        
+        updated
+    }
+    
+    /// Key of the exact count for the current selection, with its line range
+    fn selection_key(&self) -> Option<(u64, usize, usize)> {
+        let (start, end) = self.selection_range().filter(|&(_, end)| end < self.content.len())?;
+        let (start_column, end_column) = self.selection_columns();
+        Some((content_hash(&(self.content_version, start, end, start_column, end_column)), start, end))
+    }
+    
+    /// Estimate the selection's tokens from the cached line counts
+    /// 
+    /// Only the partly selected first and last lines of a character-wise
+    /// selection are encoded. The prompt template, if any, is added on top.
+    fn estimate_selection_tokens(&self, start: usize, end: usize) -> usize {
+        let (start_column, end_column) = self.selection_columns();
+        if start_column.is_none() && end_column.is_none() {
+            return self.line_tokens.range_total(start, end) + self.prompt_overhead();
+        }
+        
+        let tokenizer = self.line_tokens.tokenizer();
+        let count = |line: usize, from: Option<usize>, to: Option<usize>| {
+            tokenizer.count(&Self::slice_lines(&self.content[line..=line], from, to)[0])
+        };
+        let selected = if start == end {
+            count(start, start_column, end_column)
+        } else {
+            let newlines = if end - start > 1 { 2 } else { 1 };
+            count(start, start_column, None) + self.line_tokens.range_total(start + 1, end - 1) + count(end, None, end_column) + newlines
+        };
+        selected + self.prompt_overhead()
+    }
+    
+    /// Count every line of the file right away instead of in the background
+    /// 
+    /// For chunking without the TUI, where there's nothing to show meanwhile.
+    pub fn count_tokens_now(&mut self) {
+        self.line_tokens.recount(&self.content);
+    }
+    
+    /// Lines counted so far and lines in total while the file is still being counted
+    pub fn token_counting_progress(&self) -> Option<(usize, usize)> {
+        self.line_tokens.progress()
+    }
+    
+    /// Whether a token count on screen is still an estimate
+    /// 
+    /// This is the selection's count while there is a selection, otherwise the
+    /// file's total.
+    pub fn token_count_pending(&self) -> bool {
+        match self.selection_key() {
+            Some((key, _, _)) => self.exact_selection.result(key).is_none(),
+            None => self.exact_total.result(self.content_version).is_none(),
         }
     }
     
@@ -138,14 +364,88 @@ impl Viewer {
                 // Entering selection mode - set selection start
                 self.selection_mode = true;
                 self.selection_start = Some(self.cursor_position);
+                self.char_selection = false;
             } else {
                 // Exiting selection mode - clear the selection
-                self.selection_mode = false;
-                self.selection_start = None;
+                self.clear_selection();
+            }
+        }
+    }
+    
+    /// Toggle character-wise selection mode
+    /// 
+    /// The selection starts at the cursor column instead of the start of the
+    /// line, so chunks can begin and end part-way through a line.
+    pub fn toggle_char_selection_mode(&mut self) {
+        if !self.content.is_empty() {
+            if !self.selection_mode {
+                self.selection_mode = true;
+                self.selection_start = Some(self.cursor_position);
+                self.selection_start_column = self.cursor_column;
+                self.char_selection = true;
+            } else {
+                self.clear_selection();
             }
         }
     }
     
+    /// Check if the current selection is character-wise
+    pub fn is_char_selection(&self) -> bool {
+        self.char_selection
+    }
+    
+    /// Get the current cursor column (in characters)
+    pub fn cursor_column(&self) -> usize {
+        self.cursor_column
+    }
+    
+    /// Move the cursor left by a number of characters within the current line
+    pub fn cursor_left(&mut self, count: usize) {
+        self.cursor_column = self.cursor_column.saturating_sub(count);
+    }
+    
+    /// Move the cursor right by a number of characters within the current line
+    pub fn cursor_right(&mut self, count: usize) {
+        self.cursor_column += count;
+        self.clamp_cursor_column();
+    }
+    
+    /// Handle a key that moves the cursor within the current line
+    /// 
+    /// Left/Right and h/l move by one character, ten with Shift. Outside a
+    /// selection this places the cursor where a character-wise selection
+    /// will start. Left and h at the start of the line are left unhandled
+    /// unless a character-wise selection is under way, so they still go
+    /// back to the explorer. Returns whether the key was handled.
+    pub fn handle_column_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            || (self.selection_mode && !self.char_selection)
+        {
+            return false;
+        }
+        let count = if key.modifiers.contains(KeyModifiers::SHIFT) { 10 } else { 1 };
+        
+        match key.code {
+            KeyCode::Left | KeyCode::Char('h' | 'H') if self.char_selection || self.cursor_column > 0 => {
+                self.cursor_left(count);
+                true
+            },
+            KeyCode::Right | KeyCode::Char('l' | 'L') => {
+                self.cursor_right(count);
+                true
+            },
+            _ => false,
+        }
+    }
+    
+    /// Keep the cursor column on a character of the current line
+    fn clamp_cursor_column(&mut self) {
+        let line_length = self.content
+            .get(self.cursor_position)
+            .map_or(0, |line| line.chars().count());
+        self.cursor_column = self.cursor_column.min(line_length.saturating_sub(1));
+    }
+    
     /// Check if selection mode is active
     pub fn is_selection_mode(&self) -> bool {
         self.selection_mode
@@ -168,23 +468,72 @@ impl Viewer {
         })
     }
     
+    /// Get the column bounds of the current selection
+    /// 
+    /// Returns the first selected character on the first line and one past the
+    /// last selected character on the last line. A bound is `None` when the
+    /// selection reaches the start or end of that line, which is always the
+    /// case for line-wise selections.
+    pub fn selection_columns(&self) -> (Option<usize>, Option<usize>) {
+        let Some(anchor) = self.selection_start.filter(|_| self.char_selection) else {
+            return (None, None);
+        };
+        
+        let (start, end) = if (anchor, self.selection_start_column) <= (self.cursor_position, self.cursor_column) {
+            ((anchor, self.selection_start_column), (self.cursor_position, self.cursor_column))
+        } else {
+            ((self.cursor_position, self.cursor_column), (anchor, self.selection_start_column))
+        };
+        
+        let end_length = self.content.get(end.0).map_or(0, |line| line.chars().count());
+        let start_column = Some(start.1).filter(|&column| column > 0);
+        let end_column = Some(end.1 + 1).filter(|&column| column < end_length);
+        
+        (start_column, end_column)
+    }
+    
+    /// Get the selected text, trimmed to the selection columns
+    pub fn selected_lines(&self) -> Vec<String> {
+        match self.selection_range() {
+            Some((start, end)) if end < self.content.len() => {
+                let (start_column, end_column) = self.selection_columns();
+                Self::slice_lines(&self.content[start..=end], start_column, end_column)
+            },
+            _ => Vec::new(),
+        }
+    }
+    
+    /// Cut the first and last of a block of lines down to the given columns
+    fn slice_lines(lines: &[String], start_column: Option<usize>, end_column: Option<usize>) -> Vec<String> {
+        let last = lines.len().saturating_sub(1);
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let from = if i == 0 { start_column.unwrap_or(0) } else { 0 };
+                let to = if i == last { end_column.unwrap_or(usize::MAX) } else { usize::MAX };
+                line.chars().take(to).skip(from).collect()
+            })
+            .collect()
+    }
+    
     /// Get token count for the current selection
+    /// 
+    /// The exact count once the background recount finished, otherwise an
+    /// estimate from the per-line counts. With a prompt template this is the
+    /// count of the selection rendered in it.
     pub fn selection_token_count(&self) -> Option<usize> {
-        self.selection_range().map(|(start, end)| {
-            if start >= self.content.len() || end >= self.content.len() {
-                0
-            } else {
-                let selected_lines = &self.content[start..=end];
-                count_tokens_in_lines(selected_lines)
-            }
-        })
+        let (key, start, end) = self.selection_key()?;
+        Some(self.exact_selection.result(key).unwrap_or_else(|| self.estimate_selection_tokens(start, end)))
     }
     
     // Removed unused function: selection_exceeds_token_limit
     
     /// Get token count for the entire file
+    /// 
+    /// Exact once the background recount finished, estimated until then.
     pub fn total_token_count(&self) -> usize {
-        self.total_tokens
+        self.exact_total.result(self.content_version).unwrap_or_else(|| self.line_tokens.total())
     }
     
     // Removed unused function: formatted_selection_token_count
@@ -193,6 +542,7 @@ impl Viewer {
     pub fn clear_selection(&mut self) {
         self.selection_mode = false;
         self.selection_start = None;
+        self.char_selection = false;
     }
     
     /// Get the current file path
@@ -213,6 +563,7 @@ impl Viewer {
     /// Move cursor up one line
     pub fn cursor_up(&mut self) {
         self.cursor_position = self.cursor_position.saturating_sub(1);
+        self.clamp_cursor_column();
         
         // Ensure cursor is visible by scrolling if needed
         if self.cursor_position < self.scroll_position {
@@ -224,6 +575,7 @@ impl Viewer {
     pub fn cursor_down(&mut self) {
         if !self.content.is_empty() {
             self.cursor_position = (self.cursor_position + 1).min(self.content.len().saturating_sub(1));
+            self.clamp_cursor_column();
             
             // Ensure cursor is visible by scrolling if needed
             if self.cursor_position >= self.scroll_position + 20 { // Arbitrary threshold assuming 20 visible lines
@@ -325,8 +677,20 @@ impl Viewer {
     
     // Removed unused function: is_whitespace_line
     
-    /// Save current selection as a chunk using CSV storage
+    /// Save current selection as a chunk in chunk storage
+    #[allow(dead_code)]
     pub fn save_selection_as_chunk(&mut self, chunk_storage: &mut ChunkStorage, root_dir: &Path) -> Result<String> {
+        self.save_annotated_selection(chunk_storage, root_dir, Vec::new(), Metadata::new())
+    }
+    
+    /// Save current selection as a chunk with the given labels and metadata
+    pub fn save_annotated_selection(
+        &mut self,
+        chunk_storage: &mut ChunkStorage,
+        root_dir: &Path,
+        labels: Vec<String>,
+        metadata: Metadata,
+    ) -> Result<String> {
         // Get selected range
         let range = self.selection_range().ok_or_else(|| anyhow!("No text selected"))?;
         
@@ -336,22 +700,15 @@ impl Viewer {
         }
         
         // Check for overlap with existing chunks
-        let has_overlap = self.check_chunk_overlap(range.0, range.1);
+        let has_overlap = self.selection_overlaps_chunks();
         
-        // Extract the lines from the current in-memory content (which may have been edited)
+        // Extract the selected text from the current in-memory content (which may have been edited)
         // Make sure to include both start and end indices inclusively
-        let selected_content = &self.content[range.0..=range.1];
+        let (start_column, end_column) = self.selection_columns();
+        let selected_content = self.selected_lines();
         
         // Get file path and make it relative to root if needed
-        let file_path = self.file_path().ok_or_else(|| anyhow!("No file opened"))?;
-        let relative_path = if file_path.starts_with(root_dir) {
-            match file_path.strip_prefix(root_dir) {
-                Ok(rel_path) => rel_path.to_path_buf(),
-                Err(_) => file_path.to_path_buf(),
-            }
-        } else {
-            file_path.to_path_buf()
-        };
+        let relative_path = self.relative_file_path(root_dir).ok_or_else(|| anyhow!("No file opened"))?;
         
         // Check if content has been edited
         let was_edited = self.has_edited_content;
@@ -359,20 +716,43 @@ impl Viewer {
         // Join the selected lines into a single string
         let content = selected_content.join("\n");
         
+        // Fingerprint the file and the original (unedited) lines the chunk covers.
+        // Once an edit added or removed lines, the selection no longer lines up
+        // with the file, so such chunks are saved without a fingerprint (and are
+        // never reported as stale) rather than with one of the wrong lines.
+        let (source_hash, lines_hash) = match self.original_content.get(range.0..=range.1) {
+            Some(original) if self.content.len() == self.original_content.len() => {
+                (hash_lines(&self.original_content), hash_lines(original))
+            },
+            _ => (String::new(), String::new()),
+        };
+        
         // Create a new chunk (Chunk uses 1-indexed line numbers)
-        let chunk = Chunk::new(
+        let mut chunk = Chunk::new(
             relative_path,
             self.to_storage_index(range.0), // Convert from 0-indexed (Viewer) to 1-indexed (Chunk)
             self.to_storage_index(range.1), // Convert from 0-indexed (Viewer) to 1-indexed (Chunk)
             content,
             was_edited,
-        );
+        )
+        .with_columns(start_column, end_column)
+        .with_fingerprint(source_hash, lines_hash);
+        chunk.labels = labels;
+        chunk.metadata = metadata;
         
         // Add the chunk to storage
         chunk_storage.add_chunk(chunk.clone())?;
         
         // Add to chunked ranges (keeping 0-indexed internally)
-        self.chunked_ranges.push((range.0, range.1));
+        self.chunked_ranges.push(ChunkedRange {
+            id: chunk.id.clone(),
+            start: range.0,
+            end: range.1,
+            start_column,
+            end_column,
+            stale: false,
+            window_id: String::new(),
+        });
         
         // Return the chunk ID and overlap status
         Ok(format!("{}{}", 
@@ -381,35 +761,274 @@ impl Viewer {
         ))
     }
     
-    /// Check if a range overlaps with existing chunks
+    /// Check if the current selection overlaps with existing chunks
     /// 
-    /// Note: This function expects 0-indexed values for line numbers
-    pub fn check_chunk_overlap(&self, start_line: usize, end_line: usize) -> bool {
-        for (chunk_start, chunk_end) in &self.chunked_ranges {
-            // Check for any overlap
-            if !(end_line < *chunk_start || start_line > *chunk_end) {
-                return true;
-            }
-        }
-        false
+    /// Character-wise selections only overlap a chunk if they share a character
+    /// with it, so two chunks may split a line between them.
+    pub fn selection_overlaps_chunks(&self) -> bool {
+        let Some((start, end)) = self.selection_range() else {
+            return false;
+        };
+        let (start_column, end_column) = self.selection_columns();
+        let span = ChunkSpan {
+            start_line: self.to_storage_index(start),
+            start_column,
+            end_line: self.to_storage_index(end),
+            end_column,
+        };
+        self.chunked_ranges.iter().any(|chunked| chunked.span().overlaps(&span))
     }
     
     /// Check if a line is part of a saved chunk
     /// 
+    /// Lines only partly covered by a chunk count as chunked.
+    /// 
     /// Note: This function expects 0-indexed values for line numbers
     pub fn is_line_chunked(&self, line_number: usize) -> bool {
-        self.chunked_ranges.iter().any(|(start, end)| {
-            line_number >= *start && line_number <= *end
+        self.chunk_at(line_number).is_some()
+    }
+    
+    /// Get the character columns of a line covered by each chunk, as `(start, end, stale)`
+    /// 
+    /// Columns are `[start, end)` character offsets, listed in the order the chunks
+    /// were saved. Empty lines inside a chunk are reported as `(0, 0)`.
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn chunk_columns_on_line(&self, line_number: usize) -> Vec<(usize, usize, bool)> {
+        let line_length = self.content.get(line_number).map_or(0, |line| line.chars().count());
+        self.chunked_ranges
+            .iter()
+            .filter_map(|chunked| {
+                chunked.span()
+                    .columns_on_line(self.to_storage_index(line_number), line_length)
+                    .map(|(start, end)| (start, end, chunked.stale))
+            })
+            .collect()
+    }
+    
+    /// Toggle showing token boundaries and per-line token counts
+    pub fn toggle_token_boundaries(&mut self) {
+        self.show_token_boundaries = !self.show_token_boundaries;
+    }
+    
+    /// Check if token boundaries and per-line token counts are shown
+    pub fn shows_token_boundaries(&self) -> bool {
+        self.show_token_boundaries
+    }
+    
+    /// Get the character columns of each token on a line, as `[start, end)`
+    /// 
+    /// The line is encoded on its own with the configured tokenizer, so this
+    /// is meant for the few lines on screen.
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn token_columns_on_line(&self, line_number: usize) -> Vec<(usize, usize)> {
+        let Some(line) = self.content.get(line_number) else {
+            return Vec::new();
+        };
+        
+        // Token spans are byte ranges in order; map them to character columns
+        // in a single pass over the line
+        let mut boundaries = line.char_indices()
+            .map(|(byte, _)| byte)
+            .chain(std::iter::once(line.len()))
+            .enumerate()
+            .peekable();
+        let mut column_at = |byte: usize| {
+            while boundaries.next_if(|&(_, start)| start < byte).is_some() {}
+            boundaries.peek().map_or(0, |&(column, _)| column)
+        };
+        self.line_tokens
+            .tokenizer()
+            .token_spans(line)
+            .into_iter()
+            .map(|span| (column_at(span.start), column_at(span.end)))
+            .collect()
+    }
+    
+    /// Get the token count of a line, or `None` while it's still being counted
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn line_token_count(&self, line_number: usize) -> Option<usize> {
+        let counted = self.line_tokens.progress().map_or(self.content.len(), |(counted, _)| counted);
+        (line_number < counted).then(|| self.line_tokens.line(line_number))
+    }
+    
+    /// Get how dense a line is compared to the densest line of the file
+    /// 
+    /// Ranges from 0.0 for a line without tokens to 1.0 for the line with the
+    /// most tokens, or `None` while the file is still being counted.
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn line_token_density(&self, line_number: usize) -> Option<f64> {
+        if self.line_tokens.progress().is_some() || line_number >= self.content.len() {
+            return None;
+        }
+        let densest = self.line_tokens.densest();
+        Some(if densest == 0 { 0.0 } else { self.line_tokens.line(line_number) as f64 / densest as f64 })
+    }
+    
+    /// Get the tokens in each of `buckets` equal runs of lines across the file
+    /// 
+    /// Values are relative to the fullest bucket, so 1.0 is the densest part
+    /// of the file. Short files get one bucket per line. `None` while the file
+    /// is still being counted.
+    pub fn token_density_profile(&self, buckets: usize) -> Option<Vec<f64>> {
+        if self.line_tokens.progress().is_some() {
+            return None;
+        }
+        let counts = self.line_tokens.lines();
+        let buckets = buckets.min(counts.len());
+        if buckets == 0 {
+            return Some(Vec::new());
+        }
+        
+        let mut totals = vec![0usize; buckets];
+        for (index, &count) in counts.iter().enumerate() {
+            totals[index * buckets / counts.len()] += count;
+        }
+        let fullest = totals.iter().copied().max().unwrap_or(0).max(1);
+        Some(totals.into_iter().map(|total| total as f64 / fullest as f64).collect())
+    }
+    
+    /// Get the character columns of a line covered by the selection, as `[start, end)`
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn selection_columns_on_line(&self, line_number: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.selection_range()?;
+        let (start_column, end_column) = self.selection_columns();
+        let line_length = self.content.get(line_number).map_or(0, |line| line.chars().count());
+        
+        ChunkSpan {
+            start_line: start,
+            start_column,
+            end_line: end,
+            end_column,
+        }.columns_on_line(line_number, line_length)
+    }
+    
+    /// Check if the chunk shown on a line (the most recent one covering it) is stale
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn is_line_stale(&self, line_number: usize) -> bool {
+        self.chunk_at(line_number).is_some_and(|chunked| chunked.stale)
+    }
+    
+    /// Number of chunks in the current file whose source lines have changed
+    pub fn stale_chunk_count(&self) -> usize {
+        self.chunked_ranges.iter().filter(|chunked| chunked.stale).count()
+    }
+    
+    /// Get the most recently saved chunk that covers a line
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn chunk_at(&self, line_number: usize) -> Option<&ChunkedRange> {
+        let line_length = self.content.get(line_number).map_or(0, |line| line.chars().count());
+        self.chunked_ranges.iter().rev().find(|chunked| {
+            chunked.span().columns_on_line(self.to_storage_index(line_number), line_length).is_some()
         })
     }
     
-    /// Get all chunked ranges
+    /// Get the chunk under the cursor
+    /// 
+    /// Prefers the most recent chunk containing the cursor column, falling back
+    /// to any chunk on the cursor line.
+    pub fn chunk_at_cursor(&self) -> Option<&ChunkedRange> {
+        let line = self.to_storage_index(self.cursor_position);
+        self.chunked_ranges
+            .iter()
+            .rev()
+            .find(|chunked| chunked.span().contains(line, self.cursor_column))
+            .or_else(|| self.chunk_at(self.cursor_position))
+    }
+    
+    /// Get all chunked ranges as 0-indexed (start, end) pairs
     #[allow(dead_code)]
-    pub fn chunked_ranges(&self) -> &[(usize, usize)] {
-        &self.chunked_ranges
+    pub fn chunked_ranges(&self) -> Vec<(usize, usize)> {
+        self.chunked_ranges
+            .iter()
+            .map(|chunked| (chunked.start, chunked.end))
+            .collect()
+    }
+    
+    /// Delete the chunk under the cursor from storage
+    /// 
+    /// Returns the deleted chunk, or `None` if the cursor isn't on a chunked line.
+    pub fn delete_chunk_at_cursor(&mut self, chunk_storage: &mut ChunkStorage) -> Result<Option<Chunk>> {
+        let Some(id) = self.chunk_at_cursor().map(|chunked| chunked.id.clone()) else {
+            return Ok(None);
+        };
+        
+        let deleted = chunk_storage.delete_chunk(&id)?;
+        self.chunked_ranges.retain(|chunked| chunked.id != id);
+        
+        Ok(deleted)
+    }
+    
+    /// Get the stored chunk under the cursor, e.g. to edit its labels
+    pub fn stored_chunk_at_cursor(&self, chunk_storage: &ChunkStorage) -> Result<Option<Chunk>> {
+        match self.chunk_at_cursor() {
+            Some(chunked) => chunk_storage.get_chunk(&chunked.id),
+            None => Ok(None),
+        }
+    }
+    
+    /// Re-open the chunk under the cursor for editing
+    /// 
+    /// Returns the stored chunk so its content can be loaded into the editor.
+    /// The chunk stays re-opened until `save_reopened_chunk` or `clear_reopened_chunk`.
+    pub fn reopen_chunk_at_cursor(&mut self, chunk_storage: &ChunkStorage) -> Result<Option<Chunk>> {
+        let Some(id) = self.chunk_at_cursor().map(|chunked| chunked.id.clone()) else {
+            return Ok(None);
+        };
+        
+        let chunk = chunk_storage.get_chunk(&id)?
+            .ok_or_else(|| anyhow!("Chunk {} is no longer in storage", id))?;
+        self.reopened_chunk_id = Some(id);
+        
+        Ok(Some(chunk))
+    }
+    
+    /// Work out new line ranges for this file's stale chunks
+    /// 
+    /// Nothing is written to storage; apply `ReanchorReport::updated_chunks`
+    /// with `ChunkStorage::update_chunks` to accept the result.
+    pub fn reanchor_stale_chunks(&self, chunk_storage: &ChunkStorage, root_dir: &Path) -> Result<ReanchorReport> {
+        let relative_path = self.relative_file_path(root_dir).ok_or_else(|| anyhow!("No file opened"))?;
+        let file_chunks = chunk_storage.get_chunks_for_file(&relative_path)?;
+        
+        Ok(reanchor_chunks(&file_chunks, &self.original_content))
+    }
+    
+    /// Get the id of the chunk currently re-opened for editing
+    pub fn reopened_chunk_id(&self) -> Option<&str> {
+        self.reopened_chunk_id.as_deref()
+    }
+    
+    /// Stop tracking a re-opened chunk without saving it
+    pub fn clear_reopened_chunk(&mut self) {
+        self.reopened_chunk_id = None;
+    }
+    
+    /// Save edited content back into the re-opened chunk under its existing id
+    pub fn save_reopened_chunk(&mut self, chunk_storage: &mut ChunkStorage, edited_content: Vec<String>) -> Result<String> {
+        let id = self.reopened_chunk_id.take().ok_or_else(|| anyhow!("No chunk re-opened for editing"))?;
+        let mut chunk = chunk_storage.get_chunk(&id)?
+            .ok_or_else(|| anyhow!("Chunk {} is no longer in storage", id))?;
+        
+        // The chunk counts as edited if it no longer matches the original file lines
+        let start = self.to_viewer_index(chunk.start_line);
+        let end = self.to_viewer_index(chunk.end_line);
+        let original = self.original_content.get(start..=end);
+        chunk.edited = original != Some(edited_content.as_slice());
+        chunk.content = edited_content.join("\n");
+        
+        chunk_storage.update_chunk(chunk)?;
+        
+        Ok(id)
     }
     
-    /// Load chunked ranges from CSV storage
+    /// Load chunked ranges from chunk storage
     pub fn load_chunked_ranges(&mut self, chunk_storage: &ChunkStorage, root_dir: &Path) -> Result<()> {
         // Only proceed if we have a file path
         let file_path = match &self.file_path {
@@ -431,44 +1050,377 @@ impl Viewer {
         };
         
         // Get all chunks for this file from storage
-        let file_chunks = chunk_storage.get_chunks_for_file(&relative_path);
+        let file_chunks = chunk_storage.get_chunks_for_file(&relative_path)?;
+        
+        // Flag chunks whose original lines no longer match the file
+        let source_hash = hash_lines(&self.original_content);
         
         // Extract and add the ranges (converting from 1-indexed in storage to 0-indexed used internally)
         for chunk in file_chunks {
-            self.chunked_ranges.push((
-                self.to_viewer_index(chunk.start_line),
-                self.to_viewer_index(chunk.end_line)
-            ));
+            let start = self.to_viewer_index(chunk.start_line);
+            let end = self.to_viewer_index(chunk.end_line);
+            let stale = chunk.is_stale(&source_hash, self.original_content.get(start..=end));
+            
+            self.chunked_ranges.push(ChunkedRange {
+                id: chunk.id,
+                start,
+                end,
+                start_column: chunk.start_column,
+                end_column: chunk.end_column,
+                stale,
+                window_id: chunk.window_id,
+            });
         }
         
         Ok(())
     }
     
-    /// Calculate the percentage of file that has been chunked
-    pub fn chunking_percentage(&self) -> f64 {
-        if self.content.is_empty() {
-            return 0.0;
+    /// Build a planner that counts chunks of the current file the way they're sent
+    /// 
+    /// Chunks are planned to fit `max_tokens`. Needs the per-line token
+    /// counts, so it fails while they're still being worked out in the
+    /// background.
+    fn chunk_planner(&self, max_tokens: usize) -> Result<ChunkPlanner<'_>> {
+        let relative_path = self.relative_file_path(&self.prompt_root).ok_or_else(|| anyhow!("No file opened"))?;
+        if self.line_tokens.progress().is_some() {
+            return Err(anyhow!("Still counting tokens - try again in a moment"));
         }
         
-        // Count unique lines that are chunked
-        let mut chunked_lines = vec![false; self.content.len()];
+        let tokenizer = self.line_tokens.tokenizer().clone();
+        let template = self.prompt_template.clone();
+        let file_path = relative_path.to_string_lossy().to_string();
+        let content = &self.content;
+        let exact = move |start: usize, end: usize| {
+            let text = content[start..=end].join("\n");
+            match &template {
+                Some(template) => {
+                    let context = PromptContext { file_path: file_path.clone(), start_line: start + 1, end_line: end + 1 };
+                    tokenizer.count(&template.render(&context, &text))
+                },
+                None => tokenizer.count(&text),
+            }
+        };
         
-        for (start, end) in &self.chunked_ranges {
-            // Ranges are already 0-indexed
-            let start_idx = *start;
-            let max_idx = chunked_lines.len() - 1;
-            let end_idx = (*end).min(max_idx);
-            
-            for i in start_idx..=end_idx {
-                if i < chunked_lines.len() {
-                    chunked_lines[i] = true;
-                }
+        // The overhead only sharpens the estimate; exact counts include the real prompt
+        let overhead = match &self.prompt_template {
+            Some(template) => self.line_tokens.tokenizer().count(&template.render(&PromptContext::default(), "")),
+            None => 0,
+        };
+        
+        Ok(ChunkPlanner::new(&self.content, self.line_tokens.lines(), max_tokens, overhead, exact))
+    }
+    
+    /// Get the runs of lines no saved chunk touches, as 0-indexed ranges
+    fn unchunked_runs(&self) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for line in (0..self.content.len()).filter(|&line| self.chunk_at(line).is_none()) {
+            match runs.last_mut() {
+                Some(run) if run.end == line => run.end = line + 1,
+                _ => runs.push(line..line + 1),
+            }
+        }
+        runs
+    }
+    
+    /// Get the structure chunks of the current file follow, if it has one
+    /// 
+    /// Decided by the file's extension (e.g. Hoon arms for `.hoon` files).
+    pub fn structure(&self) -> Option<Structure> {
+        self.file_path.as_deref().and_then(Structure::for_path)
+    }
+    
+    /// Propose chunks up to the token budget for every line not chunked yet
+    /// 
+    /// Chunks follow the file's structure if it has one, or end at blank
+    /// lines otherwise. The proposal is shown for review (see `chunk_preview`)
+    /// and only saved by `save_chunk_preview`. Returns the number of chunks proposed.
+    pub fn propose_chunks(&mut self) -> Result<usize> {
+        self.propose_chunks_along(self.structure())
+    }
+    
+    /// Propose chunks for every line not chunked yet, following `structure` if any
+    fn propose_chunks_along(&mut self, structure: Option<Structure>) -> Result<usize> {
+        let runs = self.unchunked_runs();
+        if runs.is_empty() {
+            return Err(anyhow!("Every line is already chunked"));
+        }
+        let (breaks, blocks) = match structure {
+            Some(structure) => (structure.breaks(&self.content), structure.blocks(&self.content)),
+            None => Default::default(),
+        };
+        let chunks = self.chunk_planner(self.max_tokens_per_chunk)?.with_breaks(breaks).with_blocks(blocks).pack(&runs);
+        Ok(self.show_chunk_preview(ChunkPreview { chunks, max_tokens: self.max_tokens_per_chunk, windowed: false }))
+    }
+    
+    /// Propose overlapping sliding windows for every line not chunked yet
+    /// 
+    /// Windows hold up to the configured window size (capped at the chunk
+    /// budget) and share about the configured overlap with the window before
+    /// them. Saved windows are marked as one sliding-window pass, so their
+    /// overlap isn't reported as a mistake. Returns the number of windows.
+    pub fn propose_windows(&mut self) -> Result<usize> {
+        let runs = self.unchunked_runs();
+        if runs.is_empty() {
+            return Err(anyhow!("Every line is already chunked"));
+        }
+        let max_tokens = self.sliding_window.0.min(self.max_tokens_per_chunk);
+        let chunks = self.chunk_planner(max_tokens)?.windows(&runs, self.sliding_window.1);
+        Ok(self.show_chunk_preview(ChunkPreview { chunks, max_tokens, windowed: true }))
+    }
+    
+    /// Start reviewing a proposal at its first chunk, returning the number of chunks
+    fn show_chunk_preview(&mut self, preview: ChunkPreview) -> usize {
+        self.clear_selection();
+        self.jump_to_line(preview.chunks[0].start);
+        let count = preview.chunks.len();
+        self.chunk_preview = Some(preview);
+        count
+    }
+    
+    /// Set the sliding window size and the overlap between windows, in tokens
+    pub fn set_sliding_window(&mut self, window_tokens: usize, overlap_tokens: usize) {
+        self.sliding_window = (window_tokens, overlap_tokens);
+    }
+    
+    /// Get the proposed chunks while they're being reviewed
+    pub fn chunk_preview(&self) -> Option<&[ProposedChunk]> {
+        self.chunk_preview.as_ref().map(|preview| preview.chunks.as_slice())
+    }
+    
+    /// Get the token budget of the proposed chunks
+    pub fn chunk_preview_budget(&self) -> Option<usize> {
+        self.chunk_preview.as_ref().map(|preview| preview.max_tokens)
+    }
+    
+    /// Whether the proposed chunks are overlapping sliding windows
+    pub fn chunk_preview_is_windowed(&self) -> bool {
+        self.chunk_preview.as_ref().is_some_and(|preview| preview.windowed)
+    }
+    
+    /// Get the number of proposed chunks covering a line
+    /// 
+    /// Sliding windows cover the lines they share twice or more.
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn proposed_chunks_covering(&self, line_number: usize) -> usize {
+        self.chunk_preview()
+            .unwrap_or_default()
+            .iter()
+            .filter(|chunk| chunk.start <= line_number && line_number <= chunk.end)
+            .count()
+    }
+    
+    /// Get the index of the proposed chunk covering a line, if any
+    /// 
+    /// Note: This function expects 0-indexed values for line numbers
+    pub fn proposed_chunk_at(&self, line_number: usize) -> Option<usize> {
+        self.chunk_preview()?
+            .iter()
+            .position(|chunk| chunk.start <= line_number && line_number <= chunk.end)
+    }
+    
+    /// Discard the proposed chunks
+    pub fn cancel_chunk_preview(&mut self) {
+        self.chunk_preview = None;
+    }
+    
+    /// Move the end of the proposed chunk under the cursor a line down or up
+    /// 
+    /// The next proposed chunk gives up or takes the line if it's adjacent.
+    /// A chunk can't grow into a saved chunk or shrink to nothing. Returns
+    /// whether anything changed.
+    pub fn move_preview_boundary(&mut self, down: bool) -> Result<bool> {
+        let Some(index) = self.proposed_chunk_at(self.cursor_position) else {
+            return Ok(false);
+        };
+        let mut proposal = self.chunk_preview().unwrap_or_default().to_vec();
+        let end = proposal[index].end;
+        let adjacent = proposal.get(index + 1).is_some_and(|next| next.start == end + 1);
+        
+        if down {
+            if end + 1 >= self.content.len() || self.chunk_at(end + 1).is_some() {
+                return Ok(false);
+            }
+            proposal[index].end = end + 1;
+            if adjacent && proposal[index + 1].start == proposal[index + 1].end {
+                proposal.remove(index + 1);
+            } else if adjacent {
+                proposal[index + 1].start += 1;
+            }
+        } else {
+            if end == proposal[index].start {
+                return Ok(false);
+            }
+            proposal[index].end = end - 1;
+            if adjacent {
+                proposal[index + 1].start = end;
             }
         }
         
-        // Calculate percentage
-        let total_chunked = chunked_lines.iter().filter(|&&chunked| chunked).count();
-        (total_chunked as f64 / self.content.len() as f64) * 100.0
+        self.recount_preview(proposal, index..index + 2)
+    }
+    
+    /// Split the proposed chunk under the cursor so a new one starts at the cursor line
+    pub fn split_preview_at_cursor(&mut self) -> Result<bool> {
+        let Some(index) = self.proposed_chunk_at(self.cursor_position) else {
+            return Ok(false);
+        };
+        let mut proposal = self.chunk_preview().unwrap_or_default().to_vec();
+        if proposal[index].start == self.cursor_position {
+            return Ok(false);
+        }
+        
+        let tail = ProposedChunk { start: self.cursor_position, end: proposal[index].end, tokens: 0 };
+        proposal[index].end = self.cursor_position - 1;
+        proposal.insert(index + 1, tail);
+        self.recount_preview(proposal, index..index + 2)
+    }
+    
+    /// Merge the proposed chunk under the cursor with the next one, if they're adjacent or overlap
+    pub fn merge_preview_with_next(&mut self) -> Result<bool> {
+        let Some(index) = self.proposed_chunk_at(self.cursor_position) else {
+            return Ok(false);
+        };
+        let mut proposal = self.chunk_preview().unwrap_or_default().to_vec();
+        if proposal.get(index + 1).is_none_or(|next| next.start > proposal[index].end + 1) {
+            return Ok(false);
+        }
+        
+        let next = proposal.remove(index + 1);
+        proposal[index].end = proposal[index].end.max(next.end);
+        self.recount_preview(proposal, index..index + 1)
+    }
+    
+    /// Replace the proposal with an adjusted one, recounting the chunks in `changed`
+    fn recount_preview(&mut self, mut proposal: Vec<ProposedChunk>, changed: Range<usize>) -> Result<bool> {
+        {
+            let planner = self.chunk_planner(self.chunk_preview_budget().unwrap_or(self.max_tokens_per_chunk))?;
+            for chunk in proposal.iter_mut().take(changed.end).skip(changed.start) {
+                *chunk = planner.propose(chunk.start, chunk.end);
+            }
+        }
+        if let Some(preview) = &mut self.chunk_preview {
+            preview.chunks = proposal;
+        }
+        Ok(true)
+    }
+    
+    /// Move the cursor to the start of the next or previous proposed chunk
+    pub fn jump_to_proposed_chunk(&mut self, forward: bool) {
+        let cursor = self.cursor_position;
+        let target = self.chunk_preview().and_then(|proposal| {
+            if forward {
+                proposal.iter().find(|chunk| chunk.start > cursor)
+            } else {
+                proposal.iter().rev().find(|chunk| chunk.start < cursor)
+            }
+        });
+        if let Some(start) = target.map(|chunk| chunk.start) {
+            self.jump_to_line(start);
+        }
+    }
+    
+    /// Move the cursor to a line, scrolling it into view
+    fn jump_to_line(&mut self, line_number: usize) {
+        self.cursor_position = line_number.min(self.content.len().saturating_sub(1));
+        self.clamp_cursor_column();
+        if self.cursor_position < self.scroll_position || self.cursor_position >= self.scroll_position + 20 {
+            self.scroll_position = self.cursor_position;
+        }
+    }
+    
+    /// Save the proposed chunks in one batch, with the same labels and metadata
+    /// 
+    /// Sliding windows are saved with a shared window id, and chunks of
+    /// Markdown documents with the headings they're under (unless the metadata
+    /// already has a breadcrumb). The batch is undone as a single operation.
+    /// Returns the number of chunks saved.
+    pub fn save_chunk_preview(
+        &mut self,
+        chunk_storage: &mut ChunkStorage,
+        root_dir: &Path,
+        labels: Vec<String>,
+        metadata: Metadata,
+    ) -> Result<usize> {
+        let preview = self.chunk_preview.as_ref().ok_or_else(|| anyhow!("No chunks proposed"))?;
+        let relative_path = self.relative_file_path(root_dir).ok_or_else(|| anyhow!("No file opened"))?;
+        let source_hash = hash_lines(&self.original_content);
+        let window_id = if preview.windowed { Uuid::new_v4().to_string() } else { String::new() };
+        let structure = self.structure();
+        
+        let chunks: Vec<Chunk> = preview
+            .chunks
+            .iter()
+            .map(|proposed| {
+                let lines = &self.content[proposed.start..=proposed.end];
+                let original = self.original_content.get(proposed.start..=proposed.end);
+                let mut chunk = Chunk::new(
+                    relative_path.clone(),
+                    self.to_storage_index(proposed.start),
+                    self.to_storage_index(proposed.end),
+                    lines.join("\n"),
+                    original != Some(lines),
+                )
+                .with_fingerprint(source_hash.clone(), original.map(hash_lines).unwrap_or_default())
+                .with_window(window_id.clone());
+                chunk.labels = labels.clone();
+                chunk.metadata = metadata.clone();
+                if let Some(breadcrumb) = structure.and_then(|structure| structure.breadcrumb(&self.content, proposed.start, proposed.end)) {
+                    chunk.metadata.entry(BREADCRUMB_FIELD.to_string()).or_insert(MetadataValue::Text(breadcrumb));
+                }
+                chunk
+            })
+            .collect();
+        
+        chunk_storage.add_chunks(chunks.clone())?;
+        
+        let ranges: Vec<ChunkedRange> = chunks
+            .iter()
+            .map(|chunk| ChunkedRange {
+                id: chunk.id.clone(),
+                start: self.to_viewer_index(chunk.start_line),
+                end: self.to_viewer_index(chunk.end_line),
+                start_column: None,
+                end_column: None,
+                stale: false,
+                window_id: chunk.window_id.clone(),
+            })
+            .collect();
+        self.chunked_ranges.extend(ranges);
+        self.chunk_preview = None;
+        
+        Ok(chunks.len())
+    }
+    
+    /// Get the number of saved chunks that overlap another one without meaning to
+    /// 
+    /// Sliding windows overlapping the other windows of their pass don't count.
+    pub fn unintended_overlap_count(&self) -> usize {
+        let mut ranges: Vec<&ChunkedRange> = self.chunked_ranges.iter().collect();
+        ranges.sort_by_key(|chunk| (chunk.start, chunk.start_column));
+        
+        // Sorted by start, a chunk can only overlap the ones after it that start before it ends
+        let mut overlapping = vec![false; ranges.len()];
+        for (index, chunk) in ranges.iter().enumerate() {
+            for (offset, other) in ranges[index + 1..].iter().enumerate() {
+                if other.start > chunk.end {
+                    break;
+                }
+                if chunk.overlaps_unintentionally(other) {
+                    overlapping[index] = true;
+                    overlapping[index + 1 + offset] = true;
+                }
+            }
+        }
+        overlapping.iter().filter(|&&overlapping| overlapping).count()
+    }
+    
+    /// Calculate the percentage of file that has been chunked
+    /// 
+    /// Characters covered by several chunks (e.g. overlapping windows) count once.
+    pub fn chunking_percentage(&self) -> f64 {
+        // Partly chunked lines count by the share of characters covered
+        let spans: Vec<ChunkSpan> = self.chunked_ranges.iter().map(ChunkedRange::span).collect();
+        coverage_percentage(&spans, &line_lengths(&self.content))
     }
     
     /// Update the selected text content with edited content
@@ -480,8 +1432,19 @@ impl Viewer {
                 return false;
             }
             
+            // A character-wise selection only covers part of its first and last
+            // lines; keep the unselected text around the edited fragment
+            let (start_column, end_column) = self.selection_columns();
+            let fragment_end = edited_content.last().map_or(0, |line| line.chars().count())
+                + if edited_content.len() <= 1 { start_column.unwrap_or(0) } else { 0 };
+            let edited_content = if self.char_selection {
+                self.splice_fragment(start, end, start_column, end_column, edited_content)
+            } else {
+                edited_content
+            };
+            
             // Check if content has actually been edited by comparing with original
-            let original_selection = &self.original_content[start..=end];
+            let original_selection = self.original_content.get(start..=end).unwrap_or_default();
             let original_slice: Vec<&String> = original_selection.iter().collect();
             let edited_slice: Vec<&String> = edited_content.iter().collect();
             
@@ -492,39 +1455,41 @@ impl Viewer {
             let range_len = end - start + 1;
             let replacement_len = edited_content.len();
             
-            // Remove the selected lines and insert the edited content
+            // Remove the selected lines and insert the edited content, recounting only those
+            // lines (or everything, if the file is still being counted)
             self.content.splice(start..=end, edited_content);
+            if self.line_tokens.progress().is_some() {
+                self.line_tokens.recount_in_background(self.content.clone());
+            } else {
+                self.line_tokens.splice(start, end + 1, &self.content[start..start + replacement_len]);
+            }
+            self.content_version += 1;
             
             // If the number of lines has changed, we need to adjust chunked ranges
             if range_len != replacement_len {
                 let line_diff = replacement_len as isize - range_len as isize;
                 
                 // Update chunked ranges that come after the edit
-                for i in 0..self.chunked_ranges.len() {
-                    let (chunk_start, chunk_end) = self.chunked_ranges[i];
-                    
-                    // Convert to 0-indexed for comparison with start/end (which are 0-indexed)
-                    // Ranges are already 0-indexed
-                    let chunk_start_0idx = chunk_start; 
-                    let chunk_end_0idx = chunk_end;
-                    
-                    // If the chunk is entirely after the edit, shift it
-                    if chunk_start_0idx > end {
-                        self.chunked_ranges[i] = (
-                            (chunk_start as isize + line_diff) as usize,
-                            (chunk_end as isize + line_diff) as usize
-                        );
-                    }
-                    // If the chunk overlaps with the edit, we might need more complex logic
-                    // For now, we'll consider those chunks invalid and remove them
-                    else if chunk_end_0idx >= start {
-                        // Mark for removal
-                        self.chunked_ranges[i] = (0, 0);
+                // Ranges are already 0-indexed, like start/end
+                // If the chunk overlaps with the edit, we might need more complex logic
+                // For now, we'll consider those chunks invalid and remove them
+                self.chunked_ranges.retain(|chunked| chunked.start > end || chunked.end < start);
+                
+                // If the chunk is entirely after the edit, shift it
+                for chunked in &mut self.chunked_ranges {
+                    if chunked.start > end {
+                        chunked.start = (chunked.start as isize + line_diff) as usize;
+                        chunked.end = (chunked.end as isize + line_diff) as usize;
                     }
                 }
-                
-                // Remove invalid chunks (those marked as (0,0))
-                self.chunked_ranges.retain(|&range| range != (0, 0));
+            }
+            
+            // Keep a character-wise selection around the edited fragment
+            if self.char_selection {
+                self.selection_start = Some(start);
+                self.selection_start_column = start_column.unwrap_or(0);
+                self.cursor_position = start + replacement_len.max(1) - 1;
+                self.cursor_column = fragment_end.saturating_sub(1);
             }
             
             // Update cursor position if needed (e.g., if content shrinks)
@@ -538,6 +1503,40 @@ impl Viewer {
         false
     }
     
+    /// Rebuild whole lines from an edited fragment of lines `start..=end`
+    /// 
+    /// The text before `start_column` on the first line and after `end_column`
+    /// on the last line is put back around the fragment.
+    fn splice_fragment(
+        &self,
+        start: usize,
+        end: usize,
+        start_column: Option<usize>,
+        end_column: Option<usize>,
+        fragment: Vec<String>,
+    ) -> Vec<String> {
+        let prefix: String = self.content[start].chars().take(start_column.unwrap_or(0)).collect();
+        let suffix: String = end_column
+            .map(|column| self.content[end].chars().skip(column).collect())
+            .unwrap_or_default();
+        
+        let mut lines = if fragment.is_empty() { vec![String::new()] } else { fragment };
+        lines[0].insert_str(0, &prefix);
+        if let Some(last) = lines.last_mut() {
+            last.push_str(&suffix);
+        }
+        lines
+    }
+    
+    /// Get the current file path relative to the root directory used for storage
+    fn relative_file_path(&self, root_dir: &Path) -> Option<PathBuf> {
+        let file_path = self.file_path.as_ref()?;
+        Some(match file_path.strip_prefix(root_dir) {
+            Ok(rel_path) => rel_path.to_path_buf(),
+            Err(_) => file_path.clone(),
+        })
+    }
+    
     /// Check if the selected content has been edited
     #[allow(dead_code)]
     pub fn has_edited_content(&self) -> bool {
@@ -5,7 +5,7 @@ use std::io::Write;
 use tempfile::tempdir;
 
 use packrat::viewer::Viewer;
-use packrat::storage::ChunkStorage;
+use packrat::storage::{ChunkStorage, MatchKind, MetadataValue, StorageBackend};
 
 fn setup_test_environment() -> Result<(tempfile::TempDir, PathBuf, ChunkStorage)> {
     let temp_dir = tempdir()?;
@@ -61,7 +61,7 @@ fn test_chunking_with_edited_content() -> Result<()> {
     assert!(!chunk_id.is_empty(), "Should receive a valid chunk ID");
     
     // Check the chunks in storage
-    let chunks = chunk_storage.get_chunks();
+    let chunks = chunk_storage.get_chunks()?;
     assert!(!chunks.is_empty(), "Storage should contain at least one chunk");
     
     // Get the saved chunk and verify its content
@@ -75,9 +75,559 @@ fn test_chunking_with_edited_content() -> Result<()> {
     assert!(viewer.has_edited_content(), "Viewer should track that content was edited");
     assert!(saved_chunk.edited, "Chunk should be marked as edited");
     
+    // The edit removed lines, so the selection no longer lines up with the file to fingerprint
+    assert!(saved_chunk.source_hash.is_empty() && saved_chunk.lines_hash.is_empty());
+    
     // Verify chunked ranges are tracked
     let chunked_ranges = viewer.chunked_ranges();
     assert!(!chunked_ranges.is_empty(), "Viewer should track chunked ranges");
     
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_delete_and_reopen_chunk() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let test_file_path = root_path.join("test_file.txt");
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&test_file_path)?;
+    
+    // Save lines 1-3 (0-indexed 0-2) as a chunk
+    viewer.toggle_selection_mode();
+    viewer.cursor_down();
+    viewer.cursor_down();
+    let chunk_id = viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    viewer.clear_selection();
+    
+    // Re-open the chunk from a line inside it and save edited content back
+    viewer.scroll_to_top();
+    viewer.cursor_down();
+    let chunk = viewer.reopen_chunk_at_cursor(&chunk_storage)?.expect("cursor should be on a chunk");
+    assert_eq!(chunk.id, chunk_id);
+    assert_eq!(viewer.reopened_chunk_id(), Some(chunk_id.as_str()));
+    
+    let updated_id = viewer.save_reopened_chunk(&mut chunk_storage, vec!["Rewritten chunk".to_string()])?;
+    assert_eq!(updated_id, chunk_id, "Re-opened chunk should keep its id");
+    assert!(viewer.reopened_chunk_id().is_none());
+    
+    let chunks = chunk_storage.get_chunks()?;
+    assert_eq!(chunks.len(), 1, "Updating should not create a new chunk");
+    assert_eq!(chunks[0].content, "Rewritten chunk");
+    assert!(chunks[0].edited, "Changed content should be marked as edited");
+    assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 3), "Line range should be unchanged");
+    
+    // Deleting from outside the chunk does nothing
+    for _ in 0..10 {
+        viewer.cursor_down();
+    }
+    assert!(viewer.delete_chunk_at_cursor(&mut chunk_storage)?.is_none());
+    
+    // Deleting from inside the chunk removes it and clears the highlight
+    viewer.scroll_to_top();
+    viewer.cursor_down();
+    viewer.cursor_down();
+    let deleted = viewer.delete_chunk_at_cursor(&mut chunk_storage)?.expect("cursor should be on a chunk");
+    assert_eq!(deleted.id, chunk_id);
+    assert!(!viewer.is_line_chunked(2));
+    assert_eq!(viewer.chunking_percentage(), 0.0);
+    assert!(chunk_storage.get_chunks()?.is_empty());
+    
+    Ok(())
+}
+
+#[test]
+fn test_stale_chunks_after_source_edit() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let test_file_path = root_path.join("test_file.txt");
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&test_file_path)?;
+    
+    // Chunk lines 1-3 and 11-13 (0-indexed 0-2 and 10-12)
+    viewer.toggle_selection_mode();
+    viewer.cursor_down();
+    viewer.cursor_down();
+    viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    viewer.clear_selection();
+    
+    for _ in 0..8 {
+        viewer.cursor_down();
+    }
+    viewer.toggle_selection_mode();
+    viewer.cursor_down();
+    viewer.cursor_down();
+    viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    viewer.clear_selection();
+    
+    let chunks = chunk_storage.get_chunks()?;
+    assert!(chunks.iter().all(|chunk| !chunk.source_hash.is_empty() && !chunk.lines_hash.is_empty()),
+            "Saved chunks should carry fingerprints");
+    
+    // Reloading an unchanged file finds nothing stale
+    viewer.open_file(&test_file_path)?;
+    viewer.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(viewer.stale_chunk_count(), 0);
+    
+    // Change a line inside the second chunk only
+    let mut lines: Vec<String> = std::fs::read_to_string(&test_file_path)?.lines().map(String::from).collect();
+    lines[11] = "Line 12 was rewritten".to_string();
+    std::fs::write(&test_file_path, lines.join("\n") + "\n")?;
+    
+    viewer.open_file(&test_file_path)?;
+    viewer.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(viewer.stale_chunk_count(), 1);
+    assert!(!viewer.is_line_stale(1), "Untouched chunk should not be stale");
+    assert!(viewer.is_line_stale(10), "Chunk covering the edited line should be stale");
+    assert!(viewer.is_line_chunked(10), "Stale chunks still count as chunked");
+    
+    Ok(())
+}
+
+#[test]
+fn test_reanchor_chunks_after_source_edit() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let test_file_path = root_path.join("test_file.txt");
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&test_file_path)?;
+    
+    // Chunk lines 3-5, 9-12 and 16-18 (0-indexed 2-4, 8-11 and 15-17)
+    for (start, len) in [(2, 2), (8, 3), (15, 2)] {
+        viewer.scroll_to_top();
+        for _ in 0..start {
+            viewer.cursor_down();
+        }
+        viewer.toggle_selection_mode();
+        for _ in 0..len {
+            viewer.cursor_down();
+        }
+        viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+        viewer.clear_selection();
+    }
+    
+    // Insert two lines at the top, reword one line of the second chunk
+    // and drop the third chunk's lines entirely
+    let original: Vec<String> = std::fs::read_to_string(&test_file_path)?.lines().map(String::from).collect();
+    let mut lines = vec!["New header".to_string(), "".to_string()];
+    lines.extend(original[..15].iter().cloned());
+    lines[2 + 9] = "Line 10: reworded".to_string();
+    lines.extend(original[18..].iter().cloned());
+    std::fs::write(&test_file_path, lines.join("\n") + "\n")?;
+    
+    viewer.open_file(&test_file_path)?;
+    viewer.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(viewer.stale_chunk_count(), 3);
+    
+    let report = viewer.reanchor_stale_chunks(&chunk_storage, &root_path)?;
+    assert_eq!(report.placed.len(), 2);
+    assert_eq!(report.unplaced.len(), 1);
+    assert_eq!(report.unplaced[0].start_line, 16);
+    
+    let exact = &report.placed[0];
+    assert_eq!(exact.kind, MatchKind::Exact);
+    assert_eq!(exact.old_range, (3, 5));
+    assert_eq!((exact.chunk.start_line, exact.chunk.end_line), (5, 7));
+    
+    let fuzzy = &report.placed[1];
+    assert_eq!(fuzzy.kind, MatchKind::Fuzzy);
+    assert_eq!((fuzzy.chunk.start_line, fuzzy.chunk.end_line), (11, 14));
+    
+    // Apply in bulk: the placed chunks are no longer stale
+    chunk_storage.update_chunks(report.updated_chunks())?;
+    viewer.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(viewer.stale_chunk_count(), 1);
+    assert!(viewer.is_line_chunked(4) && !viewer.is_line_stale(4));
+    
+    // The bulk update is undone as a single operation
+    chunk_storage.undo()?;
+    viewer.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(viewer.stale_chunk_count(), 3);
+    
+    Ok(())
+}
+
+#[test]
+fn test_character_wise_chunks() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let test_file_path = root_path.join("test_file.txt");
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&test_file_path)?;
+    
+    // Select from "This" on line 2 through "Line 3: This" on line 3
+    viewer.cursor_down();
+    viewer.cursor_right(8);
+    viewer.toggle_char_selection_mode();
+    viewer.cursor_down();
+    viewer.cursor_right(3);
+    assert_eq!(viewer.selection_columns(), (Some(8), Some(12)));
+    assert_eq!(viewer.selected_lines(), vec![
+        "This is test content for line 2.".to_string(),
+        "Line 3: This".to_string(),
+    ]);
+    let first_id = viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    viewer.clear_selection();
+    
+    // Only the covered characters count towards the percentage
+    let percent = viewer.chunking_percentage();
+    assert!(percent > 5.0 && percent < 6.0, "Expected about 5.5%, got {:.2}%", percent);
+    
+    // The rest of line 3 can be chunked separately without overlapping
+    viewer.cursor_right(1);
+    viewer.toggle_char_selection_mode();
+    viewer.cursor_right(100);
+    assert_eq!(viewer.selection_columns(), (Some(12), None));
+    assert!(!viewer.selection_overlaps_chunks());
+    let second_id = viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    viewer.clear_selection();
+    
+    assert_eq!(viewer.chunk_columns_on_line(2), vec![(0, 12, false), (12, 40, false)]);
+    assert_eq!(viewer.chunk_at_cursor().map(|chunk| chunk.id.clone()), Some(second_id.clone()));
+    
+    // Columns survive a reload from CSV and the import into a new SQLite database
+    let chunks = ChunkStorage::new(root_path.join("chunks.csv"))?.get_chunks()?;
+    let sqlite = ChunkStorage::open(root_path.join("chunks.db"), StorageBackend::Sqlite)?;
+    
+    for storage in [&chunk_storage, &sqlite] {
+        let first = storage.get_chunk(&first_id)?.expect("First chunk should be stored");
+        assert_eq!((first.start_line, first.start_column), (2, Some(8)));
+        assert_eq!((first.end_line, first.end_column), (3, Some(12)));
+        assert_eq!(first.content, "This is test content for line 2.\nLine 3: This");
+        
+        let second = storage.get_chunk(&second_id)?.expect("Second chunk should be stored");
+        assert_eq!((second.start_column, second.end_column), (Some(12), None));
+    }
+    assert_eq!(chunks.len(), 2);
+    
+    Ok(())
+}
+
+/// Wait until the viewer has counted every line in the background
+fn wait_for_line_counts(viewer: &mut Viewer) {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+    while viewer.token_counting_progress().is_some() && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        viewer.poll_token_counts();
+    }
+}
+
+#[test]
+fn test_auto_chunking_by_token_budget() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let tokenizer = packrat::utils::TokenizerKind::Cl100k.tokenizer()?;
+    
+    // Five paragraphs of four lines, separated by blank lines
+    let paragraphs: Vec<String> = (1..=5)
+        .map(|p| (1..=4).map(|l| format!("Paragraph {} line {}: some words to count.", p, l)).collect::<Vec<_>>().join("\n"))
+        .collect();
+    let file_path = root_path.join("paragraphs.txt");
+    std::fs::write(&file_path, paragraphs.join("\n\n"))?;
+    
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(60);
+    viewer.open_file(&file_path)?;
+    assert!(viewer.propose_chunks().is_err(), "Lines are still being counted");
+    wait_for_line_counts(&mut viewer);
+    
+    // Chunk the first paragraph by hand; only the rest is proposed
+    viewer.toggle_selection_mode();
+    for _ in 0..3 {
+        viewer.cursor_down();
+    }
+    viewer.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    let count = viewer.propose_chunks()?;
+    
+    // Each remaining paragraph fits the budget with its blank line but not with the next one
+    let proposal = viewer.chunk_preview().unwrap().to_vec();
+    assert_eq!(count, 4);
+    assert_eq!(proposal[0].start, 4);
+    assert_eq!(proposal.last().unwrap().end, viewer.content().len() - 1);
+    for (chunk, next) in proposal.iter().zip(proposal.iter().skip(1)) {
+        assert_eq!(next.start, chunk.end + 1);
+        assert!(viewer.content()[chunk.end].is_empty(), "{:?} should end on a blank line", chunk);
+    }
+    for chunk in &proposal {
+        assert_eq!(chunk.tokens, tokenizer.count(&viewer.content()[chunk.start..=chunk.end].join("\n")));
+        assert!(chunk.tokens <= 60);
+    }
+    assert_eq!(viewer.cursor_position(), 4);
+    assert_eq!(viewer.proposed_chunk_at(4), Some(0));
+    assert_eq!(viewer.proposed_chunk_at(0), None);
+    
+    // Give the first proposed chunk's blank line to the next one, then take it back
+    // (the blank line after the hand-made chunk opens the first proposed chunk)
+    assert_eq!(proposal[0].end, 9);
+    assert!(viewer.move_preview_boundary(false)?);
+    assert_eq!((viewer.chunk_preview().unwrap()[0].end, viewer.chunk_preview().unwrap()[1].start), (8, 9));
+    assert!(viewer.move_preview_boundary(true)?);
+    assert_eq!(viewer.chunk_preview().unwrap().to_vec(), proposal);
+    
+    // Split at the cursor and merge back
+    viewer.cursor_down();
+    viewer.cursor_down();
+    assert!(viewer.split_preview_at_cursor()?);
+    assert_eq!(viewer.chunk_preview().unwrap().len(), 5);
+    assert_eq!(viewer.chunk_preview().unwrap()[1].start, 6);
+    assert!(!viewer.split_preview_at_cursor()?, "The cursor already starts a chunk");
+    viewer.cursor_up();
+    assert!(viewer.merge_preview_with_next()?);
+    assert_eq!(viewer.chunk_preview().unwrap().to_vec(), proposal);
+    
+    // Jump between proposed chunks
+    viewer.jump_to_proposed_chunk(true);
+    assert_eq!(viewer.cursor_position(), proposal[1].start);
+    viewer.jump_to_proposed_chunk(false);
+    assert_eq!(viewer.cursor_position(), proposal[0].start);
+    
+    // Saving adds them all in one batch that is undone at once
+    let labels = vec!["auto".to_string()];
+    assert_eq!(viewer.save_chunk_preview(&mut chunk_storage, &root_path, labels, Default::default())?, 4);
+    assert!(viewer.chunk_preview().is_none());
+    assert_eq!(viewer.chunking_percentage(), 100.0);
+    assert!(viewer.propose_chunks().is_err(), "Nothing is left to chunk");
+    
+    let chunks = chunk_storage.get_chunks_for_file("paragraphs.txt")?;
+    assert_eq!(chunks.len(), 5);
+    let saved: Vec<_> = chunks.iter().filter(|chunk| chunk.labels == ["auto"]).collect();
+    assert_eq!(saved.len(), 4);
+    assert!(saved.iter().all(|chunk| !chunk.edited && !chunk.lines_hash.is_empty()));
+    assert!(saved.iter().any(|chunk| chunk.content == format!("\n{}\n", paragraphs[1])));
+    assert!(saved.iter().any(|chunk| chunk.content == format!("{}\n", paragraphs[2])));
+    
+    chunk_storage.undo()?;
+    assert_eq!(chunk_storage.get_chunks_for_file("paragraphs.txt")?.len(), 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_sliding_windows_overlap_on_purpose() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    let tokenizer = packrat::utils::TokenizerKind::Cl100k.tokenizer()?;
+    
+    let lines: Vec<String> = (1..=12).map(|l| format!("Line {} has a handful of words to count.", l)).collect();
+    let file_path = root_path.join("windows.txt");
+    std::fs::write(&file_path, lines.join("\n"))?;
+    
+    // The window is capped at the chunk budget
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(40);
+    viewer.set_sliding_window(1024, 10);
+    viewer.open_file(&file_path)?;
+    wait_for_line_counts(&mut viewer);
+    
+    let count = viewer.propose_windows()?;
+    assert!(viewer.chunk_preview_is_windowed());
+    assert_eq!(viewer.chunk_preview_budget(), Some(40));
+    
+    // Windows fit the budget, share at least the overlap and cover the whole file
+    let proposal = viewer.chunk_preview().unwrap().to_vec();
+    assert_eq!(proposal.len(), count);
+    assert!(count > 1);
+    assert_eq!((proposal[0].start, proposal.last().unwrap().end), (0, lines.len() - 1));
+    for (window, next) in proposal.iter().zip(proposal.iter().skip(1)) {
+        assert!(next.start > window.start && next.start <= window.end, "{:?} and {:?} should overlap", window, next);
+        assert!(tokenizer.count(&lines[next.start..=window.end].join("\n")) >= 10);
+    }
+    for window in &proposal {
+        assert!(window.tokens <= 40);
+    }
+    assert_eq!(viewer.proposed_chunks_covering(proposal[1].start), 2);
+    
+    // Saved windows share a window id; their overlap is intended and counted once
+    viewer.save_chunk_preview(&mut chunk_storage, &root_path, Vec::new(), Default::default())?;
+    let chunks = chunk_storage.get_chunks_for_file("windows.txt")?;
+    assert_eq!(chunks.len(), count);
+    assert!(!chunks[0].window_id.is_empty());
+    assert!(chunks.iter().all(|chunk| chunk.window_id == chunks[0].window_id));
+    assert_eq!(viewer.chunking_percentage(), 100.0);
+    assert_eq!(viewer.unintended_overlap_count(), 0);
+    
+    // Reloaded from storage, the windows are still known to overlap on purpose
+    let mut reopened = Viewer::new();
+    reopened.open_file(&file_path)?;
+    reopened.load_chunked_ranges(&chunk_storage, &root_path)?;
+    assert_eq!(reopened.unintended_overlap_count(), 0);
+    
+    // A chunk saved by hand over a window does overlap by accident
+    reopened.toggle_selection_mode();
+    reopened.save_selection_as_chunk(&mut chunk_storage, &root_path)?;
+    assert_eq!(reopened.unintended_overlap_count(), 2);
+    assert!(chunk_storage.get_chunks_for_file("windows.txt")?.iter().any(|chunk| chunk.window_id.is_empty()));
+    
+    Ok(())
+}
+
+#[test]
+fn test_hoon_files_are_chunked_at_arms() -> Result<()> {
+    let (_temp_dir, root_path, _chunk_storage) = setup_test_environment()?;
+    let file_path = root_path.join("behn.hoon");
+    std::fs::copy("tests/fixtures/hoon/vane/behn.hoon", &file_path)?;
+    
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(400);
+    viewer.open_file(&file_path)?;
+    wait_for_line_counts(&mut viewer);
+    assert_eq!(viewer.structure(), Some(packrat::chunker::Structure::Hoon));
+    
+    viewer.propose_chunks()?;
+    let proposal = viewer.chunk_preview().unwrap().to_vec();
+    assert_eq!(proposal.last().unwrap().end, viewer.content().len() - 1);
+    
+    // Every chunk fits and starts with an arm, a core or the code after one,
+    // unless it continues an arm without nested arms that's too large for one chunk
+    let breaks = packrat::chunker::hoon_breaks(viewer.content());
+    let mut at_breaks = 0;
+    for (chunk, next) in proposal.iter().zip(proposal.iter().skip(1)) {
+        assert_eq!(next.start, chunk.end + 1);
+        assert!(chunk.tokens <= 400, "{:?} is over the budget", chunk);
+        if breaks[next.start].is_some() {
+            at_breaks += 1;
+        } else {
+            assert!(chunk.tokens * 2 >= 400, "{:?} was ended early, away from a break", chunk);
+        }
+    }
+    assert!(at_breaks * 4 >= proposal.len() * 3, "Only {} of {} chunks start at a break", at_breaks, proposal.len());
+    
+    Ok(())
+}
+
+#[test]
+fn test_markdown_chunks_keep_fences_whole_and_record_breadcrumbs() -> Result<()> {
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    
+    let fence: Vec<String> = (1..=12).map(|l| format!("echo step {} of the build", l)).collect();
+    let document = format!(
+        "# Install\n\nRead this first.\n\n## Linux\n\nBuild it from source:\n\n```sh\n{}\n```\n\n## macOS\n\nUse Homebrew.\n",
+        fence.join("\n")
+    );
+    let file_path = root_path.join("guide.md");
+    std::fs::write(&file_path, document)?;
+    
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(40);
+    viewer.open_file(&file_path)?;
+    wait_for_line_counts(&mut viewer);
+    viewer.propose_chunks()?;
+    
+    // The fence is over the budget, but stays in one chunk
+    let fence_start = viewer.content().iter().position(|line| line == "```sh").unwrap();
+    let fence_end = fence_start + fence.len() + 1;
+    let proposal = viewer.chunk_preview().unwrap().to_vec();
+    assert!(proposal.iter().any(|chunk| chunk.start == fence_start && chunk.end >= fence_end && chunk.tokens > 40));
+    assert!(proposal.iter().all(|chunk| chunk.end < fence_start || chunk.start == fence_start || chunk.start > fence_end));
+    
+    // Every chunk records the headings it's under
+    viewer.save_chunk_preview(&mut chunk_storage, &root_path, Vec::new(), Default::default())?;
+    let mut chunks = chunk_storage.get_chunks_for_file("guide.md")?;
+    chunks.sort_by_key(|chunk| chunk.start_line);
+    let breadcrumbs: Vec<String> = chunks
+        .iter()
+        .map(|chunk| match chunk.metadata.get("breadcrumb") {
+            Some(MetadataValue::Text(breadcrumb)) => breadcrumb.clone(),
+            other => panic!("Unexpected breadcrumb {:?}", other),
+        })
+        .collect();
+    assert_eq!(breadcrumbs.first().map(String::as_str), Some("Install"));
+    assert!(breadcrumbs.contains(&"Install > Linux".to_string()));
+    assert_eq!(breadcrumbs.last().map(String::as_str), Some("Install > macOS"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_source_files_are_chunked_along_their_syntax_tree() -> Result<()> {
+    let (_temp_dir, root_path, _chunk_storage) = setup_test_environment()?;
+    let file_path = root_path.join("markdown.rs");
+    std::fs::copy("src/chunker/markdown.rs", &file_path)?;
+    
+    let mut viewer = Viewer::new();
+    viewer.set_max_tokens_per_chunk(300);
+    viewer.open_file(&file_path)?;
+    wait_for_line_counts(&mut viewer);
+    let rust = packrat::chunker::Structure::Syntax(packrat::chunker::Language::Rust);
+    assert_eq!(viewer.structure(), Some(rust));
+    
+    viewer.propose_chunks()?;
+    let proposal = viewer.chunk_preview().unwrap().to_vec();
+    assert_eq!(proposal.last().unwrap().end, viewer.content().len() - 1);
+    
+    // Small items are packed together and large functions are split at their
+    // statements, so every chunk starts at an item or a statement
+    let breaks = rust.breaks(viewer.content());
+    for (chunk, next) in proposal.iter().zip(proposal.iter().skip(1)) {
+        assert_eq!(next.start, chunk.end + 1);
+        assert!(chunk.tokens <= 300, "{:?} is over the budget", chunk);
+        assert!(breaks[next.start].is_some(), "{:?} doesn't start at an item", next);
+    }
+    assert!(proposal.len() > 3);
+    
+    // The doc comment above a function starts the chunk with it
+    let documented = viewer.content().iter().position(|line| line.starts_with("pub fn markdown_blocks")).unwrap();
+    assert!(proposal.iter().all(|chunk| chunk.start != documented));
+    
+    Ok(())
+}
+
+#[test]
+fn test_batch_chunking_skips_chunked_files() -> Result<()> {
+    use packrat::viewer::{chunk_files, BatchOptions, ChunkStrategy, FileOutcome, FileReport};
+    
+    let (_temp_dir, root_path, mut chunk_storage) = setup_test_environment()?;
+    std::fs::create_dir_all(root_path.join("docs/.drafts"))?;
+    std::fs::write(root_path.join("docs/guide.md"), "# Guide\n\nRead this.\n\n## More\n\nAnd this.\n")?;
+    std::fs::write(root_path.join("docs/.drafts/wip.md"), "# Not yet\n")?;
+    std::fs::write(root_path.join("docs/logo.png"), [0x89, b'P', b'N', b'G', 0, 0])?;
+    std::fs::write(root_path.join("empty.txt"), "")?;
+    
+    let options = BatchOptions {
+        strategy: ChunkStrategy::from_name("structure")?,
+        max_tokens: 60,
+        sliding_window: (1024, 128),
+        prompt_template: None,
+        tokenizer: packrat::utils::TokenizerKind::Words.tokenizer()?,
+        labels: vec!["ci".to_string()],
+        root_dir: root_path.clone(),
+        exclude: vec![root_path.join("chunks.csv")],
+    };
+    let outcomes = |reports: Vec<FileReport>| -> Vec<(String, FileOutcome)> {
+        reports
+            .into_iter()
+            .map(|report| (report.path.strip_prefix(&root_path).unwrap().display().to_string(), report.outcome))
+            .collect()
+    };
+    
+    // Hidden directories and the chunk file are left out, and only text is chunked
+    let reports = chunk_files(std::slice::from_ref(&root_path), &mut chunk_storage, &options)?;
+    assert_eq!(
+        outcomes(reports),
+        vec![
+            ("docs/guide.md".to_string(), FileOutcome::Chunked { chunks: 1, largest: 8 }),
+            ("docs/logo.png".to_string(), FileOutcome::NotText),
+            ("empty.txt".to_string(), FileOutcome::Empty),
+            ("test_file.txt".to_string(), FileOutcome::Chunked { chunks: 4, largest: 54 }),
+        ]
+    );
+    let saved = chunk_storage.get_chunks_for_file("test_file.txt")?;
+    assert_eq!(saved.len(), 4);
+    assert!(saved.iter().all(|chunk| chunk.labels == ["ci"]));
+    assert_eq!(saved.iter().map(|chunk| chunk.end_line - chunk.start_line + 1).sum::<usize>(), 20);
+    
+    // Running again chunks only what's new
+    std::fs::write(root_path.join("docs/new.md"), "# New\n")?;
+    let reports = chunk_files(&[root_path.join("docs"), root_path.join("test_file.txt")], &mut chunk_storage, &options)?;
+    assert_eq!(
+        outcomes(reports),
+        vec![
+            ("docs/guide.md".to_string(), FileOutcome::AlreadyChunked),
+            ("docs/logo.png".to_string(), FileOutcome::NotText),
+            ("docs/new.md".to_string(), FileOutcome::Chunked { chunks: 1, largest: 2 }),
+            ("test_file.txt".to_string(), FileOutcome::AlreadyChunked),
+        ]
+    );
+    
+    assert!(chunk_files(&[root_path.join("missing")], &mut chunk_storage, &options).is_err());
+    assert!(ChunkStrategy::from_name("paragraphs").is_err());
+    
+    Ok(())
+}
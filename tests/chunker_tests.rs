@@ -0,0 +1,346 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use packrat::chunker::{ChunkPlanner, Language, ProposedChunk, Structure};
+
+/// Lines with the given token counts, blank where the count is zero
+fn lines_for(counts: &[usize]) -> Vec<String> {
+    counts
+        .iter()
+        .map(|&count| if count == 0 { String::new() } else { "x ".repeat(count).trim_end().to_string() })
+        .collect()
+}
+
+/// Sum of the lines plus one token per newline, like the planner's estimate
+fn sum_with_newlines(counts: &[usize], start: usize, end: usize) -> usize {
+    counts[start..=end].iter().sum::<usize>() + (end - start)
+}
+
+fn chunk(start: usize, end: usize, tokens: usize) -> ProposedChunk {
+    ProposedChunk { start, end, tokens }
+}
+
+#[test]
+fn test_chunks_prefer_blank_lines() {
+    let counts = [3, 3, 0, 3, 3, 3, 0, 3];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 12, 0, |start, end| sum_with_newlines(&counts, start, end));
+    
+    // Line 3 would still fit in the first chunk, but the blank line before it is a better place to stop
+    assert_eq!(
+        planner.pack_run(0..lines.len()),
+        vec![chunk(0, 2, 8), chunk(3, 6, 12), chunk(7, 7, 3)]
+    );
+    
+    // A blank line that would leave a chunk less than half full is ignored
+    let counts = [0, 5, 5, 5];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 12, 0, |start, end| sum_with_newlines(&counts, start, end));
+    assert_eq!(planner.pack_run(0..4), vec![chunk(0, 2, 12), chunk(3, 3, 5)]);
+}
+
+#[test]
+fn test_chunks_fit_the_exact_count() {
+    // Chunks of three or more lines cost more than the estimate says
+    let counts = [2, 2, 2, 2];
+    let lines = lines_for(&counts);
+    let exact = |start: usize, end: usize| sum_with_newlines(&counts, start, end) + if end - start >= 2 { 10 } else { 0 };
+    let planner = ChunkPlanner::new(&lines, &counts, 10, 0, exact);
+    assert_eq!(planner.estimate(0, 2), 8);
+    assert_eq!(planner.measure(0, 2), 18);
+    assert_eq!(planner.pack_run(0..4), vec![chunk(0, 1, 5), chunk(2, 3, 5)]);
+    
+    // The overhead (e.g. a prompt template) counts against every chunk
+    let planner = ChunkPlanner::new(&lines, &counts, 10, 4, |start, end| sum_with_newlines(&counts, start, end) + 4);
+    assert_eq!(planner.pack_run(0..4), vec![chunk(0, 1, 9), chunk(2, 3, 9)]);
+    
+    // A line over the budget on its own still gets a chunk
+    let counts = [2, 20, 2];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 10, 0, |start, end| sum_with_newlines(&counts, start, end));
+    assert_eq!(planner.pack_run(0..3), vec![chunk(0, 0, 2), chunk(1, 1, 20), chunk(2, 2, 2)]);
+}
+
+#[test]
+fn test_runs_are_packed_separately() {
+    let counts = [1; 10];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 100, 0, |start, end| sum_with_newlines(&counts, start, end));
+    
+    assert_eq!(planner.pack(&[0..3, 5..10]), vec![chunk(0, 2, 5), chunk(5, 9, 9)]);
+    assert_eq!(planner.pack(&[]), vec![]);
+    assert_eq!(planner.propose(4, 4), chunk(4, 4, 1));
+}
+
+#[test]
+fn test_windows_overlap_by_the_configured_tokens() {
+    let counts = [2; 10];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 17, 0, |start, end| sum_with_newlines(&counts, start, end));
+    
+    // Each window starts at the last line that still shares 6 tokens with the one before
+    assert_eq!(
+        planner.window_run(0..10, 6),
+        vec![chunk(0, 5, 17), chunk(3, 8, 17), chunk(6, 9, 11)]
+    );
+    
+    // Windows without overlap are just chunks that ignore blank lines
+    assert_eq!(planner.window_run(0..10, 0), vec![chunk(0, 5, 17), chunk(6, 9, 11)]);
+    
+    // The overlap is capped at half the budget, so windows always move on
+    assert_eq!(planner.window_run(0..4, 100), vec![chunk(0, 3, 11)]);
+    assert_eq!(
+        planner.windows(&[0..7, 8..10], 100),
+        vec![chunk(0, 5, 17), chunk(3, 6, 11), chunk(8, 9, 5)]
+    );
+    assert_eq!(planner.window_run(0..10, 100), planner.window_run(0..10, 8));
+}
+
+#[test]
+fn test_windows_skip_past_long_lines() {
+    // No window can hold line 3 with its neighbours, so the next one starts at it
+    let counts = [2, 2, 2, 30, 2];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 10, 0, |start, end| sum_with_newlines(&counts, start, end));
+    assert_eq!(planner.window_run(0..5, 3), vec![chunk(0, 2, 8), chunk(3, 3, 30), chunk(4, 4, 2)]);
+}
+
+#[test]
+fn test_hoon_breaks_at_cores_and_arms() {
+    let source = [
+        "::  layer one",
+        "|%",
+        "++  add",
+        "  |=  [a=@ b=@]",
+        "  (sum a b)  ::  |% in a comment opens nothing",
+        "::",
+        "::  +door: a door with arms",
+        "::",
+        "++  door",
+        "  |_  a=@",
+        "  ++  get  a",
+        "    ::  the end of get",
+        "  ::",
+        "  +$  item  @",
+        "  --",
+        "--",
+        "=>  ~",
+        "|%",
+        "+|  %chapter",
+        "++  last  ~",
+        "--",
+    ];
+    let lines: Vec<String> = source.iter().map(|line| line.to_string()).collect();
+    
+    // Comment blocks start the chunk of the arm or core below them, unless
+    // they're indented deeper and end the arm above
+    let mut expected = vec![None; lines.len()];
+    for (line, depth) in [(0, 1), (2, 1), (5, 1), (9, 2), (10, 2), (12, 2), (16, 0), (17, 1), (18, 1), (19, 1)] {
+        expected[line] = Some(depth);
+    }
+    assert_eq!(Structure::Hoon.breaks(&lines), expected);
+    
+    assert_eq!(Structure::for_path(Path::new("sys/hoon.hoon")), Some(Structure::Hoon));
+    assert_eq!(Structure::for_path(Path::new("notes.txt")), None);
+}
+
+#[test]
+fn test_structure_packs_small_arms_and_splits_large_ones() {
+    let counts = [3; 8];
+    let lines = lines_for(&counts);
+    let planner = |breaks: &[(usize, usize)]| {
+        let mut depths = vec![None; counts.len()];
+        for &(line, depth) in breaks {
+            depths[line] = Some(depth);
+        }
+        ChunkPlanner::new(&lines, &counts, 16, 0, |start, end| sum_with_newlines(&counts, start, end)).with_breaks(depths)
+    };
+    
+    // Arms of two lines are packed two to a chunk
+    let arms = planner(&[(0, 1), (2, 1), (4, 1), (6, 1)]);
+    assert_eq!(arms.pack_run(0..8), vec![chunk(0, 3, 15), chunk(4, 7, 15)]);
+    
+    // An arm too large for one chunk is split at the arms nested in it, and
+    // the chunk ends before the next arm at the outer level
+    let nested = planner(&[(0, 1), (1, 2), (3, 2), (5, 2), (7, 1)]);
+    assert_eq!(nested.pack_run(0..8), vec![chunk(0, 2, 11), chunk(3, 6, 15), chunk(7, 7, 3)]);
+    
+    // A small arm takes the start of a large one after it, which has to be split anyway...
+    let large = planner(&[(0, 1), (1, 1), (3, 2), (5, 2)]);
+    assert_eq!(large.pack_run(0..7), vec![chunk(0, 2, 11), chunk(3, 6, 15)]);
+    
+    // ...but not of one that fits a chunk of its own
+    let fits = planner(&[(0, 1), (1, 1), (3, 2), (5, 1)]);
+    assert_eq!(fits.pack_run(0..7), vec![chunk(0, 0, 3), chunk(1, 4, 15), chunk(5, 6, 7)]);
+}
+
+#[test]
+fn test_hoon_fixtures_break_at_every_arm() {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for dir in ["tests/fixtures/hoon", "tests/fixtures/hoon/vane"] {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if Structure::for_path(&path) == Some(Structure::Hoon) {
+                paths.push(path);
+            }
+        }
+    }
+    assert!(paths.len() > 10);
+    
+    for path in paths {
+        let lines: Vec<String> = fs::read_to_string(&path).unwrap().lines().map(String::from).collect();
+        let breaks = Structure::Hoon.breaks(&lines);
+        
+        // Every arm starts a chunk, on its own line or with its comments
+        for (index, line) in lines.iter().enumerate() {
+            if line.trim_start().starts_with("++  ") {
+                let top = (0..=index)
+                    .rev()
+                    .take_while(|&above| above == index || lines[above].trim().is_empty() || lines[above].trim_start().starts_with("::"))
+                    .find(|&above| breaks[above].is_some());
+                assert!(top.is_some(), "{}:{} doesn't start a chunk", path.display(), index + 1);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_markdown_sections_and_blocks() {
+    let source = [
+        "# Install",
+        "",
+        "Some intro text.",
+        "",
+        "## Linux",
+        "",
+        "```sh",
+        "# not a heading",
+        "make install",
+        "```",
+        "",
+        "| Distro | Package |",
+        "| ------ | ------- |",
+        "| Debian | apt     |",
+        "",
+        "- one",
+        "  - nested",
+        "- two",
+        "",
+        "macOS",
+        "-----",
+        "Brew works.",
+        "## C# ##",
+    ];
+    let lines: Vec<String> = source.iter().map(|line| line.to_string()).collect();
+    let markdown = Structure::for_path(Path::new("docs/README.md")).unwrap();
+    assert_eq!(markdown, Structure::Markdown);
+    
+    // Headings break at their level; blocks and list items below every heading
+    let mut expected = vec![None; lines.len()];
+    for (line, depth) in [(0, 0), (2, 6), (4, 1), (6, 6), (11, 6), (15, 6), (16, 7), (17, 6), (19, 1), (21, 6), (22, 1)] {
+        expected[line] = Some(depth);
+    }
+    assert_eq!(markdown.breaks(&lines), expected);
+    assert_eq!(markdown.blocks(&lines), vec![6..10, 11..14]);
+    
+    assert_eq!(markdown.breadcrumb(&lines, 0, 0).as_deref(), Some("Install"));
+    assert_eq!(markdown.breadcrumb(&lines, 8, 8).as_deref(), Some("Install > Linux"));
+    assert_eq!(markdown.breadcrumb(&lines, 21, 21).as_deref(), Some("Install > macOS"));
+    assert_eq!(markdown.breadcrumb(&lines, 22, 22).as_deref(), Some("Install > C#"));
+    
+    // A chunk is where its first line with text is
+    assert_eq!(markdown.breadcrumb(&lines, 3, 5).as_deref(), Some("Install > Linux"));
+    assert_eq!(Structure::Hoon.breadcrumb(&lines, 8, 8), None);
+}
+
+#[test]
+fn test_blocks_are_never_split() {
+    let counts = [3; 8];
+    let lines = lines_for(&counts);
+    let planner = ChunkPlanner::new(&lines, &counts, 10, 0, |start, end| sum_with_newlines(&counts, start, end))
+        .with_blocks(vec![1..5, 6..8]);
+    
+    // Chunks before a block end early, and a block stays whole even over the budget
+    assert_eq!(
+        planner.pack_run(0..8),
+        vec![chunk(0, 0, 3), chunk(1, 4, 15), chunk(5, 5, 3), chunk(6, 7, 7)]
+    );
+}
+
+#[test]
+fn test_syntax_breaks_at_items_and_statements() {
+    let source = [
+        "use std::fmt;",
+        "",
+        "/// A point",
+        "#[derive(Debug)]",
+        "struct Point {",
+        "    x: i32,",
+        "}",
+        "",
+        "impl Point {",
+        "    // The origin",
+        "    fn origin() -> Self {",
+        "        let x = 0;",
+        "        Point { x }",
+        "    }",
+        "",
+        "    fn x(&self) -> i32 { self.x } // trailing",
+        "}",
+    ];
+    let lines: Vec<String> = source.iter().map(|line| line.to_string()).collect();
+    let rust = Structure::for_path(Path::new("src/point.rs")).unwrap();
+    assert_eq!(rust, Structure::Syntax(Language::Rust));
+    
+    // Doc comments and attributes start the item they're above, and a
+    // trailing comment stays with the code before it
+    let mut expected = vec![None; lines.len()];
+    for (line, depth) in [(0, 0), (2, 0), (5, 1), (8, 0), (9, 1), (11, 2), (12, 2), (15, 1)] {
+        expected[line] = Some(depth);
+    }
+    assert_eq!(rust.breaks(&lines), expected);
+    assert!(rust.blocks(&lines).is_empty());
+    assert_eq!(rust.breadcrumb(&lines, 0, 0), None);
+}
+
+#[test]
+fn test_syntax_breaks_in_python_and_typescript() {
+    let python = [
+        "import os",
+        "",
+        "@dataclass",
+        "class Job:",
+        "    # What to run",
+        "    name: str",
+        "",
+        "    def run(self):",
+        "        os.system(self.name)",
+    ];
+    let lines: Vec<String> = python.iter().map(|line| line.to_string()).collect();
+    let structure = Structure::for_path(Path::new("jobs.py")).unwrap();
+    let mut expected = vec![None; lines.len()];
+    for (line, depth) in [(0, 0), (2, 0), (4, 1), (7, 1), (8, 2)] {
+        expected[line] = Some(depth);
+    }
+    assert_eq!(structure.breaks(&lines), expected);
+    
+    let typescript = [
+        "export class Queue {",
+        "  private items: string[] = [];",
+        "",
+        "  push(item: string) {",
+        "    this.items.push(item);",
+        "  }",
+        "}",
+        "",
+        "const view = () => <Queue />;",
+    ];
+    let lines: Vec<String> = typescript.iter().map(|line| line.to_string()).collect();
+    let structure = Structure::for_path(Path::new("web/queue.tsx")).unwrap();
+    assert_eq!(structure, Structure::Syntax(Language::Tsx));
+    let mut expected = vec![None; lines.len()];
+    for (line, depth) in [(0, 0), (1, 1), (3, 1), (4, 2), (8, 0)] {
+        expected[line] = Some(depth);
+    }
+    assert_eq!(structure.breaks(&lines), expected);
+}
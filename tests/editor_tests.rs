@@ -1,8 +1,10 @@
 use packrat::editor::Editor;
+use packrat::utils::TokenizerKind;
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::path::Path;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
 #[test]
@@ -10,7 +12,7 @@ fn test_editor_creation() {
     let editor = Editor::new();
     assert!(!editor.is_modified());
     assert_eq!(editor.content(), Vec::<String>::new());
-    assert!(editor.mode().len() > 0, "Editor should have a mode");
+    assert!(!editor.mode().is_empty(), "Editor should have a mode");
 }
 
 #[test]
@@ -83,17 +85,17 @@ fn test_key_handling() {
     // Ctrl+S should not be handled by the editor (reserved for app-level save)
     let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
     let handled = editor.handle_key_event(ctrl_s);
-    assert_eq!(handled, false);
+    assert!(!handled);
     
     // Question mark key should not be handled by editor (reserved for help)
     let help_key = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::empty());
     let handled = editor.handle_key_event(help_key);
-    assert_eq!(handled, false);
+    assert!(!handled);
     
     // Esc in insert mode should be handled by the editor
     editor.handle_key_event(KeyEvent::new(KeyCode::Char('i'), KeyModifiers::empty())); // Enter insert mode
     let handled = editor.handle_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()));
-    assert_eq!(handled, true);
+    assert!(handled);
 }
 
 /// Comprehensive test to verify that the original files are never modified
@@ -152,4 +154,32 @@ fn hash_file(path: &Path) -> u64 {
         hash = hash.wrapping_mul(31).wrapping_add(byte as u64);
     }
     hash
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_token_count_follows_edits() -> anyhow::Result<()> {
+    let tokenizer = TokenizerKind::Words.tokenizer()?;
+    let mut editor = Editor::new();
+    editor.set_tokenizer(tokenizer.clone());
+    editor.set_content(vec!["one two".to_string(), "three".to_string()]);
+    assert_eq!(editor.token_count(), 3 + 1);
+    
+    // Typing updates the estimate straight away
+    editor.handle_key_event(KeyEvent::new(KeyCode::Char('A'), KeyModifiers::empty()));
+    editor.handle_key_event(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::empty()));
+    editor.handle_key_event(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::empty()));
+    editor.handle_key_event(KeyEvent::new(KeyCode::Esc, KeyModifiers::empty()));
+    assert_eq!(editor.token_count(), 4 + 1);
+    assert!(editor.token_count_pending());
+    
+    // The exact count follows from the background
+    let deadline = Instant::now() + Duration::from_secs(10);
+    editor.poll_token_count();
+    while editor.token_count_pending() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+        editor.poll_token_count();
+    }
+    assert_eq!(editor.token_count(), tokenizer.count_lines(&editor.content()));
+    
+    Ok(())
+}
@@ -0,0 +1,796 @@
+use anyhow::Result;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+use packrat::storage::{
+    export_chunks, hash_lines, import_chunks, reanchor_chunks, same_window, validate_chunks, Chunk, ChunkStats, ChunkStorage,
+    ExportFormat, ExportOptions, ImportReport, MatchKind, Metadata, MetadataValue, Problem, StorageBackend,
+};
+use packrat::utils::prompt::PromptTemplate;
+
+fn sample_chunk(file: &str, start_line: usize, end_line: usize) -> Chunk {
+    Chunk::new(
+        PathBuf::from(file),
+        start_line,
+        end_line,
+        format!("Content of {} lines {}-{}", file, start_line, end_line),
+        false,
+    )
+}
+
+#[test]
+fn test_csv_storage_round_trip() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    storage.add_chunk(sample_chunk("a.txt", 1, 10))?;
+    storage.add_chunk(sample_chunk("a.txt", 11, 20))?;
+    storage.add_chunk(sample_chunk("b.txt", 1, 5))?;
+    
+    // Reopen from disk to make sure appended rows are readable
+    let storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert_eq!(storage.get_chunks()?.len(), 3);
+    assert_eq!(storage.get_chunks_for_file("a.txt")?.len(), 2);
+    assert_eq!(storage.get_chunked_ranges("b.txt")?, vec![(1, 5)]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_csv_storage_update_and_delete() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    
+    let mut storage = ChunkStorage::new(&csv_path)?;
+    let first = sample_chunk("a.txt", 1, 10);
+    let second = sample_chunk("a.txt", 11, 20);
+    storage.add_chunk(first.clone())?;
+    storage.add_chunk(second.clone())?;
+    
+    // Replace the first chunk's content under the same id
+    let mut updated = first.clone();
+    updated.content = "Updated content".to_string();
+    updated.end_line = 8;
+    storage.update_chunk(updated)?;
+    
+    // Delete the second chunk
+    let removed = storage.delete_chunk(&second.id)?;
+    assert_eq!(removed.map(|chunk| chunk.id), Some(second.id.clone()));
+    assert!(storage.delete_chunk(&second.id)?.is_none(), "Deleting twice should be a no-op");
+    
+    // Changes must survive a reload
+    let storage = ChunkStorage::new(&csv_path)?;
+    let chunks = storage.get_chunks()?;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].id, first.id);
+    assert_eq!(chunks[0].content, "Updated content");
+    assert_eq!(chunks[0].end_line, 8);
+    assert!(storage.get_chunk(&second.id)?.is_none());
+    
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_storage_queries() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let db_path = temp_dir.path().join("chunks.db");
+    
+    let mut storage = ChunkStorage::open(&db_path, StorageBackend::Sqlite)?;
+    let mut labelled = sample_chunk("a.txt", 11, 20);
+    labelled.labels = vec!["arm".to_string(), "needs|review".to_string()];
+    storage.add_chunks(vec![
+        sample_chunk("a.txt", 1, 10),
+        labelled.clone(),
+        sample_chunk("b.txt", 1, 5),
+    ])?;
+    
+    // Reopen to make sure everything was committed
+    let mut storage = ChunkStorage::open(&db_path, StorageBackend::Sqlite)?;
+    assert_eq!(storage.get_chunks()?.len(), 3);
+    assert_eq!(storage.get_chunked_ranges("a.txt")?, vec![(1, 10), (11, 20)]);
+    assert_eq!(storage.calculate_chunking_percentage("b.txt", &[20; 10])?, 50.0);
+    assert_eq!(storage.get_all_chunk_spans()?.len(), 2);
+    
+    let loaded = storage.get_chunk(&labelled.id)?.expect("Labelled chunk should be stored");
+    assert_eq!(loaded.labels, labelled.labels);
+    
+    // Duplicate ids are rejected and the batch is rolled back
+    let result = storage.add_chunks(vec![sample_chunk("c.txt", 1, 2), labelled.clone()]);
+    assert!(result.is_err(), "Inserting a duplicate id should fail");
+    assert!(storage.get_chunks_for_file("c.txt")?.is_empty(), "Failed batch should not be partially stored");
+    
+    assert!(storage.delete_chunk(&labelled.id)?.is_some());
+    assert_eq!(storage.get_chunked_ranges("a.txt")?, vec![(1, 10)]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_storage_migrates_csv() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    let db_path = temp_dir.path().join("chunks.db");
+    
+    let mut csv_storage = ChunkStorage::new(&csv_path)?;
+    csv_storage.add_chunk(sample_chunk("a.txt", 1, 10))?;
+    csv_storage.add_chunk(sample_chunk("b.txt", 3, 4))?;
+    
+    // Creating the database next to the CSV file imports its chunks
+    let storage = ChunkStorage::open(&db_path, StorageBackend::Sqlite)?;
+    assert_eq!(storage.get_chunks()?.len(), 2);
+    assert_eq!(storage.get_chunked_ranges("b.txt")?, vec![(3, 4)]);
+    
+    // Pointing the SQLite backend at the CSV file itself is rejected
+    assert!(ChunkStorage::open(&csv_path, StorageBackend::Sqlite).is_err());
+    
+    Ok(())
+}
+
+#[test]
+fn test_failed_csv_migration_is_retried() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    let db_path = temp_dir.path().join("chunks.db");
+    
+    // A CSV file that can't be read fails the migration and leaves no database behind
+    fs::write(&csv_path, "# packrat chunk schema version 99\n\"id\"\n\"future\"\n")?;
+    assert!(ChunkStorage::open(&db_path, StorageBackend::Sqlite).is_err());
+    assert!(!db_path.exists());
+    
+    // So the migration runs again once the CSV file is fixed
+    fs::remove_file(&csv_path)?;
+    ChunkStorage::new(&csv_path)?.add_chunk(sample_chunk("a.txt", 1, 10))?;
+    let mut storage = ChunkStorage::open(&db_path, StorageBackend::Sqlite)?;
+    assert_eq!(storage.get_chunks()?.len(), 1);
+    
+    // Labels that aren't valid JSON are an error, not an unlabelled chunk
+    storage.add_chunk(sample_chunk("b.txt", 1, 2))?;
+    drop(storage);
+    rusqlite::Connection::open(&db_path)?.execute("UPDATE chunks SET labels = '[\"draft' WHERE file_path = 'b.txt'", [])?;
+    assert!(ChunkStorage::open(&db_path, StorageBackend::Sqlite)?.get_chunks().is_err());
+    
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_storage_replays_log() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let log_path = temp_dir.path().join("chunks.jsonl");
+    
+    let mut storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    let first = sample_chunk("a.txt", 1, 10);
+    let second = sample_chunk("a.txt", 11, 20);
+    storage.add_chunk(first.clone())?;
+    storage.add_chunk(second.clone())?;
+    
+    let mut updated = first.clone();
+    updated.content = "Updated content".to_string();
+    storage.update_chunk(updated)?;
+    storage.delete_chunk(&second.id)?;
+    
+    // One record per change was appended after the schema record
+    assert_eq!(fs::read_to_string(&log_path)?.lines().count(), 5);
+    
+    // Reopening replays the log into the same state
+    let mut storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    let chunks = storage.get_chunks()?;
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].content, "Updated content");
+    assert!(storage.warnings().is_empty());
+    
+    // Compaction keeps only the schema record and the live chunk
+    storage.compact()?;
+    assert_eq!(fs::read_to_string(&log_path)?.lines().count(), 2);
+    let storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    assert_eq!(storage.get_chunks()?[0].id, first.id);
+    
+    Ok(())
+}
+
+#[test]
+fn test_jsonl_storage_tolerates_truncated_tail() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let log_path = temp_dir.path().join("chunks.jsonl");
+    
+    let mut storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    storage.add_chunk(sample_chunk("a.txt", 1, 10))?;
+    storage.add_chunk(sample_chunk("a.txt", 11, 20))?;
+    drop(storage);
+    
+    // Simulate a crash halfway through writing a third record
+    let mut log = OpenOptions::new().append(true).open(&log_path)?;
+    write!(log, "{{\"op\":\"add\",\"chunk\":{{\"id\":\"partial")?;
+    drop(log);
+    
+    let mut storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    assert_eq!(storage.get_chunks()?.len(), 2, "Complete records should still load");
+    assert_eq!(storage.warnings().len(), 1, "The truncated record should be reported");
+    
+    // New records are appended after the last complete one
+    storage.add_chunk(sample_chunk("b.txt", 1, 5))?;
+    let storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    assert_eq!(storage.get_chunks()?.len(), 3);
+    assert!(storage.warnings().is_empty());
+    
+    Ok(())
+}
+
+#[test]
+fn test_undo_redo_persists_across_sessions() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    
+    let first = sample_chunk("a.txt", 1, 10);
+    let second = sample_chunk("a.txt", 11, 20);
+    
+    {
+        let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+        storage.add_chunk(first.clone())?;
+        storage.add_chunk(second.clone())?;
+        
+        let mut edited = first.clone();
+        edited.content = "Edited".to_string();
+        storage.update_chunk(edited)?;
+        storage.delete_chunk(&second.id)?;
+    }
+    
+    // Undo history is read back from the journal after a restart
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    
+    let undone = storage.undo()?.expect("delete should be undoable");
+    assert_eq!(undone.file_paths(), vec![PathBuf::from("a.txt")]);
+    assert!(storage.get_chunk(&second.id)?.is_some(), "Undoing a delete restores the chunk");
+    
+    storage.undo()?;
+    assert_eq!(storage.get_chunk(&first.id)?.unwrap().content, first.content, "Undoing an edit restores the old content");
+    
+    storage.undo()?;
+    storage.undo()?;
+    assert!(storage.get_chunks()?.is_empty(), "Undoing both creates removes both chunks");
+    assert!(storage.undo()?.is_none());
+    
+    // Redo walks forward again
+    storage.redo()?;
+    storage.redo()?;
+    assert_eq!(storage.get_chunks()?.len(), 2);
+    
+    // A new operation discards the remaining redo history
+    storage.add_chunk(sample_chunk("b.txt", 1, 5))?;
+    assert!(storage.redo()?.is_none());
+    
+    // A corrupt journal is reported and replaced rather than failing to open
+    fs::write(temp_dir.path().join("chunks.csv.journal"), "not json")?;
+    let storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert_eq!(storage.warnings().len(), 1);
+    assert_eq!(storage.get_chunks()?.len(), 3);
+    
+    Ok(())
+}
+
+#[test]
+fn test_undo_journal_is_appended_to() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    let journal_path = temp_dir.path().join("chunks.csv.journal");
+    let journal_lines = || -> Result<usize> { Ok(fs::read_to_string(&journal_path)?.lines().count()) };
+    
+    // Each operation, undo and redo adds one line rather than rewriting the history
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    let first = sample_chunk("a.txt", 1, 10);
+    storage.add_chunk(first.clone())?;
+    storage.add_chunk(sample_chunk("a.txt", 11, 20))?;
+    let journal = fs::read_to_string(&journal_path)?;
+    storage.undo()?;
+    assert_eq!(journal_lines()?, 3);
+    assert!(fs::read_to_string(&journal_path)?.starts_with(&journal), "Earlier records should be left as they were");
+    
+    // Undoing and redoing the same operation over and over compacts the journal
+    for _ in 0..10 {
+        storage.redo()?;
+        storage.undo()?;
+    }
+    assert!(journal_lines()? <= 4);
+    drop(storage);
+    
+    // The history is the same after compaction
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert!(storage.warnings().is_empty());
+    assert_eq!(storage.redo()?.map(|operation| operation.describe()), Some(format!("create of chunk {}", storage.get_chunks()?[1].id)));
+    storage.undo()?;
+    storage.undo()?;
+    assert!(storage.get_chunk(&first.id)?.is_none());
+    
+    // Journals written as a single object are read and converted
+    let legacy = serde_json::json!({ "undo": [{ "op": "create", "chunks": [first] }], "redo": [] });
+    fs::write(&journal_path, legacy.to_string())?;
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert!(storage.warnings().is_empty());
+    assert_eq!(journal_lines()?, 1);
+    assert!(storage.undo()?.is_some());
+    
+    // An undo that can't be journaled isn't made, so the history still matches the chunks
+    let second = sample_chunk("a.txt", 21, 30);
+    storage.add_chunk(second.clone())?;
+    fs::remove_file(&journal_path)?;
+    fs::create_dir(&journal_path)?;
+    assert!(storage.undo().is_err());
+    assert!(storage.get_chunk(&second.id)?.is_some());
+    fs::remove_dir(&journal_path)?;
+    assert!(storage.undo()?.is_some());
+    assert!(storage.get_chunk(&second.id)?.is_none());
+    fs::remove_file(&journal_path)?;
+    fs::create_dir(&journal_path)?;
+    assert!(storage.redo().is_err());
+    assert!(storage.get_chunk(&second.id)?.is_none());
+    fs::remove_dir(&journal_path)?;
+    assert!(storage.redo()?.is_some());
+    assert!(storage.get_chunk(&second.id)?.is_some());
+    fs::remove_file(&journal_path)?;
+    fs::create_dir(&journal_path)?;
+    drop(storage);
+    
+    // A journal that can't be written doesn't fail a save that went through
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    storage.add_chunk(sample_chunk("b.txt", 1, 5))?;
+    assert_eq!(storage.get_chunks()?.len(), 2);
+    assert_eq!(storage.take_warnings().len(), 1);
+    assert!(storage.warnings().is_empty());
+    
+    Ok(())
+}
+
+#[test]
+fn test_full_undo_history_survives_compaction() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let path = temp_dir.path().join("chunks.jsonl");
+    let journal_path = temp_dir.path().join("chunks.jsonl.journal");
+    
+    // A full undo stack of 200 operations, and 5 more to redo
+    let chunks: Vec<Chunk> = (1..=205).map(|line| sample_chunk("a.txt", line, line)).collect();
+    let mut storage = ChunkStorage::open(&path, StorageBackend::Jsonl)?;
+    storage.add_chunks(chunks[..200].to_vec())?;
+    drop(storage);
+    let create = |chunk: &Chunk| serde_json::json!({ "op": "create", "chunks": [chunk] });
+    let legacy = serde_json::json!({
+        "undo": chunks[..200].iter().map(create).collect::<Vec<_>>(),
+        "redo": chunks[200..].iter().rev().map(create).collect::<Vec<_>>(),
+    });
+    fs::write(&journal_path, legacy.to_string())?;
+    
+    // Compacted, every operation is recorded before the undone ones are undone again
+    drop(ChunkStorage::open(&path, StorageBackend::Jsonl)?);
+    assert_eq!(fs::read_to_string(&journal_path)?.lines().count(), 205 + 5);
+    
+    // Replaying the compacted journal keeps every operation, oldest included
+    let mut storage = ChunkStorage::open(&path, StorageBackend::Jsonl)?;
+    for _ in 0..200 {
+        assert!(storage.undo()?.is_some());
+    }
+    assert!(storage.undo()?.is_none());
+    assert_eq!(storage.get_chunks()?.len(), 0);
+    
+    Ok(())
+}
+
+#[test]
+fn test_csv_without_fingerprint_columns_is_upgraded() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let csv_path = temp_dir.path().join("chunks.csv");
+    
+    // A chunk file written before source/lines hashes were stored
+    fs::write(
+        &csv_path,
+        "\"id\",\"file_path\",\"start_line\",\"end_line\",\"content\",\"timestamp\",\"edited\",\"labels\"\n\
+         \"old\",\"a.txt\",\"1\",\"2\",\"hello\",\"0\",\"false\",\"\"\n",
+    )?;
+    
+    let mut storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    let old = storage.get_chunk("old")?.expect("legacy row should load");
+    assert!(old.lines_hash.is_empty());
+    
+    let chunk = sample_chunk("a.txt", 3, 4).with_fingerprint("source".to_string(), "lines".to_string());
+    storage.add_chunk(chunk.clone())?;
+    
+    // The appended row keeps its fingerprint after a reload
+    let storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    let reloaded = storage.get_chunk(&chunk.id)?.unwrap();
+    assert_eq!(reloaded.source_hash, "source");
+    assert_eq!(reloaded.lines_hash, "lines");
+    
+    Ok(())
+}
+
+#[test]
+fn test_older_chunk_files_are_migrated_with_backup() -> Result<()> {
+    let temp_dir = tempdir()?;
+    
+    // A version 1 CSV file, from before fingerprints and column boundaries
+    let csv_path = temp_dir.path().join("chunks.csv");
+    let legacy_csv = "\"id\",\"file_path\",\"start_line\",\"end_line\",\"content\",\"timestamp\",\"edited\",\"labels\"\n\
+                      \"old\",\"a.txt\",\"1\",\"2\",\"hello\",\"0\",\"false\",\"arm\"\n";
+    fs::write(&csv_path, legacy_csv)?;
+    
+    let storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert_eq!(storage.get_chunk("old")?.unwrap().labels, vec!["arm".to_string()]);
+    assert_eq!(storage.warnings().len(), 1, "The upgrade should be reported");
+    assert!(storage.warnings()[0].contains("from schema version 1 to 6"));
+    assert_eq!(fs::read_to_string(temp_dir.path().join("chunks.csv.v1.bak"))?, legacy_csv);
+    
+    let upgraded = fs::read_to_string(&csv_path)?;
+    assert!(upgraded.starts_with("# packrat chunk schema version 6\n"));
+    assert!(upgraded.lines().nth(1).unwrap().ends_with("\"start_column\",\"end_column\",\"metadata\",\"window_id\""));
+    
+    // The upgraded file opens without another migration
+    let storage = ChunkStorage::open(&csv_path, StorageBackend::Csv)?;
+    assert!(storage.warnings().is_empty());
+    assert_eq!(storage.get_chunks()?.len(), 1);
+    
+    // A version 2 JSONL log gains the column fields
+    let log_path = temp_dir.path().join("chunks.jsonl");
+    fs::write(
+        &log_path,
+        "{\"op\":\"add\",\"chunk\":{\"id\":\"old\",\"file_path\":\"a.txt\",\"start_line\":1,\"end_line\":2,\
+         \"content\":\"hello\",\"timestamp\":0,\"edited\":false,\"labels\":\"\",\"source_hash\":\"s\",\"lines_hash\":\"l\"}}\n",
+    )?;
+    
+    let storage = ChunkStorage::open(&log_path, StorageBackend::Jsonl)?;
+    assert_eq!(storage.get_chunk("old")?.unwrap().lines_hash, "l");
+    assert!(storage.warnings()[0].contains("from schema version 2 to 6"));
+    assert!(temp_dir.path().join("chunks.jsonl.v2.bak").exists());
+    assert!(fs::read_to_string(&log_path)?.starts_with("{\"op\":\"schema\",\"version\":6}\n"));
+    
+    // A version 1 SQLite database gets the missing columns
+    let db_path = temp_dir.path().join("legacy.db");
+    rusqlite::Connection::open(&db_path)?.execute_batch(
+        "CREATE TABLE chunks (
+            id TEXT PRIMARY KEY NOT NULL, file_path TEXT NOT NULL, start_line INTEGER NOT NULL,
+            end_line INTEGER NOT NULL, content TEXT NOT NULL, timestamp INTEGER NOT NULL,
+            edited INTEGER NOT NULL, labels TEXT NOT NULL
+        );
+        INSERT INTO chunks VALUES ('old', 'a.txt', 1, 2, 'hello', 0, 0, '[]');",
+    )?;
+    
+    let storage = ChunkStorage::open(&db_path, StorageBackend::Sqlite)?;
+    let old = storage.get_chunk("old")?.unwrap();
+    assert_eq!((old.start_column, old.end_column), (None, None));
+    assert!(old.window_id.is_empty());
+    assert!(storage.warnings()[0].contains("from schema version 1 to 6"));
+    assert!(temp_dir.path().join("legacy.db.v1.bak").exists());
+    
+    let version: u32 = rusqlite::Connection::open(&db_path)?
+        .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    assert_eq!(version, 6);
+    
+    Ok(())
+}
+
+#[test]
+fn test_labels_round_trip_filter_and_export() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let awkward = vec!["a|b".to_string(), "x, y".to_string(), "say \"hi\"".to_string()];
+    
+    // Labels with separators and quotes survive a reload in every text backend
+    for (name, backend) in [("chunks.csv", StorageBackend::Csv), ("chunks.jsonl", StorageBackend::Jsonl)] {
+        let path = temp_dir.path().join(name);
+        let mut chunk = sample_chunk("a.txt", 1, 2);
+        chunk.labels = awkward.clone();
+        ChunkStorage::open(&path, backend)?.add_chunk(chunk.clone())?;
+        
+        let storage = ChunkStorage::open(&path, backend)?;
+        assert_eq!(storage.get_chunk(&chunk.id)?.unwrap().labels, awkward, "{} lost labels", name);
+    }
+    
+    // Version 3 CSV files joined labels with '|', which becomes a JSON array
+    let legacy_path = temp_dir.path().join("legacy.csv");
+    fs::write(
+        &legacy_path,
+        "# packrat chunk schema version 3\n\
+         \"id\",\"file_path\",\"start_line\",\"end_line\",\"content\",\"timestamp\",\"edited\",\"labels\",\
+         \"source_hash\",\"lines_hash\",\"start_column\",\"end_column\"\n\
+         \"old\",\"a.txt\",\"1\",\"2\",\"hello\",\"0\",\"false\",\"arm|leg\",\"\",\"\",\"\",\"\"\n",
+    )?;
+    let storage = ChunkStorage::open(&legacy_path, StorageBackend::Csv)?;
+    assert_eq!(storage.get_chunk("old")?.unwrap().labels, vec!["arm".to_string(), "leg".to_string()]);
+    assert!(fs::read_to_string(&legacy_path)?.contains("\"[\"\"arm\"\",\"\"leg\"\"]\""));
+    
+    // Label queries and filtered exports
+    let mut storage = ChunkStorage::open(temp_dir.path().join("labelled.jsonl"), StorageBackend::Jsonl)?;
+    for (file, labels) in [("a.txt", vec!["draft"]), ("b.txt", vec!["draft", "final"]), ("c.txt", vec![])] {
+        let mut chunk = sample_chunk(file, 1, 2);
+        chunk.labels = labels.into_iter().map(String::from).collect();
+        storage.add_chunk(chunk)?;
+    }
+    assert_eq!(storage.all_labels()?, vec!["draft".to_string(), "final".to_string()]);
+    let final_files = storage.files_with_labels(&["final".to_string()])?;
+    assert!(final_files.contains(&PathBuf::from("b.txt")) && final_files.len() == 1);
+    
+    let options = ExportOptions { labels: vec!["draft".to_string()], ..ExportOptions::default() };
+    let jsonl_path = temp_dir.path().join("export.jsonl");
+    assert_eq!(export_chunks(&storage, &jsonl_path, ExportFormat::from_path(&jsonl_path)?, &options)?, 2);
+    let first: serde_json::Value = serde_json::from_str(fs::read_to_string(&jsonl_path)?.lines().next().unwrap())?;
+    assert_eq!(first["labels"], serde_json::json!(["draft"]));
+    
+    let csv_path = temp_dir.path().join("export.csv");
+    assert_eq!(export_chunks(&storage, &csv_path, ExportFormat::Csv, &ExportOptions::default())?, 3);
+    assert_eq!(fs::read_to_string(&csv_path)?.lines().count(), 4, "Header plus one row per chunk");
+    assert!(ExportFormat::from_path(&temp_dir.path().join("export.txt")).is_err());
+    
+    Ok(())
+}
+
+#[test]
+fn test_metadata_round_trips_and_is_flattened_in_exports() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let metadata: Metadata = [
+        ("language".to_string(), MetadataValue::Text("en".to_string())),
+        ("difficulty".to_string(), MetadataValue::Integer(3)),
+        ("score".to_string(), MetadataValue::Number(0.5)),
+        ("reviewed".to_string(), MetadataValue::Boolean(true)),
+    ].into_iter().collect();
+    
+    // Values keep their types in every backend
+    for (name, backend) in [
+        ("chunks.csv", StorageBackend::Csv),
+        ("chunks.jsonl", StorageBackend::Jsonl),
+        ("chunks.db", StorageBackend::Sqlite),
+    ] {
+        let path = temp_dir.path().join(name);
+        let mut chunk = sample_chunk("a.txt", 1, 2);
+        chunk.metadata = metadata.clone();
+        ChunkStorage::open(&path, backend)?.add_chunk(chunk.clone())?;
+        
+        let storage = ChunkStorage::open(&path, backend)?;
+        assert_eq!(storage.get_chunk(&chunk.id)?.unwrap().metadata, metadata, "{} lost metadata", name);
+    }
+    
+    // A chunk without metadata exports empty cells for the other chunk's keys
+    let mut storage = ChunkStorage::open(temp_dir.path().join("chunks.jsonl"), StorageBackend::Jsonl)?;
+    storage.add_chunk(sample_chunk("b.txt", 1, 1))?;
+    
+    let jsonl_path = temp_dir.path().join("export.jsonl");
+    export_chunks(&storage, &jsonl_path, ExportFormat::Jsonl, &ExportOptions::default())?;
+    let first: serde_json::Value = serde_json::from_str(fs::read_to_string(&jsonl_path)?.lines().next().unwrap())?;
+    assert_eq!(first["difficulty"], serde_json::json!(3));
+    assert_eq!(first["reviewed"], serde_json::json!(true));
+    
+    let csv_path = temp_dir.path().join("export.csv");
+    export_chunks(&storage, &csv_path, ExportFormat::Csv, &ExportOptions::default())?;
+    let exported = fs::read_to_string(&csv_path)?;
+    let lines: Vec<&str> = exported.lines().collect();
+    assert!(lines[0].ends_with(",content,difficulty,language,reviewed,score"));
+    assert!(lines[1].ends_with(",3,en,true,0.5"));
+    assert!(lines[2].ends_with(",,,,"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_exports_can_include_rendered_prompts() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let mut storage = ChunkStorage::open(temp_dir.path().join("chunks.csv"), StorageBackend::Csv)?;
+    let mut chunk = sample_chunk("src/a.rs", 3, 4);
+    chunk.content = "fn a() {}".to_string();
+    chunk.metadata.insert("topic".to_string(), MetadataValue::Text("io".to_string()));
+    storage.add_chunk(chunk)?;
+    
+    let options = ExportOptions {
+        prompt_template: Some(PromptTemplate::new("{file_path}:{start_line}-{end_line}\n{content}\n{{done}}")),
+        ..ExportOptions::default()
+    };
+    let rendered = "src/a.rs:3-4\nfn a() {}\n{done}";
+    
+    let jsonl_path = temp_dir.path().join("export.jsonl");
+    export_chunks(&storage, &jsonl_path, ExportFormat::Jsonl, &options)?;
+    let exported: serde_json::Value = serde_json::from_str(fs::read_to_string(&jsonl_path)?.trim())?;
+    assert_eq!(exported["content"], serde_json::json!("fn a() {}"));
+    assert_eq!(exported["prompt"], serde_json::json!(rendered));
+    
+    // The prompt column goes between the content and the metadata
+    let csv_path = temp_dir.path().join("export.csv");
+    export_chunks(&storage, &csv_path, ExportFormat::Csv, &options)?;
+    let mut reader = csv::Reader::from_path(&csv_path)?;
+    let headers = reader.headers()?.clone();
+    assert!(headers.iter().collect::<Vec<_>>().ends_with(&["content", "prompt", "topic"]));
+    let record = reader.records().next().unwrap()?;
+    assert_eq!(&record[headers.len() - 2], rendered);
+    
+    // Without a template, exports are unchanged
+    export_chunks(&storage, &jsonl_path, ExportFormat::Jsonl, &ExportOptions::default())?;
+    assert!(!fs::read_to_string(&jsonl_path)?.contains("\"prompt\""));
+    
+    Ok(())
+}
+
+#[test]
+fn test_window_ids_round_trip() -> Result<()> {
+    let temp_dir = tempdir()?;
+    
+    for (name, backend) in [
+        ("chunks.csv", StorageBackend::Csv),
+        ("chunks.jsonl", StorageBackend::Jsonl),
+        ("chunks.db", StorageBackend::Sqlite),
+    ] {
+        let path = temp_dir.path().join(name);
+        let window = sample_chunk("a.txt", 1, 4).with_window("pass".to_string());
+        let plain = sample_chunk("a.txt", 3, 6);
+        ChunkStorage::open(&path, backend)?.add_chunks(vec![window.clone(), plain.clone()])?;
+        
+        let storage = ChunkStorage::open(&path, backend)?;
+        assert_eq!(storage.get_chunk(&window.id)?.unwrap().window_id, "pass", "{} lost the window id", name);
+        assert_eq!(storage.get_chunk(&plain.id)?.unwrap().window_id, "", "{}", name);
+    }
+    
+    // Only windows of the same pass overlap on purpose
+    assert!(same_window("pass", "pass"));
+    assert!(!same_window("pass", "other"));
+    assert!(!same_window("", ""));
+    
+    Ok(())
+}
+
+#[test]
+fn test_chunk_files_from_newer_versions_are_refused() -> Result<()> {
+    let temp_dir = tempdir()?;
+    
+    let csv_path = temp_dir.path().join("chunks.csv");
+    fs::write(&csv_path, "# packrat chunk schema version 99\n\"id\"\n\"future\"\n")?;
+    
+    let log_path = temp_dir.path().join("chunks.jsonl");
+    fs::write(&log_path, "{\"op\":\"schema\",\"version\":99}\n")?;
+    
+    let db_path = temp_dir.path().join("chunks.db");
+    rusqlite::Connection::open(&db_path)?.execute_batch("PRAGMA user_version = 99;")?;
+    
+    for (path, backend) in [
+        (&csv_path, StorageBackend::Csv),
+        (&log_path, StorageBackend::Jsonl),
+        (&db_path, StorageBackend::Sqlite),
+    ] {
+        let error = ChunkStorage::open(path, backend).err().expect("newer files should be refused");
+        assert!(
+            error.to_string().contains("schema version 99") && error.to_string().contains("Upgrade packrat"),
+            "Unexpected error: {}",
+            error
+        );
+    }
+    
+    // Refused files are left untouched
+    assert!(fs::read_to_string(&csv_path)?.ends_with("\"future\"\n"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_exports_import_back() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let mut chunk = sample_chunk("src/a.rs", 3, 4).with_columns(Some(2), None);
+    chunk.labels = vec!["draft".to_string(), "rust".to_string()];
+    chunk.metadata = [
+        ("language".to_string(), MetadataValue::Text("en".to_string())),
+        ("difficulty".to_string(), MetadataValue::Integer(3)),
+        ("reviewed".to_string(), MetadataValue::Boolean(true)),
+    ].into_iter().collect();
+    let windows = [
+        sample_chunk("c.txt", 1, 3).with_window("pass".to_string()).with_fingerprint("source".to_string(), "lines".to_string()),
+        sample_chunk("c.txt", 3, 4).with_window("pass".to_string()),
+    ];
+    let mut source = ChunkStorage::open(temp_dir.path().join("chunks.jsonl"), StorageBackend::Jsonl)?;
+    source.add_chunks([vec![chunk.clone(), sample_chunk("b.txt", 1, 1)], windows.to_vec()].concat())?;
+    
+    // Rendered prompts aren't read back as metadata
+    let options = ExportOptions { prompt_template: Some(PromptTemplate::new("{content}")), ..ExportOptions::default() };
+    for (name, format) in [("export.jsonl", ExportFormat::Jsonl), ("export.csv", ExportFormat::Csv)] {
+        let export_path = temp_dir.path().join(name);
+        export_chunks(&source, &export_path, format, &options)?;
+        
+        let mut storage = ChunkStorage::open(temp_dir.path().join(format!("{}.db", name)), StorageBackend::Sqlite)?;
+        assert_eq!(import_chunks(&mut storage, &export_path, format)?, ImportReport { imported: 4, skipped: 0 });
+        let imported = storage.get_chunk(&chunk.id)?.expect("ids are kept");
+        assert_eq!(imported.content, chunk.content, "{}", name);
+        assert_eq!((imported.start_column, imported.end_column), (Some(2), None), "{}", name);
+        assert_eq!(imported.labels, chunk.labels, "{}", name);
+        assert_eq!(imported.metadata, chunk.metadata, "{} lost metadata types", name);
+        assert_eq!(imported.timestamp, chunk.timestamp, "{}", name);
+        
+        // Windows stay one pass, so their overlap is still intended
+        let window = storage.get_chunk(&windows[0].id)?.expect("ids are kept");
+        assert_eq!(window.window_id, "pass", "{} lost the window id", name);
+        assert_eq!((window.source_hash.as_str(), window.lines_hash.as_str()), ("source", "lines"), "{}", name);
+        let problems = validate_chunks(&storage.get_chunks()?, temp_dir.path(), 100, |_| 1);
+        assert!(
+            !problems.iter().any(|problem| matches!(problem.problem, Problem::Overlap { .. })),
+            "{}: {:?}",
+            name,
+            problems
+        );
+        
+        // Importing again adds nothing
+        assert_eq!(import_chunks(&mut storage, &export_path, format)?, ImportReport { imported: 0, skipped: 4 });
+        assert_eq!(storage.get_chunks()?.len(), 4);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_validation_finds_broken_chunks() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let lines: Vec<String> = (1..=6).map(|line| format!("line {}", line)).collect();
+    fs::write(temp_dir.path().join("a.txt"), lines.join("\n"))?;
+    let fingerprinted = |start: usize, end: usize, lines: &[String]| {
+        sample_chunk("a.txt", start, end).with_fingerprint(hash_lines(lines), hash_lines(&lines[start - 1..end]))
+    };
+    
+    // Saved before line 2 changed
+    let mut old_lines = lines.clone();
+    old_lines[1] = "old line 2".to_string();
+    let stale = fingerprinted(1, 2, &old_lines);
+    let fresh = fingerprinted(3, 4, &lines);
+    let overlapping = sample_chunk("a.txt", 4, 5);
+    let windows = [
+        sample_chunk("a.txt", 5, 6).with_window("pass".to_string()),
+        sample_chunk("a.txt", 6, 6).with_window("pass".to_string()),
+    ];
+    let past_end = sample_chunk("a.txt", 6, 9);
+    let missing = sample_chunk("gone.txt", 1, 1);
+    let chunks = [vec![stale.clone(), fresh, overlapping.clone(), past_end.clone(), missing.clone()], windows.to_vec()].concat();
+    
+    // Every chunk fits a budget of 100 except the one past the end of the file
+    let problems = validate_chunks(&chunks, temp_dir.path(), 100, |chunk| if chunk.id == past_end.id { 101 } else { 1 });
+    let found: Vec<(&str, &Problem)> = problems.iter().map(|problem| (problem.id.as_str(), &problem.problem)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (stale.id.as_str(), &Problem::Stale),
+            (overlapping.id.as_str(), &Problem::Overlap { other: chunks[1].id.clone() }),
+            (windows[0].id.as_str(), &Problem::Overlap { other: overlapping.id.clone() }),
+            (past_end.id.as_str(), &Problem::OutOfRange { lines: 6 }),
+            (past_end.id.as_str(), &Problem::Overlap { other: windows[0].id.clone() }),
+            (past_end.id.as_str(), &Problem::OverBudget { tokens: 101, max_tokens: 100 }),
+            (missing.id.as_str(), &Problem::MissingFile),
+        ]
+    );
+    
+    // Counts for the stats command
+    let mut labelled = sample_chunk("b.txt", 1, 1);
+    labelled.labels = vec!["draft".to_string()];
+    labelled.metadata.insert("topic".to_string(), MetadataValue::Text("io".to_string()));
+    let stats = ChunkStats::collect(&[chunks, vec![labelled]].concat());
+    assert_eq!((stats.chunks, stats.files, stats.unlabelled, stats.window_passes), (8, 3, 7, 1));
+    assert_eq!(stats.labels.get("draft"), Some(&1));
+    assert_eq!(stats.metadata_fields.get("topic"), Some(&1));
+    
+    Ok(())
+}
+
+#[test]
+fn test_reanchoring_lines_chunks_up_by_their_rarest_lines() -> Result<()> {
+    // Mostly braces and blank lines, with a few lines that only occur once
+    let mut lines: Vec<String> = (0..6000).map(|i| if i % 2 == 0 { "}".to_string() } else { String::new() }).collect();
+    lines[100] = "fn moved() {".to_string();
+    lines[200] = "fn reworded() {".to_string();
+    lines[201] = "    body();".to_string();
+    let chunk_at = |lines: &[String], start: usize, end: usize| {
+        let content = lines[start - 1..end].join("\n");
+        Chunk::new(PathBuf::from("a.rs"), start, end, content, false)
+            .with_fingerprint(hash_lines(lines), hash_lines(&lines[start - 1..end]))
+    };
+    let moved = chunk_at(&lines, 101, 104);
+    let reworded = chunk_at(&lines, 201, 204);
+    
+    // Move the first chunk's lines far down and reword a line of the second
+    let mut changed = lines.clone();
+    let block: Vec<String> = changed.drain(100..104).collect();
+    changed.splice(5000..5000, block);
+    changed[197] = "fn reworded(x: u32) {".to_string();
+    
+    let report = reanchor_chunks(&[moved, reworded], &changed);
+    assert!(report.unplaced.is_empty());
+    let placed: Vec<_> = report.placed.iter().map(|placement| (placement.kind, placement.chunk.start_line)).collect();
+    assert_eq!(placed, vec![(MatchKind::Exact, 5001), (MatchKind::Fuzzy, 197)]);
+    
+    Ok(())
+}
@@ -1 +1,378 @@
+use packrat::storage::{validate_metadata_fields, Metadata, MetadataField, MetadataType, MetadataValue};
+use packrat::utils::labels::{format_labels, parse_labels, LabelInput};
+use packrat::utils::metadata_form::MetadataForm;
+use packrat::utils::prompt::{PromptContext, PromptTemplate};
+use packrat::utils::token_cache::{BackgroundCounter, LineTokenCounts};
+use packrat::utils::{load_bpe_file, Tokenizer, TokenizerKind};
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+#[test]
+fn test_parse_and_format_labels() {
+    assert_eq!(parse_labels(" arm , leg,,arm "), vec!["arm".to_string(), "leg".to_string()]);
+    assert!(parse_labels("  ").is_empty());
+
+    // Commas and backslashes can be escaped, and formatting escapes them back
+    let labels = parse_labels(r"x\, y, back\\slash");
+    assert_eq!(labels, vec!["x, y".to_string(), r"back\slash".to_string()]);
+    assert_eq!(parse_labels(&format_labels(&labels)), labels);
+}
+
+#[test]
+fn test_label_input_completion() {
+    let known = vec!["review".to_string(), "arm".to_string(), "Reference".to_string()];
+    
+    // Prefilled labels are followed by a separator for the next label
+    let mut input = LabelInput::new(&["arm".to_string()], known.clone());
+    assert_eq!(input.text(), "arm, ");
+    
+    // Completion is case-insensitive and skips labels already entered
+    input.push('r');
+    assert_eq!(input.suggestions(), vec!["review", "Reference"]);
+    assert!(input.complete());
+    assert_eq!(input.labels(), vec!["arm".to_string(), "review".to_string()]);
+    
+    input.push('a');
+    assert!(input.suggestions().is_empty());
+    assert!(!input.complete());
+    input.backspace();
+    assert_eq!(input.suggestions(), vec!["Reference"]);
+}
+
+fn metadata_field(name: &str, value_type: MetadataType, required: bool, choices: &[&str]) -> MetadataField {
+    MetadataField {
+        name: name.to_string(),
+        value_type,
+        required,
+        choices: choices.iter().map(|choice| choice.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_metadata_form_validates_typed_values() {
+    let fields = vec![
+        metadata_field("language", MetadataType::Text, true, &["en", "de"]),
+        metadata_field("difficulty", MetadataType::Integer, false, &[]),
+        metadata_field("reviewed", MetadataType::Boolean, false, &[]),
+    ];
+    assert!(validate_metadata_fields(&fields).is_ok());
+    
+    // Existing values are prefilled, and keys that are no longer configured are kept
+    let initial: Metadata = [
+        ("difficulty".to_string(), MetadataValue::Integer(2)),
+        ("legacy".to_string(), MetadataValue::Text("kept".to_string())),
+    ].into_iter().collect();
+    let mut form = MetadataForm::new(fields, &initial);
+    assert_eq!(form.entries().map(|(_, value)| value).collect::<Vec<_>>(), vec!["", "2", ""]);
+    
+    // A missing required value focuses its field
+    form.next_field();
+    assert!(form.metadata().unwrap_err().to_string().contains("language is required"));
+    assert_eq!(form.focused(), 0);
+    
+    form.push('f');
+    form.push('r');
+    assert!(form.metadata().unwrap_err().to_string().contains("one of: en, de"));
+    form.backspace();
+    form.backspace();
+    form.push('d');
+    form.push('e');
+    
+    form.next_field();
+    form.push('x');
+    assert!(form.metadata().is_err());
+    assert_eq!(form.focused(), 1);
+    form.backspace();
+    
+    form.previous_field();
+    form.previous_field();
+    form.push('y');
+    let metadata = form.metadata().unwrap();
+    assert_eq!(metadata["language"], MetadataValue::Text("de".to_string()));
+    assert_eq!(metadata["difficulty"], MetadataValue::Integer(2));
+    assert_eq!(metadata["reviewed"], MetadataValue::Boolean(true));
+    assert_eq!(metadata["legacy"], MetadataValue::Text("kept".to_string()));
+}
+
+#[test]
+fn test_metadata_fields_are_validated() {
+    let duplicate = vec![
+        metadata_field("topic", MetadataType::Text, false, &[]),
+        metadata_field("topic", MetadataType::Integer, false, &[]),
+    ];
+    assert!(validate_metadata_fields(&duplicate).is_err());
+    
+    // Exports flatten metadata next to the built-in fields, so names can't clash
+    assert!(validate_metadata_fields(&[metadata_field("content", MetadataType::Text, false, &[])]).is_err());
+    assert!(validate_metadata_fields(&[metadata_field("prompt", MetadataType::Text, false, &[])]).is_err());
+    assert!(validate_metadata_fields(&[metadata_field("level", MetadataType::Integer, false, &["1"])]).is_err());
+}
+
+#[test]
+fn test_metadata_numbers_must_be_finite() -> anyhow::Result<()> {
+    let score = metadata_field("score", MetadataType::Number, false, &[]);
+    assert_eq!(score.parse("0.5")?, Some(MetadataValue::Number(0.5)));
+    
+    // JSON can't hold these, so a stored chunk with one couldn't be read back
+    for text in ["NaN", "inf", "-inf", "infinity"] {
+        assert!(score.parse(text).is_err(), "{} was accepted", text);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_selectable_tokenizers() -> anyhow::Result<()> {
+    let text = "fn main() {\n    println!(\"héllo\");\n}";
+    
+    assert_eq!(TokenizerKind::Words.tokenizer()?.count(text), 5);
+    assert_eq!(TokenizerKind::Chars.tokenizer()?.count(text), 36);
+    
+    for kind in TokenizerKind::ALL {
+        let tokenizer = kind.tokenizer()?;
+        assert_eq!(tokenizer.name(), kind.name());
+        assert_eq!(TokenizerKind::from_name(kind.name())?, kind);
+        assert!(tokenizer.count(text) > 0);
+        assert_eq!(tokenizer.count_lines(&["a b".to_string(), "c".to_string()]), tokenizer.count("a b\nc"));
+    }
+    assert!(TokenizerKind::from_name("claude").is_err());
+    
+    // The encodings really differ, most visibly outside English
+    let japanese = "日本語のテキストを分割します";
+    let counts: Vec<usize> = [TokenizerKind::O200k, TokenizerKind::Cl100k, TokenizerKind::P50k]
+        .into_iter()
+        .map(|kind| kind.tokenizer().map(|tokenizer| tokenizer.count(japanese)))
+        .collect::<anyhow::Result<_>>()?;
+    assert!(counts[0] < counts[1] && counts[1] < counts[2], "Unexpected counts: {:?}", counts);
+    
+    // The tokenizer is chosen by name in the config file, defaulting to cl100k_base
+    let config: packrat::config::Config = toml::from_str(
+        "chunk_file = \"chunks.csv\"\nmax_tokens_per_chunk = 10\nenable_debug = false\n\
+         debug_dir = \"debug\"\nsource_dir = \".\"\nauto_save_chunks = false\ntokenizer = \"o200k_base\"\n",
+    )?;
+    assert_eq!(config.tokenizer, TokenizerKind::O200k);
+    assert_eq!(packrat::config::Config::default().tokenizer, TokenizerKind::Cl100k);
+    
+    Ok(())
+}
+
+#[test]
+fn test_token_spans() -> anyhow::Result<()> {
+    let text = "let café = \"naïve\"; // 日本語";
+    
+    // Spans cover the text in order, one per counted token
+    for kind in TokenizerKind::ALL {
+        let tokenizer = kind.tokenizer()?;
+        let spans = tokenizer.token_spans(text);
+        let joined: String = spans.iter().map(|span| &text[span.clone()]).collect();
+        assert!(spans.windows(2).all(|pair| pair[0].end <= pair[1].start), "{}: {:?}", kind.name(), spans);
+        if kind == TokenizerKind::Words {
+            assert_eq!(joined, "letcafé=\"naïve\";//日本語");
+        } else {
+            assert_eq!(joined, text, "{}", kind.name());
+        }
+        // Tokens splitting a character are merged, so BPE may show fewer spans
+        assert!(spans.len() <= tokenizer.count(text), "{}", kind.name());
+    }
+    
+    // Plain ASCII never splits a character, so every token gets its own span
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    let ascii = "fn main() { println!(\"hello\"); }";
+    assert_eq!(tokenizer.token_spans(ascii).len(), tokenizer.count(ascii));
+    
+    let words = TokenizerKind::Words.tokenizer()?;
+    assert_eq!(words.token_spans("  a bc\td "), vec![2..3, 4..6, 7..8]);
+    let chars = TokenizerKind::Chars.tokenizer()?;
+    assert_eq!(chars.token_spans("aé"), vec![0..1, 1..3]);
+    assert!(chars.token_spans("").is_empty());
+    
+    Ok(())
+}
+
+/// Write a tiktoken ranks file with every byte plus the merges given, in rank order
+fn write_ranks_file(path: &std::path::Path, merges: &[&str]) -> anyhow::Result<()> {
+    use base64::Engine;
+    let tokens = (0..=255u8).map(|byte| vec![byte]).chain(merges.iter().map(|merge| merge.as_bytes().to_vec()));
+    let lines: Vec<String> = tokens
+        .enumerate()
+        .map(|(rank, token)| format!("{} {}", base64::engine::general_purpose::STANDARD.encode(token), rank))
+        .collect();
+    fs::write(path, lines.join("\n"))?;
+    Ok(())
+}
+
+#[test]
+fn test_custom_bpe_files() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    
+    // A tiktoken ranks file, split with cl100k_base's pattern unless configured
+    let ranks_file = dir.path().join("tiny.tiktoken");
+    write_ranks_file(&ranks_file, &["he", "hel", "hell", "hello"])?;
+    let tokenizer = load_bpe_file(&ranks_file, None)?;
+    assert_eq!(tokenizer.name(), "tiny.tiktoken");
+    // "hello", then " help" as " " + "hel" + "p"
+    assert_eq!(tokenizer.count("hello help"), 4);
+    assert_eq!(load_bpe_file(&ranks_file, Some("."))?.count("hello help"), 10);
+    
+    // A HuggingFace byte-level tokenizer.json, where "Ġ" stands for a space
+    let json_file = dir.path().join("tokenizer.json");
+    fs::write(&json_file, r#"{
+        "model": {"type": "BPE", "vocab": {}, "merges": ["h e", "he l", ["hel", "l"], "hell o", "Ġ w"]},
+        "pre_tokenizer": {"type": "ByteLevel", "add_prefix_space": false, "use_regex": true}
+    }"#)?;
+    // "hello", then " world" as " w" + "o" + "r" + "l" + "d"
+    assert_eq!(load_bpe_file(&json_file, None)?.count("hello world"), 6);
+    
+    // The file is set by path in the config and replaces the built-in tokenizer
+    let config = packrat::config::Config {
+        tokenizer_file: Some(json_file.clone()),
+        ..Default::default()
+    };
+    let tokenizer = config.load_tokenizer()?;
+    assert_eq!(tokenizer.name(), "tokenizer.json");
+    assert_eq!(tokenizer.count("hello"), 1);
+    
+    // Vocabularies that can't encode every byte are rejected up front
+    let error = |path: &std::path::Path| load_bpe_file(path, None).err().map(|e| format!("{:#}", e)).unwrap_or_default();
+    let sentencepiece = dir.path().join("sentencepiece.json");
+    fs::write(&sentencepiece, r#"{"model": {"type": "BPE", "merges": ["▁ h"]}}"#)?;
+    assert!(error(&sentencepiece).contains("byte-level"));
+    fs::write(&ranks_file, "aGk= 0\n")?;
+    assert!(error(&ranks_file).contains("no token for byte 0x00"));
+    assert!(error(&dir.path().join("missing.tiktoken")).contains("Failed to read"));
+    
+    Ok(())
+}
+
+/// Counts words and how many times it was asked to
+#[derive(Default)]
+struct CallCounter(AtomicUsize);
+
+impl Tokenizer for CallCounter {
+    fn name(&self) -> &str {
+        "calls"
+    }
+    
+    fn count(&self, text: &str) -> usize {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        text.split_whitespace().count()
+    }
+}
+
+#[test]
+fn test_line_token_counts_are_cached() {
+    let tokenizer = Arc::new(CallCounter::default());
+    let mut counts = LineTokenCounts::new(tokenizer.clone());
+    let lines: Vec<String> = ["a b", "c", "a b", "d e f"].iter().map(|line| line.to_string()).collect();
+    
+    // Repeated lines are encoded once
+    counts.recount(&lines);
+    assert_eq!(tokenizer.0.load(Ordering::SeqCst), 3);
+    assert_eq!(counts.line(3), 3);
+    assert_eq!(counts.densest(), 3);
+    
+    // Range totals come from prefix sums, plus a token per newline
+    assert_eq!(counts.range_total(1, 2), 1 + 2 + 1);
+    assert_eq!(counts.total(), 2 + 1 + 2 + 3 + 3);
+    assert_eq!(counts.range_total(3, 10), 3);
+    assert_eq!(counts.range_total(2, 1), 0);
+    
+    // An edit only encodes the lines that changed
+    let mut edited = lines.clone();
+    edited[1] = "c c c c".to_string();
+    edited.insert(2, "g".to_string());
+    counts.update(&lines, &edited);
+    assert_eq!(tokenizer.0.load(Ordering::SeqCst), 5);
+    assert_eq!(counts.range_total(0, 4), 2 + 4 + 1 + 2 + 3 + 4);
+    assert_eq!(counts.densest(), 4);
+    
+    // Removing the densest line finds the next densest
+    counts.splice(1, 3, &[]);
+    assert_eq!(counts.total(), 2 + 2 + 3 + 2);
+    assert_eq!(counts.densest(), 3);
+    assert_eq!(tokenizer.0.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn test_background_counter_answers_the_newest_request() -> anyhow::Result<()> {
+    let tokenizer = TokenizerKind::Words.tokenizer()?;
+    let mut counter = BackgroundCounter::new(tokenizer);
+    
+    counter.request(1, || "one two".to_string());
+    counter.request(2, || "one two three".to_string());
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while counter.result(2).is_none() && Instant::now() < deadline {
+        counter.poll();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(counter.result(2), Some(3));
+    assert_eq!(counter.result(1), None);
+    
+    // Already counted keys aren't counted again
+    counter.request(2, || panic!("text built for a finished count"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_prompt_templates() {
+    let context = PromptContext { file_path: "src/lib.rs".to_string(), start_line: 10, end_line: 12 };
+    let template = PromptTemplate::new("Review {file_path} ({start_line}-{end_line}):\n{content}\nReply as {{\"ok\": bool}}");
+    assert!(template.validate().is_ok());
+    assert_eq!(
+        template.render(&context, "let x = 1;"),
+        "Review src/lib.rs (10-12):\nlet x = 1;\nReply as {\"ok\": bool}"
+    );
+    
+    // The content must have a place, and only known placeholders can be used
+    assert!(PromptTemplate::new("Just {file_path}").validate().is_err());
+    assert!(PromptTemplate::new("{content} {labels}").validate().unwrap_err().to_string().contains("{labels}"));
+    assert!(PromptTemplate::new("{content").validate().is_err());
+    assert!(PromptTemplate::new("{content} }").validate().is_err());
+}
+
+#[test]
+fn test_example_config_holds_the_defaults() -> anyhow::Result<()> {
+    // `config --init` writes the commented example, so it must be the default configuration
+    let example: packrat::config::Config = toml::from_str(packrat::config::EXAMPLE_CONFIG)?;
+    example.validate()?;
+    assert_eq!(toml::to_string(&example)?, toml::to_string(&packrat::config::Config::default())?);
+    
+    Ok(())
+}
+
+#[test]
+fn test_config_fields_can_be_overridden() -> anyhow::Result<()> {
+    let mut config = packrat::config::Config::default();
+    
+    // Values are read as TOML, or taken as text when they aren't valid TOML
+    config.set("max_tokens_per_chunk", "2048")?;
+    config.set("tokenizer", "words")?;
+    config.set("source_dir", "docs/guides")?;
+    config.set("prompt_for_labels", "false")?;
+    config.set("tokenizer_pattern", "\\w+")?;
+    assert_eq!(config.max_tokens_per_chunk, 2048);
+    assert_eq!(config.tokenizer, TokenizerKind::Words);
+    assert_eq!(config.source_dir, std::path::PathBuf::from("docs/guides"));
+    assert!(!config.prompt_for_labels);
+    assert_eq!(config.tokenizer_pattern.as_deref(), Some("\\w+"));
+    
+    // Unknown fields and values of the wrong type are refused, leaving the config as it was
+    assert!(config.set("max_tokens", "10").is_err());
+    assert!(config.set("max_tokens_per_chunk", "lots").is_err());
+    assert_eq!(config.max_tokens_per_chunk, 2048);
+    
+    // Settings that only make sense together are checked afterwards
+    config.set("window_overlap_tokens", "4096")?;
+    assert!(config.validate().is_err());
+    
+    // The overlap must also fit the chunk budget, which caps the window
+    config.set("window_overlap_tokens", "128")?;
+    config.validate()?;
+    config.set("max_tokens_per_chunk", "100")?;
+    assert!(config.validate().is_err());
+    
+    Ok(())
+}
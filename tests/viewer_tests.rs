@@ -1,10 +1,14 @@
 use anyhow::Result;
 use std::path::PathBuf;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
+use std::time::{Duration, Instant};
 use tempfile::tempdir;
 
+use packrat::utils::prompt::PromptTemplate;
+use packrat::utils::TokenizerKind;
 use packrat::viewer::Viewer;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 fn setup_test_files() -> Result<(tempfile::TempDir, PathBuf, PathBuf, PathBuf)> {
     let temp_dir = tempdir()?;
@@ -143,4 +147,224 @@ fn test_viewer_file_switching() -> Result<()> {
     assert!(viewer.content().len() < 10, "Small file should have fewer lines");
     
     Ok(())
-}
\ No newline at end of file
+}
+/// Poll the viewer until every line is counted and its exact token counts are in
+fn wait_for_exact_counts(viewer: &mut Viewer) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let done = |viewer: &Viewer| !viewer.token_count_pending() && viewer.token_counting_progress().is_none();
+    viewer.poll_token_counts();
+    while !done(viewer) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+        viewer.poll_token_counts();
+    }
+    assert!(done(viewer), "Exact token counts never arrived");
+}
+
+#[test]
+fn test_token_counts_are_estimated_then_exact() -> Result<()> {
+    let (_temp_dir, _, varied_file_path, _) = setup_test_files()?;
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&varied_file_path)?;
+    
+    // Until the background recount finishes, totals are estimated
+    assert!(viewer.token_count_pending());
+    wait_for_exact_counts(&mut viewer);
+    assert_eq!(viewer.total_token_count(), tokenizer.count_lines(viewer.content()));
+    
+    // Same for a line-wise selection...
+    viewer.cursor_down();
+    viewer.toggle_selection_mode();
+    viewer.cursor_down();
+    viewer.cursor_down();
+    let estimate = viewer.selection_token_count().unwrap();
+    assert!(viewer.token_count_pending());
+    wait_for_exact_counts(&mut viewer);
+    let exact = tokenizer.count_lines(&viewer.selected_lines());
+    assert_eq!(viewer.selection_token_count(), Some(exact));
+    assert!(estimate.abs_diff(exact) <= 3, "Estimate {} too far from {}", estimate, exact);
+    
+    // ...and a character-wise one
+    viewer.clear_selection();
+    viewer.cursor_right(5);
+    viewer.toggle_char_selection_mode();
+    viewer.cursor_down();
+    wait_for_exact_counts(&mut viewer);
+    assert_eq!(viewer.selection_token_count(), Some(tokenizer.count_lines(&viewer.selected_lines())));
+    
+    Ok(())
+}
+
+#[test]
+fn test_char_selection_starts_where_the_cursor_was_moved() -> Result<()> {
+    let (_temp_dir, small_file_path, _, _) = setup_test_files()?;
+    let key = |code: KeyCode, modifiers: KeyModifiers| KeyEvent::new(code, modifiers);
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&small_file_path)?;
+    
+    // At the start of the line Left is left to the app (it goes back to the explorer)
+    assert!(!viewer.handle_column_key(key(KeyCode::Left, KeyModifiers::NONE)));
+    
+    // The cursor moves along the line before any selection is started
+    assert!(viewer.handle_column_key(key(KeyCode::Right, KeyModifiers::SHIFT)));
+    assert!(viewer.handle_column_key(key(KeyCode::Char('l'), KeyModifiers::NONE)));
+    assert!(viewer.handle_column_key(key(KeyCode::Char('h'), KeyModifiers::NONE)));
+    assert_eq!(viewer.cursor_column(), 10);
+    
+    // So a character-wise selection starts mid-line and extends from there
+    viewer.toggle_char_selection_mode();
+    for _ in 0..3 {
+        assert!(viewer.handle_column_key(key(KeyCode::Right, KeyModifiers::NONE)));
+    }
+    assert_eq!(viewer.selection_columns_on_line(0), Some((10, 14)));
+    assert_eq!(viewer.selected_lines(), vec!["is i".to_string()]);
+    
+    // During a line-wise selection the columns don't matter
+    viewer.clear_selection();
+    viewer.toggle_selection_mode();
+    assert!(!viewer.handle_column_key(key(KeyCode::Right, KeyModifiers::NONE)));
+    
+    Ok(())
+}
+
+#[test]
+fn test_token_boundaries_can_be_shown() -> Result<()> {
+    let (temp_dir, _, _, _) = setup_test_files()?;
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    let file_path = temp_dir.path().join("tokens.txt");
+    fs::write(&file_path, "héllo wörld, hello world\n\nfn main() {}")?;
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&file_path)?;
+    assert!(!viewer.shows_token_boundaries());
+    viewer.toggle_token_boundaries();
+    assert!(viewer.shows_token_boundaries());
+    
+    // Token columns are in characters and tile the whole line
+    let columns = viewer.token_columns_on_line(0);
+    assert_eq!(columns.first().map(|&(start, _)| start), Some(0));
+    assert_eq!(columns.last().map(|&(_, end)| end), Some(24));
+    assert!(columns.windows(2).all(|pair| pair[0].1 == pair[1].0));
+    assert_eq!(viewer.token_columns_on_line(1), vec![]);
+    assert_eq!(viewer.token_columns_on_line(2).len(), tokenizer.count("fn main() {}"));
+    assert_eq!(viewer.token_columns_on_line(3), vec![]);
+    
+    // The gutter's counts are the per-line counts
+    wait_for_exact_counts(&mut viewer);
+    assert_eq!(viewer.line_token_count(1), Some(0));
+    assert_eq!(viewer.line_token_count(2), Some(tokenizer.count("fn main() {}")));
+    
+    viewer.toggle_token_boundaries();
+    assert!(!viewer.shows_token_boundaries());
+    
+    Ok(())
+}
+
+#[test]
+fn test_token_density_heatmap_and_profile() -> Result<()> {
+    let (temp_dir, _, _, _) = setup_test_files()?;
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    
+    // A short prose section, then a base64-like blob
+    let file_path = temp_dir.path().join("dense.txt");
+    let blob = "aGVsbG8gd29ybGQ/Zm9vYmFyYmF6cXV4+/0xMjM0NTY3ODkwYWJjZGVmZ2hpams=";
+    let mut lines: Vec<String> = (0..6).map(|i| format!("Just some words {}", i)).collect();
+    lines.push(String::new());
+    lines.extend((0..5).map(|_| blob.to_string()));
+    fs::write(&file_path, lines.join("\n"))?;
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&file_path)?;
+    
+    // Nothing to shade until the lines are counted
+    assert_eq!(viewer.token_density_profile(4), None);
+    assert_eq!(viewer.line_token_density(0), None);
+    wait_for_exact_counts(&mut viewer);
+    
+    // Lines are shaded relative to the densest one
+    assert_eq!(viewer.line_token_density(8), Some(1.0));
+    assert_eq!(viewer.line_token_density(6), Some(0.0));
+    let expected = tokenizer.count(&lines[0]) as f64 / tokenizer.count(blob) as f64;
+    assert_eq!(viewer.line_token_density(0), Some(expected));
+    assert!(expected < 0.5);
+    assert_eq!(viewer.line_token_density(12), None);
+    
+    // The profile splits the file into equal runs of lines, densest last
+    let profile = viewer.token_density_profile(4).unwrap();
+    assert_eq!(profile.len(), 4);
+    assert_eq!(profile[3], 1.0);
+    assert!(profile[0] < profile[2]);
+    
+    // Short files get one bucket per line
+    assert_eq!(viewer.token_density_profile(100).unwrap().len(), 12);
+    assert_eq!(viewer.token_density_profile(0), Some(vec![]));
+    
+    Ok(())
+}
+
+#[test]
+fn test_files_are_counted_in_the_background() -> Result<()> {
+    let (temp_dir, small_file_path, _, _) = setup_test_files()?;
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    
+    let huge_file_path = temp_dir.path().join("huge_file.txt");
+    let mut huge_file = File::create(&huge_file_path)?;
+    for i in 1..=5000 {
+        writeln!(huge_file, "Line {}: {} bottles of beer on the wall", i, 5000 - i)?;
+    }
+    
+    // The file is readable straight away while its lines are counted
+    let mut viewer = Viewer::new();
+    viewer.open_file(&huge_file_path)?;
+    assert_eq!(viewer.content().len(), 5000);
+    assert_eq!(viewer.token_counting_progress(), Some((0, 5000)));
+    
+    // Counts fill in from the top as they arrive
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while viewer.token_counting_progress().is_some_and(|(counted, _)| counted == 0) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(1));
+        viewer.poll_token_counts();
+    }
+    assert_eq!(viewer.line_token_count(0), Some(tokenizer.count(&viewer.content()[0])));
+    
+    // Opening another file cancels the old job; only the new file's counts arrive
+    viewer.open_file(&small_file_path)?;
+    assert_eq!(viewer.token_counting_progress(), Some((0, 3)));
+    wait_for_exact_counts(&mut viewer);
+    for (index, line) in viewer.content().iter().enumerate() {
+        assert_eq!(viewer.line_token_count(index), Some(tokenizer.count(line)));
+    }
+    assert_eq!(viewer.line_token_count(3), None);
+    assert_eq!(viewer.total_token_count(), tokenizer.count_lines(viewer.content()));
+    
+    Ok(())
+}
+
+#[test]
+fn test_selection_is_counted_in_the_prompt_template() -> Result<()> {
+    let (temp_dir, small_file_path, _, _) = setup_test_files()?;
+    let tokenizer = TokenizerKind::Cl100k.tokenizer()?;
+    
+    let mut viewer = Viewer::new();
+    viewer.open_file(&small_file_path)?;
+    viewer.toggle_selection_mode();
+    viewer.cursor_down();
+    wait_for_exact_counts(&mut viewer);
+    
+    let template = PromptTemplate::new("System: you label text.\nSource: {file_path} lines {start_line}-{end_line}\n\n{content}\n\nLabel it.");
+    viewer.set_prompt_template(Some(template.clone()), temp_dir.path());
+    let context = viewer.selection_prompt_context().unwrap();
+    assert_eq!((context.file_path.as_str(), context.start_line, context.end_line), ("small_file.txt", 1, 2));
+    
+    // The estimate adds the template's tokens, and the exact count is of the rendered prompt
+    let overhead = tokenizer.count(&template.render(&context, ""));
+    let lines = viewer.line_token_count(0).unwrap() + viewer.line_token_count(1).unwrap() + 1;
+    assert_eq!(viewer.selection_token_count(), Some(lines + overhead));
+    wait_for_exact_counts(&mut viewer);
+    let rendered = template.render(&context, &viewer.selected_lines().join("\n"));
+    assert_eq!(viewer.selection_token_count(), Some(tokenizer.count(&rendered)));
+    
+    Ok(())
+}